@@ -0,0 +1,345 @@
+use crate::{
+    audit, error, error_without_parent, file_exists, get_plain_file_name, key_fingerprint,
+    open_storage, read_local_keypair, read_profile, read_public_key, save_decrypted_file,
+    trim_line_ending, AnyError, Keypar, Plan, Profile, Report, SecretBytes,
+};
+use age::armor::{ArmoredReader, ArmoredWriter, Format};
+use age::secrecy::SecretString;
+use age::{Decryptor, Encryptor};
+use bech32::{ToBase32, Variant};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
+use std::fs;
+use std::io::prelude::*;
+use std::iter;
+use std::path::Path;
+use std::str::FromStr;
+
+const RECIPIENT_PREFIX: &str = "age";
+const IDENTITY_PREFIX: &str = "age-secret-key-";
+
+// Custom types
+//
+
+#[derive(Debug, Default)]
+pub struct AgeOptions {
+    pub recipients: Vec<String>,
+    pub passphrase: Option<SecretBytes>,
+    pub armor: bool,
+}
+
+// Entrypoint functions
+//
+
+pub fn recipient(profile_name: &String) -> Result<String, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => {
+            return error(
+                "Getting recipient failed while reading user profile",
+                reason,
+            )
+        }
+    };
+
+    let pk = match read_public_key(&profile) {
+        Ok(pk) => pk,
+        Err(reason) => return error("Getting recipient failed while reading public key", reason),
+    };
+
+    encode_recipient(&pk)
+}
+
+pub fn encrypt(
+    profile_name: &String,
+    file_path: &String,
+    options: &AgeOptions,
+    should_override: bool,
+) -> Result<Report, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Encryption failed while reading user profile", reason),
+    };
+
+    plan_age_encryption(&profile, &file_path, should_override)?;
+
+    let (encryptor, fingerprints) = match prepare_encryptor(&profile, &options) {
+        Ok(prepared) => prepared,
+        Err(reason) => return error("Encryption failed while preparing recipients", reason),
+    };
+
+    let plain_content = match fs::read(file_path) {
+        Ok(raw_vec) => SecretBytes::new(raw_vec),
+        Err(reason) => return error("Encryption failed while reading source file", reason),
+    };
+
+    let age_content = match encrypt_content(encryptor, &plain_content, options.armor) {
+        Ok(content) => content,
+        Err(reason) => return error("Encryption failed while doing actual encryption", reason),
+    };
+
+    // Checked while planning, yet another writer may have taken the name since
+    let storage = open_storage(&profile);
+    let age_name = get_age_name(&file_path);
+    let put_result = if should_override {
+        storage.put(&age_name, &age_content)
+    } else {
+        storage.put_new(&age_name, &age_content)
+    };
+    match put_result {
+        Ok(_) => (),
+        Err(reason) => return error("Encryption failed while saving encrypted file", reason),
+    };
+
+    let report = Report {
+        written: vec![storage.locate(&age_name)],
+        fingerprints,
+        ..Report::default()
+    };
+    Ok(audit::record_done(
+        &profile,
+        None,
+        "age-encrypt",
+        &file_path,
+        report,
+    ))
+}
+
+pub fn plan_encrypt(
+    profile_name: &String,
+    file_path: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Encryption failed while reading user profile", reason),
+    };
+
+    plan_age_encryption(&profile, &file_path, should_override)
+}
+
+pub fn decrypt(
+    profile_name: &String,
+    file_path: &String,
+    dest_dir: &String,
+    passphrase: Option<&SecretBytes>,
+    should_override: bool,
+) -> Result<Report, AnyError> {
+    if !file_path.ends_with(".age") {
+        return error_without_parent("Decryption failed because source file is not an age file");
+    }
+
+    if !file_exists(&file_path) {
+        return error_without_parent("Decryption failed because source file does not exists");
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Decryption failed while reading user profile", reason),
+    };
+
+    let age_content = match fs::read(file_path) {
+        Ok(raw_vec) => raw_vec,
+        Err(reason) => return error("Decryption failed while reading source file", reason),
+    };
+
+    let (plain_content, fingerprints) = match decrypt_content(&profile, &age_content, passphrase) {
+        Ok(decrypted) => decrypted,
+        Err(reason) => return error("Decryption failed while doing actual decryption", reason),
+    };
+
+    let plain_file_name = get_plain_file_name(&file_path)?;
+    let plain_file_path =
+        match save_decrypted_file(&plain_content, &dest_dir, &plain_file_name, should_override) {
+            Ok(path) => path,
+            Err(reason) => return error("Decryption failed while saving plain file", reason),
+        };
+
+    let report = Report {
+        written: vec![format!("{}", plain_file_path.display())],
+        fingerprints,
+        ..Report::default()
+    };
+    Ok(audit::record_done(
+        &profile,
+        None,
+        "age-decrypt",
+        &file_path,
+        report,
+    ))
+}
+
+// Business functions
+//
+
+fn plan_age_encryption(
+    profile: &Profile,
+    file_path: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    if file_path.ends_with(".age") {
+        return error_without_parent(
+            "Encryption failed because source file was already encrypted by age (.age)",
+        );
+    }
+
+    if !file_exists(&file_path) {
+        return error_without_parent("Encryption failed because source file does not exists");
+    }
+
+    let storage = open_storage(&profile);
+    let age_name = get_age_name(&file_path);
+    if !should_override && storage.exists(&age_name) {
+        return error_without_parent("Encryption failed because target file already exists");
+    }
+
+    let mut plan = Plan::default();
+    if storage.exists(&age_name) {
+        plan.overwritten.push(storage.locate(&age_name));
+    } else {
+        plan.created.push(storage.locate(&age_name));
+    }
+    Ok(plan)
+}
+
+fn prepare_encryptor(
+    profile: &Profile,
+    options: &AgeOptions,
+) -> Result<(Encryptor, Vec<String>), AnyError> {
+    // age refuses to mix passphrases with recipients, as either would open the file
+    if let Some(passphrase) = &options.passphrase {
+        if !options.recipients.is_empty() {
+            return error_without_parent("A passphrase cannot be used along with recipients");
+        }
+        let passphrase = to_secret_string(&passphrase)?;
+        return Ok((Encryptor::with_user_passphrase(passphrase), vec![]));
+    }
+
+    let pk = read_public_key(&profile)?;
+    let mut recipients = vec![parse_recipient(&encode_recipient(&pk)?)?];
+    for given_recipient in &options.recipients {
+        recipients.push(parse_recipient(&given_recipient)?);
+    }
+
+    let encryptor = match Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    ) {
+        Ok(encryptor) => encryptor,
+        Err(reason) => return error("Could not set recipients up", reason),
+    };
+
+    Ok((encryptor, vec![key_fingerprint(&pk)]))
+}
+
+fn encrypt_content(
+    encryptor: Encryptor,
+    plain_content: &[u8],
+    should_armor: bool,
+) -> Result<Vec<u8>, AnyError> {
+    let format = if should_armor {
+        Format::AsciiArmor
+    } else {
+        Format::Binary
+    };
+
+    let written = ArmoredWriter::wrap_output(vec![], format).and_then(|armored| {
+        let mut writer = encryptor.wrap_output(armored)?;
+        writer.write_all(plain_content)?;
+        writer.finish()?.finish()
+    });
+    match written {
+        Ok(age_content) => Ok(age_content),
+        Err(reason) => error("Could not write age file", reason),
+    }
+}
+
+fn decrypt_content(
+    profile: &Profile,
+    age_content: &[u8],
+    passphrase: Option<&SecretBytes>,
+) -> Result<(SecretBytes, Vec<String>), AnyError> {
+    // Armor is told apart by the reader itself, so both kinds of file go through here
+    let decryptor = match Decryptor::new(ArmoredReader::new(age_content)) {
+        Ok(decryptor) => decryptor,
+        Err(reason) => return error("Could not read age file", reason),
+    };
+
+    let mut fingerprints = vec![];
+    let identity: Box<dyn age::Identity> = if decryptor.is_scrypt() {
+        match passphrase {
+            Some(passphrase) => {
+                Box::new(age::scrypt::Identity::new(to_secret_string(&passphrase)?))
+            }
+            None => return error_without_parent("File is protected by a passphrase"),
+        }
+    } else {
+        let keypair = read_local_keypair(&profile)?;
+        fingerprints.push(key_fingerprint(keypair.public_key()));
+        Box::new(to_identity(&keypair)?)
+    };
+
+    let mut reader = match decryptor.decrypt(iter::once(identity.as_ref())) {
+        Ok(reader) => reader,
+        Err(reason) => return error("Could not unlock age file", reason),
+    };
+
+    // Plain content is never longer than the age file, and chunks read before a failing one
+    // were already authenticated, yet must not linger either
+    match SecretBytes::read_from(&mut reader, age_content.len()) {
+        Ok(plain_content) => Ok((plain_content, fingerprints)),
+        Err(reason) => error("Could not decrypt age file", reason),
+    }
+}
+
+// Helper functions
+//
+
+fn get_age_name(file_name: &String) -> String {
+    let path = Path::new(file_name);
+    let name = path.file_name().unwrap();
+    format!("{}.age", name.to_str().unwrap())
+}
+
+// Both box_ and age keys are plain X25519, so they only differ in how they are written down
+fn encode_recipient(pk: &PublicKey) -> Result<String, AnyError> {
+    match bech32::encode(RECIPIENT_PREFIX, pk.as_ref().to_base32(), Variant::Bech32) {
+        Ok(encoded) => Ok(encoded),
+        Err(reason) => error("Could not encode age recipient", reason),
+    }
+}
+
+fn parse_recipient(encoded: &String) -> Result<age::x25519::Recipient, AnyError> {
+    match age::x25519::Recipient::from_str(encoded) {
+        Ok(recipient) => Ok(recipient),
+        Err(reason) => {
+            error_without_parent(&format!("Recipient {} is not valid: {}", encoded, reason))
+        }
+    }
+}
+
+fn to_identity(keypair: &Keypar) -> Result<age::x25519::Identity, AnyError> {
+    let sk = match keypair.secret_key() {
+        Some(sk) => sk,
+        None => return error_without_parent("Secret key is not available"),
+    };
+
+    let mut encoded = match bech32::encode(IDENTITY_PREFIX, sk.0.to_base32(), Variant::Bech32) {
+        Ok(encoded) => SecretBytes::new(encoded.into_bytes()),
+        Err(reason) => return error("Could not encode age identity", reason),
+    };
+    encoded.as_mut().make_ascii_uppercase();
+
+    let encoded = std::str::from_utf8(&encoded).unwrap();
+    match age::x25519::Identity::from_str(encoded) {
+        Ok(identity) => Ok(identity),
+        Err(reason) => error_without_parent(&format!("Could not decode age identity: {}", reason)),
+    }
+}
+
+fn to_secret_string(passphrase: &[u8]) -> Result<SecretString, AnyError> {
+    match std::str::from_utf8(trim_line_ending(passphrase)) {
+        Ok(text) => Ok(SecretString::from(text.to_owned())),
+        Err(reason) => error("Passphrase is not valid UTF-8", reason),
+    }
+}
@@ -0,0 +1,702 @@
+use crate::{
+    error, error_without_parent, key_fingerprint, read_local_keypair, read_profile, AnyError,
+    Keypar, Report, SecretBytes,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, PublicKey};
+use sodiumoxide::crypto::sign::ed25519;
+use sodiumoxide::utils::memcmp;
+use std::collections::HashMap;
+use std::env;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+pub const SOCKET_ENV_VAR: &str = "MOY_SEKRET_AGENT_SOCK";
+pub const DEFAULT_TTL_SECS: u64 = 3600;
+
+const SOCKET_FILE_NAME: &str = "moy-sekret-agent.sock";
+const SOCKET_DIR_NAME: &str = ".moy-sekret-agent";
+// Files sealed for the profile alone go through whole, so those past this size need the key file
+pub const MAX_MESSAGE_LEN: usize = 4 << 20;
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Custom types
+//
+
+// Whatever needs the secret key is done by the agent, which only ever answers with the outcome
+#[derive(Serialize, Deserialize)]
+pub enum Request {
+    Add {
+        profile: String,
+        pk: Vec<u8>,
+        sk: SecretBytes,
+        ttl_secs: Option<u64>,
+    },
+    Remove {
+        profile: String,
+    },
+    List,
+    Stop,
+    PublicKey {
+        profile: String,
+    },
+    Seal {
+        profile: String,
+        nonce: Vec<u8>,
+        data: Vec<u8>,
+    },
+    OpenFrom {
+        profile: String,
+        peer: Vec<u8>,
+        nonce: Vec<u8>,
+        data: Vec<u8>,
+    },
+    SenderTag {
+        profile: String,
+        peer: Vec<u8>,
+        message: Vec<u8>,
+    },
+    KeyedDigest {
+        profile: String,
+        context: Vec<u8>,
+        message: Vec<u8>,
+    },
+    SigningKey {
+        profile: String,
+        context: Vec<u8>,
+    },
+    Sign {
+        profile: String,
+        context: Vec<u8>,
+        message: Vec<u8>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Response {
+    Done,
+    Profiles(Vec<String>),
+    Data(SecretBytes),
+    Failed(String),
+}
+
+// Key pair held by a running agent, which does everything that needs the secret key
+pub struct AgentKey {
+    socket_path: PathBuf,
+    profile_name: String,
+}
+
+impl AgentKey {
+    pub(crate) fn seal(&self, plain_content: &[u8], nonce: &Nonce) -> Result<Vec<u8>, AnyError> {
+        let request = Request::Seal {
+            profile: self.profile_name.to_owned(),
+            nonce: nonce.as_ref().to_vec(),
+            data: plain_content.to_vec(),
+        };
+        Ok(self.ask_for_data(&request)?.to_vec())
+    }
+
+    pub(crate) fn open_from(
+        &self,
+        cipher_data: &[u8],
+        nonce: &Nonce,
+        peer_pk: &PublicKey,
+    ) -> Result<SecretBytes, AnyError> {
+        let request = Request::OpenFrom {
+            profile: self.profile_name.to_owned(),
+            peer: peer_pk.as_ref().to_vec(),
+            nonce: nonce.as_ref().to_vec(),
+            data: cipher_data.to_vec(),
+        };
+        self.ask_for_data(&request)
+    }
+
+    pub(crate) fn sender_tag(
+        &self,
+        peer_pk: &PublicKey,
+        message: &[u8],
+    ) -> Result<Vec<u8>, AnyError> {
+        let request = Request::SenderTag {
+            profile: self.profile_name.to_owned(),
+            peer: peer_pk.as_ref().to_vec(),
+            message: message.to_vec(),
+        };
+        Ok(self.ask_for_data(&request)?.to_vec())
+    }
+
+    pub(crate) fn keyed_digest(&self, context: &[u8], message: &[u8]) -> Result<Vec<u8>, AnyError> {
+        let request = Request::KeyedDigest {
+            profile: self.profile_name.to_owned(),
+            context: context.to_vec(),
+            message: message.to_vec(),
+        };
+        Ok(self.ask_for_data(&request)?.to_vec())
+    }
+
+    pub(crate) fn signing_public_key(
+        &self,
+        context: &[u8],
+    ) -> Result<ed25519::PublicKey, AnyError> {
+        let request = Request::SigningKey {
+            profile: self.profile_name.to_owned(),
+            context: context.to_vec(),
+        };
+        match ed25519::PublicKey::from_slice(&self.ask_for_data(&request)?) {
+            Some(pk) => Ok(pk),
+            None => error_without_parent("Agent gave a signing key that is not valid"),
+        }
+    }
+
+    pub(crate) fn sign(
+        &self,
+        context: &[u8],
+        message: &[u8],
+    ) -> Result<ed25519::Signature, AnyError> {
+        let request = Request::Sign {
+            profile: self.profile_name.to_owned(),
+            context: context.to_vec(),
+            message: message.to_vec(),
+        };
+        match ed25519::Signature::from_bytes(&self.ask_for_data(&request)?) {
+            Ok(signature) => Ok(signature),
+            Err(_) => error_without_parent("Agent gave a signature that is not valid"),
+        }
+    }
+
+    fn ask_for_data(&self, request: &Request) -> Result<SecretBytes, AnyError> {
+        match exchange(&self.socket_path, &request)? {
+            Response::Data(data) => Ok(data),
+            Response::Failed(message) => error_without_parent(&message),
+            _ => error_without_parent("Agent gave an unexpected response"),
+        }
+    }
+}
+
+struct HeldKey {
+    keypair: Keypar,
+    expires_at: Option<Instant>,
+}
+
+type KeyRing = HashMap<String, HeldKey>;
+
+// Entrypoint functions
+//
+
+pub fn start(socket_path: &String, default_ttl_secs: u64) -> Result<(), AnyError> {
+    match serve(
+        Path::new(socket_path),
+        Duration::from_secs(default_ttl_secs),
+    ) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Agent failed while serving", reason),
+    }
+}
+
+pub fn add(
+    socket_path: &String,
+    profile_name: &String,
+    ttl_secs: Option<u64>,
+) -> Result<Report, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Adding key failed while reading user profile", reason),
+    };
+
+    let keypair = match read_local_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Adding key failed while reading key pair", reason),
+    };
+
+    let request = Request::Add {
+        profile: profile.name.to_owned(),
+        pk: keypair.public_key().as_ref().to_vec(),
+        sk: SecretBytes::new(keypair.secret_key().unwrap().0.to_vec()),
+        ttl_secs,
+    };
+    match ask_for_nothing(Path::new(socket_path), &request) {
+        Ok(_) => (),
+        Err(reason) => return error("Adding key failed while talking to agent", reason),
+    };
+
+    Ok(Report {
+        fingerprints: vec![key_fingerprint(keypair.public_key())],
+        ..Report::default()
+    })
+}
+
+pub fn remove(socket_path: &String, profile_name: &String) -> Result<Report, AnyError> {
+    let request = Request::Remove {
+        profile: profile_name.to_owned(),
+    };
+    match ask_for_nothing(Path::new(socket_path), &request) {
+        Ok(_) => Ok(Report::default()),
+        Err(reason) => error("Removing key failed while talking to agent", reason),
+    }
+}
+
+pub fn list(socket_path: &String) -> Result<Vec<String>, AnyError> {
+    match exchange(Path::new(socket_path), &Request::List) {
+        Ok(Response::Profiles(profile_names)) => Ok(profile_names),
+        Ok(_) => {
+            error_without_parent("Listing keys failed because agent gave an unexpected response")
+        }
+        Err(reason) => error("Listing keys failed while talking to agent", reason),
+    }
+}
+
+pub fn stop(socket_path: &String) -> Result<Report, AnyError> {
+    match ask_for_nothing(Path::new(socket_path), &Request::Stop) {
+        Ok(_) => Ok(Report {
+            removed: vec![socket_path.to_owned()],
+            ..Report::default()
+        }),
+        Err(reason) => error("Stopping agent failed while talking to it", reason),
+    }
+}
+
+// Speaks the wire protocol as is, for clients doing more than the commands above
+pub fn ask(socket_path: &String, request: &Request) -> Result<Response, AnyError> {
+    exchange(Path::new(socket_path), &request)
+}
+
+pub fn socket_path() -> String {
+    // Setting the variable to an empty value turns the agent off for this process
+    if let Ok(socket_path) = env::var(SOCKET_ENV_VAR) {
+        return socket_path;
+    }
+
+    // Runtime directory is private to the user already, home is not so it gets its own
+    match dirs::runtime_dir() {
+        Some(runtime_dir) => format!("{}", runtime_dir.join(SOCKET_FILE_NAME).display()),
+        None => match dirs::home_dir() {
+            Some(home_dir) => format!(
+                "{}",
+                home_dir
+                    .join(SOCKET_DIR_NAME)
+                    .join(SOCKET_FILE_NAME)
+                    .display()
+            ),
+            None => "".to_owned(),
+        },
+    }
+}
+
+pub(crate) fn find_agent_key(profile_name: &String, pk: &PublicKey) -> Option<AgentKey> {
+    let socket_path = PathBuf::from(socket_path());
+    if socket_path.as_os_str().is_empty() || !socket_path.exists() {
+        return None;
+    }
+
+    let agent_key = AgentKey {
+        socket_path,
+        profile_name: profile_name.to_owned(),
+    };
+    let request = Request::PublicKey {
+        profile: profile_name.to_owned(),
+    };
+
+    // Agent may still hold keys a profile had before being initialized again
+    match agent_key.ask_for_data(&request) {
+        Ok(agent_pk) if memcmp(&agent_pk, pk.as_ref()) => Some(agent_key),
+        _ => None,
+    }
+}
+
+// Business functions
+//
+
+// -- Server
+
+#[cfg(unix)]
+fn serve(socket_path: &Path, default_ttl: Duration) -> Result<(), AnyError> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    prepare_socket_dir(&socket_path)?;
+    prepare_socket_path(&socket_path)?;
+
+    // Socket is reachable as soon as it exists, so it has to be born owner only
+    let previous_umask = unsafe { libc::umask(0o177) };
+    let bound = UnixListener::bind(socket_path);
+    unsafe { libc::umask(previous_umask) };
+    let listener = match bound {
+        Ok(listener) => listener,
+        Err(reason) => return error("Could not listen on agent socket", reason),
+    };
+
+    let key_ring: Arc<Mutex<KeyRing>> = Arc::new(Mutex::new(HashMap::new()));
+    let running = Arc::new(AtomicBool::new(true));
+
+    // Expired keys get dropped, and so wiped, even if nobody asks for anything
+    let reaper_key_ring = Arc::clone(&key_ring);
+    let reaper_running = Arc::clone(&running);
+    thread::spawn(move || {
+        while reaper_running.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_secs(1));
+            if let Ok(mut key_ring) = reaper_key_ring.lock() {
+                forget_expired_keys(&mut key_ring);
+            }
+        }
+    });
+
+    for stream in listener.incoming() {
+        if !running.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        // Permissions aside, nobody but the very user running the agent gets an answer
+        if peer_uid(&stream) != Some(unsafe { libc::geteuid() }) {
+            continue;
+        }
+
+        // Each client gets a thread of its own, so one that stalls keeps nobody else waiting
+        let client_key_ring = Arc::clone(&key_ring);
+        let client_running = Arc::clone(&running);
+        let client_socket_path = socket_path.to_path_buf();
+        thread::spawn(move || {
+            if serve_client(stream, &client_key_ring, default_ttl) {
+                client_running.store(false, Ordering::SeqCst);
+                // Wakes the listener up, so it gets to see it has to stop
+                let _ = UnixStream::connect(&client_socket_path);
+            }
+        });
+    }
+
+    running.store(false, Ordering::SeqCst);
+    if let Ok(mut key_ring) = key_ring.lock() {
+        key_ring.clear();
+    }
+    let _ = std::fs::remove_file(socket_path);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve(_socket_path: &Path, _default_ttl: Duration) -> Result<(), AnyError> {
+    error_without_parent("Agent is only supported on Unix systems")
+}
+
+// Tells whether the client asked the agent to stop
+#[cfg(unix)]
+fn serve_client(
+    mut stream: std::os::unix::net::UnixStream,
+    key_ring: &std::sync::Mutex<KeyRing>,
+    default_ttl: Duration,
+) -> bool {
+    let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+
+    let request: Request = match read_message(&mut stream) {
+        Ok(request) => request,
+        Err(_) => return false,
+    };
+    let should_stop = matches!(request, Request::Stop);
+
+    let response = {
+        let mut key_ring = match key_ring.lock() {
+            Ok(key_ring) => key_ring,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        answer(&mut key_ring, request, default_ttl)
+    };
+    let _ = write_message(&mut stream, &response);
+
+    should_stop
+}
+
+fn answer(key_ring: &mut KeyRing, request: Request, default_ttl: Duration) -> Response {
+    forget_expired_keys(key_ring);
+
+    match request {
+        Request::Add {
+            profile,
+            pk,
+            sk,
+            ttl_secs,
+        } => {
+            let pk = match PublicKey::from_slice(&pk) {
+                Some(pk) => pk,
+                None => return Response::Failed("Could not decode public key".to_owned()),
+            };
+            let keypair = match Keypar::from_secret_slice(pk, &sk) {
+                Some(keypair) => keypair,
+                None => return Response::Failed("Could not decode secret key".to_owned()),
+            };
+            let ttl = ttl_secs.map(Duration::from_secs).unwrap_or(default_ttl);
+            let held_key = HeldKey {
+                keypair,
+                expires_at: get_expiry(ttl),
+            };
+            key_ring.insert(profile, held_key);
+            Response::Done
+        }
+        Request::Remove { profile } => match key_ring.remove(&profile) {
+            Some(_) => Response::Done,
+            None => Response::Failed("Agent does not hold keys for this profile".to_owned()),
+        },
+        Request::List => {
+            let mut profile_names: Vec<String> = key_ring.keys().cloned().collect();
+            profile_names.sort();
+            Response::Profiles(profile_names)
+        }
+        Request::Stop => {
+            key_ring.clear();
+            Response::Done
+        }
+        Request::PublicKey { profile } => with_keypair(key_ring, &profile, |keypair| {
+            Ok(SecretBytes::new(keypair.public_key().as_ref().to_vec()))
+        }),
+        Request::Seal {
+            profile,
+            nonce,
+            data,
+        } => with_keypair(key_ring, &profile, |keypair| {
+            let nonce = decode_nonce(&nonce)?;
+            Ok(SecretBytes::new(keypair.seal(&data, &nonce)?))
+        }),
+        Request::OpenFrom {
+            profile,
+            peer,
+            nonce,
+            data,
+        } => with_keypair(key_ring, &profile, |keypair| {
+            let nonce = decode_nonce(&nonce)?;
+            keypair.open_from(&data, &nonce, &decode_public_key(&peer)?)
+        }),
+        Request::SenderTag {
+            profile,
+            peer,
+            message,
+        } => with_keypair(key_ring, &profile, |keypair| {
+            let sender_tag = keypair.sender_tag(&decode_public_key(&peer)?, &message)?;
+            Ok(SecretBytes::new(sender_tag))
+        }),
+        Request::KeyedDigest {
+            profile,
+            context,
+            message,
+        } => with_keypair(key_ring, &profile, |keypair| {
+            Ok(SecretBytes::new(keypair.keyed_digest(&context, &message)?))
+        }),
+        Request::SigningKey { profile, context } => with_keypair(key_ring, &profile, |keypair| {
+            let pk = keypair.signing_public_key(&context)?;
+            Ok(SecretBytes::new(pk.as_ref().to_vec()))
+        }),
+        Request::Sign {
+            profile,
+            context,
+            message,
+        } => with_keypair(key_ring, &profile, |keypair| {
+            let signature = keypair.sign(&context, &message)?;
+            Ok(SecretBytes::new(signature.to_bytes().to_vec()))
+        }),
+    }
+}
+
+fn with_keypair<F>(key_ring: &KeyRing, profile_name: &String, action: F) -> Response
+where
+    F: FnOnce(&Keypar) -> Result<SecretBytes, AnyError>,
+{
+    let held_key = match key_ring.get(profile_name) {
+        Some(held_key) => held_key,
+        None => return Response::Failed("Agent does not hold keys for this profile".to_owned()),
+    };
+
+    match action(&held_key.keypair) {
+        Ok(data) => Response::Data(data),
+        Err(reason) => Response::Failed(reason.to_string()),
+    }
+}
+
+fn forget_expired_keys(key_ring: &mut KeyRing) {
+    let now = Instant::now();
+    key_ring.retain(|_, held_key| match held_key.expires_at {
+        Some(expires_at) => expires_at > now,
+        None => true,
+    });
+}
+
+#[cfg(unix)]
+fn prepare_socket_path(socket_path: &Path) -> Result<(), AnyError> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    let metadata = match std::fs::symlink_metadata(socket_path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return error_without_parent("Agent socket path is taken by something other than a socket");
+    }
+    if UnixStream::connect(socket_path).is_ok() {
+        return error_without_parent("Another agent is already listening on this socket");
+    }
+
+    // Left behind by an agent that did not get to clean up after itself
+    match std::fs::remove_file(socket_path) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not remove stale agent socket", reason),
+    }
+}
+
+// Missing directory is made private, an existing one was chosen by the user and is left as is
+#[cfg(unix)]
+fn prepare_socket_dir(socket_path: &Path) -> Result<(), AnyError> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let socket_dir = match socket_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() => dir,
+        _ => return Ok(()),
+    };
+    match std::fs::DirBuilder::new().mode(0o700).create(socket_dir) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not create agent socket directory", reason),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut credentials_len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut credentials_len,
+        )
+    };
+    match result {
+        0 => Some(credentials.uid),
+        _ => None,
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut uid = 0;
+    let mut gid = 0;
+    match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Some(uid),
+        _ => None,
+    }
+}
+
+// -- Client
+
+fn ask_for_nothing(socket_path: &Path, request: &Request) -> Result<(), AnyError> {
+    match exchange(&socket_path, &request)? {
+        Response::Done => Ok(()),
+        Response::Failed(message) => error_without_parent(&message),
+        _ => error_without_parent("Agent gave an unexpected response"),
+    }
+}
+
+#[cfg(unix)]
+fn exchange(socket_path: &Path, request: &Request) -> Result<Response, AnyError> {
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = match UnixStream::connect(socket_path) {
+        Ok(stream) => stream,
+        Err(reason) => return error("Could not connect to agent", reason),
+    };
+    let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+
+    write_message(&mut stream, &request)?;
+    read_message(&mut stream)
+}
+
+#[cfg(not(unix))]
+fn exchange(_socket_path: &Path, _request: &Request) -> Result<Response, AnyError> {
+    error_without_parent("Agent is only supported on Unix systems")
+}
+
+// Helper functions
+//
+
+fn write_message<T: Serialize>(stream: &mut dyn Write, message: &T) -> Result<(), AnyError> {
+    let body = match bincode::serialize(message) {
+        Ok(body) => SecretBytes::new(body),
+        Err(reason) => return error("Could not serialize agent message", reason),
+    };
+    // Turned down here already, rather than by the agent dropping the connection
+    if body.len() > MAX_MESSAGE_LEN {
+        return error_without_parent("Agent message is too large, use the secret key file instead");
+    }
+    let body_len = (body.len() as u32).to_le_bytes();
+
+    match stream
+        .write_all(&body_len)
+        .and_then(|_| stream.write_all(&body))
+        .and_then(|_| stream.flush())
+    {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not send agent message", reason),
+    }
+}
+
+fn read_message<T: DeserializeOwned>(stream: &mut dyn Read) -> Result<T, AnyError> {
+    let mut body_len = [0; 4];
+    match stream.read_exact(&mut body_len) {
+        Ok(_) => (),
+        Err(reason) => return error("Could not receive agent message", reason),
+    };
+
+    let body_len = u32::from_le_bytes(body_len) as usize;
+    if body_len > MAX_MESSAGE_LEN {
+        return error_without_parent("Agent message is too large");
+    }
+
+    let mut body = vec![0; body_len];
+    let read_result = stream.read_exact(&mut body);
+    let body = SecretBytes::new(body);
+    match read_result {
+        Ok(_) => (),
+        Err(reason) => return error("Could not receive agent message", reason),
+    };
+
+    match bincode::deserialize(&body) {
+        Ok(message) => Ok(message),
+        Err(reason) => error("Could not deserialize agent message", reason),
+    }
+}
+
+fn decode_public_key(raw_pk: &[u8]) -> Result<PublicKey, AnyError> {
+    match PublicKey::from_slice(raw_pk) {
+        Some(pk) => Ok(pk),
+        None => error_without_parent("Could not decode peer public key"),
+    }
+}
+
+fn decode_nonce(raw_nonce: &[u8]) -> Result<Nonce, AnyError> {
+    match Nonce::from_slice(raw_nonce) {
+        Some(nonce) => Ok(nonce),
+        None => error_without_parent("Could not decode nonce"),
+    }
+}
+
+fn get_expiry(ttl: Duration) -> Option<Instant> {
+    // Zero means keys are kept until removed or the agent stops
+    if ttl.as_secs() == 0 {
+        return None;
+    }
+    Some(Instant::now() + ttl)
+}
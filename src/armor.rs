@@ -0,0 +1,63 @@
+use crate::{error, error_without_parent, AnyError};
+use data_encoding::BASE64;
+
+const BEGIN_LINE: &str = "-----BEGIN MOY SEKRET ENCRYPTED FILE-----";
+const END_LINE: &str = "-----END MOY SEKRET ENCRYPTED FILE-----";
+const LINE_LEN: usize = 64;
+
+// Business functions
+//
+
+pub(crate) fn encode(cipher_content: &[u8], header_lines: &[String]) -> String {
+    let encoded = BASE64.encode(cipher_content);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(LINE_LEN)
+        .map(|line| std::str::from_utf8(line).unwrap())
+        .collect();
+
+    let mut block = vec![BEGIN_LINE.to_owned()];
+    block.extend(header_lines.iter().cloned());
+    block.push("".to_owned());
+    block.extend(lines.iter().map(|line| line.to_string()));
+    block.push(END_LINE.to_owned());
+    block.join("\n") + "\n"
+}
+
+pub(crate) fn is_armored(content: &[u8]) -> bool {
+    // Pasted text often comes with some blank lines or indentation in front
+    let first_visible = content.iter().position(|byte| !byte.is_ascii_whitespace());
+    match first_visible {
+        Some(start) => content[start..].starts_with(BEGIN_LINE.as_bytes()),
+        None => false,
+    }
+}
+
+pub(crate) fn decode(content: &[u8]) -> Result<Vec<u8>, AnyError> {
+    let text = match std::str::from_utf8(content) {
+        Ok(text) => text,
+        Err(reason) => return error("Armored file is not valid UTF-8", reason),
+    };
+
+    let mut body: Option<String> = None;
+    for line in text.lines().map(|line| line.trim()) {
+        if line == BEGIN_LINE {
+            body = Some(String::new());
+        } else if line == END_LINE {
+            return match body {
+                Some(encoded) => match BASE64.decode(encoded.as_bytes()) {
+                    Ok(raw_vec) => Ok(raw_vec),
+                    Err(reason) => error("Could not decode armored file", reason),
+                },
+                None => error_without_parent("Armored block ends before it begins"),
+            };
+        } else if let Some(encoded) = body.as_mut() {
+            // Header lines are only there for humans, the container carries everything else
+            if !line.contains(':') {
+                encoded.push_str(line);
+            }
+        }
+    }
+
+    error_without_parent("Armored block is missing its end line")
+}
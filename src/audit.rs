@@ -0,0 +1,527 @@
+use crate::storage::Storage;
+use crate::{
+    error, error_without_parent, open_storage, read_keypair, read_profile, AnyError, Keypar,
+    Profile, Report,
+};
+use data_encoding::{BASE64, HEXLOWER};
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::hash::sha256;
+use sodiumoxide::crypto::sign::ed25519;
+use std::env;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SIGNING_KEY_CONTEXT: &[u8] = b"moy-sekret audit log";
+
+// Batches record from many threads at once, while each entry must follow the one before it,
+// and the storage lock alone is not enough where items cannot be locked
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+// Custom types
+//
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSigning {
+    #[default]
+    None,
+    Ed25519,
+}
+
+impl FromStr for LogSigning {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<LogSigning, AnyError> {
+        match value {
+            "none" => Ok(LogSigning::None),
+            "ed25519" => Ok(LogSigning::Ed25519),
+            _ => error_without_parent("Log signing must be either none or ed25519"),
+        }
+    }
+}
+
+// Last entry, kept apart from the log in a file of its own so cutting the log tail shows,
+// and signed along with entries, so the head cannot be moved back to match a shorter log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogHead {
+    pub sequence: u64,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+// Everything the hash covers, the previous hash included, so no entry can change alone
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    pub sequence: u64,
+    pub time: u64,
+    pub user: String,
+    pub action: String,
+    pub subject: String,
+    pub written: Vec<String>,
+    pub removed: Vec<String>,
+    pub fingerprints: Vec<String>,
+    pub previous: Option<String>,
+    pub signer: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub record: Record,
+    pub hash: String,
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub entries: usize,
+    pub signed: usize,
+}
+
+// Entrypoint functions
+//
+
+pub fn show(profile_name: &String) -> Result<Vec<Entry>, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Showing log failed while reading user profile", reason),
+    };
+
+    match read_entries(open_storage(&profile).as_ref(), &profile) {
+        Ok(entries) => Ok(entries),
+        Err(reason) => error("Showing log failed while reading entries", reason),
+    }
+}
+
+pub fn verify(profile_name: &String) -> Result<Verification, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Verifying log failed while reading user profile", reason),
+    };
+
+    let storage = open_storage(&profile);
+    let entries = match read_entries(storage.as_ref(), &profile) {
+        Ok(entries) => entries,
+        Err(reason) => return error("Verifying log failed while reading entries", reason),
+    };
+
+    let log_head = match read_head(storage.as_ref(), &profile) {
+        Ok(log_head) => log_head,
+        Err(reason) => return error("Verifying log failed while reading its head", reason),
+    };
+
+    let verification = match check_chain(&entries, &log_head, profile.log_signing) {
+        Ok(verification) => verification,
+        Err(reason) => return error("Verifying log failed because it was tampered with", reason),
+    };
+
+    // Only the keys can tell whether signatures were made by this very profile
+    if verification.signed > 0 {
+        let keypair = match read_keypair(&profile) {
+            Ok(keypair) => keypair,
+            Err(reason) => return error("Verifying log failed while reading key pair", reason),
+        };
+        match check_signers(&entries, &keypair) {
+            Ok(_) => (),
+            Err(reason) => {
+                return error("Verifying log failed because it was tampered with", reason)
+            }
+        };
+    }
+
+    Ok(verification)
+}
+
+// Business functions
+//
+
+// Recorded once the change is made, so a log failure is told as a warning and not as a failure
+pub(crate) fn record_done(
+    profile: &Profile,
+    keypair: Option<&Keypar>,
+    action: &str,
+    subject: &String,
+    mut report: Report,
+) -> Report {
+    if let Err(reason) = record(profile, keypair, action, subject, &report) {
+        report.warnings.push(format!(
+            "Could not record {} to the log: {}",
+            action, reason
+        ));
+    }
+    report
+}
+
+// Keys are only read when the profile wants entries signed and none were given
+pub(crate) fn record(
+    profile: &Profile,
+    keypair: Option<&Keypar>,
+    action: &str,
+    subject: &String,
+    report: &Report,
+) -> Result<(), AnyError> {
+    let _guard = LOG_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    // Held until the head is saved, as other processes record to the very same log
+    let storage = open_storage(&profile);
+    let _log_lock = match storage.lock(&get_log_name(&profile)) {
+        Ok(lock) => lock,
+        Err(reason) => return error("Could not lock log", reason),
+    };
+
+    // A log from before heads were kept apart goes on from wherever it ended
+    let head = match read_head(storage.as_ref(), &profile)? {
+        Some(head) => Some(head),
+        None => read_entries(storage.as_ref(), &profile)?
+            .last()
+            .map(|entry| LogHead {
+                sequence: entry.record.sequence,
+                hash: entry.hash.to_owned(),
+                signer: None,
+                signature: None,
+            }),
+    };
+
+    let read_keypair_holder;
+    let signing_keypair = match (&profile.log_signing, keypair) {
+        (LogSigning::None, _) => None,
+        (LogSigning::Ed25519, Some(keypair)) => Some(keypair),
+        (LogSigning::Ed25519, None) => {
+            read_keypair_holder = read_keypair(&profile)?;
+            Some(&read_keypair_holder)
+        }
+    };
+    let signer = match signing_keypair {
+        Some(keypair) => {
+            Some(BASE64.encode(keypair.signing_public_key(SIGNING_KEY_CONTEXT)?.as_ref()))
+        }
+        None => None,
+    };
+
+    let record = Record {
+        sequence: head.as_ref().map_or(1, |head| head.sequence + 1),
+        time: now(),
+        user: current_user(),
+        action: action.to_owned(),
+        subject: subject.to_owned(),
+        written: report.written.to_owned(),
+        removed: report.removed.to_owned(),
+        fingerprints: report.fingerprints.to_owned(),
+        previous: head.map(|head| head.hash),
+        signer,
+    };
+    let hash = hash_record(&record)?;
+    let signature = match signing_keypair {
+        Some(keypair) => {
+            Some(BASE64.encode(keypair.sign(SIGNING_KEY_CONTEXT, hash.as_bytes())?.as_ref()))
+        }
+        None => None,
+    };
+    let entry = Entry {
+        record,
+        hash,
+        signature,
+    };
+
+    append_entry(storage.as_ref(), &profile, &entry)?;
+
+    let head_signature = match signing_keypair {
+        Some(keypair) => {
+            let head_message = get_head_message(entry.record.sequence, &entry.hash);
+            Some(BASE64.encode(keypair.sign(SIGNING_KEY_CONTEXT, &head_message)?.as_ref()))
+        }
+        None => None,
+    };
+    save_head(
+        storage.as_ref(),
+        &profile,
+        &LogHead {
+            sequence: entry.record.sequence,
+            hash: entry.hash,
+            signer: entry.record.signer,
+            signature: head_signature,
+        },
+    )
+}
+
+fn read_entries(storage: &dyn Storage, profile: &Profile) -> Result<Vec<Entry>, AnyError> {
+    let log_name = get_log_name(&profile);
+    if !storage.exists(&log_name) {
+        return Ok(vec![]);
+    }
+
+    let content = match storage.get(&log_name).map(String::from_utf8) {
+        Ok(Ok(content)) => content,
+        Ok(Err(reason)) => return error("Log is not valid UTF-8", reason),
+        Err(reason) => return error("Could not read log", reason),
+    };
+
+    let mut entries = vec![];
+    for (index, line) in content.lines().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(reason) => {
+                return error(&format!("Line {} of log is not valid", index + 1), reason)
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+fn read_head(storage: &dyn Storage, profile: &Profile) -> Result<Option<LogHead>, AnyError> {
+    let head_name = get_head_name(&profile);
+    if !storage.exists(&head_name) {
+        return Ok(None);
+    }
+
+    let content = match storage.get(&head_name) {
+        Ok(content) => content,
+        Err(reason) => return error("Could not read log head", reason),
+    };
+    match serde_json::from_slice(&content) {
+        Ok(head) => Ok(Some(head)),
+        Err(reason) => error("Log head is not valid", reason),
+    }
+}
+
+// Replaced whole, so readers never see half a head nor lose it on a crash
+fn save_head(storage: &dyn Storage, profile: &Profile, head: &LogHead) -> Result<(), AnyError> {
+    let content = match serde_json::to_vec(&head) {
+        Ok(content) => content,
+        Err(reason) => return error("Could not serialize log head", reason),
+    };
+
+    match storage.put(&get_head_name(&profile), &content) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not save log head", reason),
+    }
+}
+
+fn append_entry(storage: &dyn Storage, profile: &Profile, entry: &Entry) -> Result<(), AnyError> {
+    let mut line = match serde_json::to_string(&entry) {
+        Ok(line) => line,
+        Err(reason) => return error("Could not serialize log entry", reason),
+    };
+    line.push('\n');
+
+    match storage.append(&get_log_name(&profile), line.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not append entry to log", reason),
+    }
+}
+
+fn check_chain(
+    entries: &[Entry],
+    log_head: &Option<LogHead>,
+    log_signing: LogSigning,
+) -> Result<Verification, AnyError> {
+    let mut verification = Verification::default();
+    let mut previous: Option<&Entry> = None;
+    let mut is_signing = false;
+    for entry in entries {
+        let sequence = entry.record.sequence;
+        let expected_sequence = previous.map_or(1, |previous| previous.record.sequence + 1);
+        if sequence != expected_sequence && previous.is_none() {
+            return error_without_parent(&format!(
+                "Log starts at entry {}, so its beginning was removed",
+                sequence
+            ));
+        }
+        if sequence != expected_sequence {
+            return error_without_parent(&format!(
+                "Entry {} comes where entry {} should, so entries were removed",
+                sequence, expected_sequence
+            ));
+        }
+
+        if entry.record.previous.as_ref() != previous.map(|previous| &previous.hash) {
+            return error_without_parent(&format!(
+                "Entry {} does not follow the one before it",
+                sequence
+            ));
+        }
+
+        if hash_record(&entry.record)? != entry.hash {
+            return error_without_parent(&format!("Entry {} was edited", sequence));
+        }
+
+        // Once entries are signed, the next ones are as well, until signing is turned off
+        if entry.signature.is_some() || entry.record.signer.is_some() {
+            check_signature(&entry)?;
+            verification.signed += 1;
+            is_signing = true;
+        } else if stops_signing(&entry.record) {
+            is_signing = false;
+        } else if is_signing {
+            return error_without_parent(&format!(
+                "Entry {} is not signed while the ones before it are, so its signature was removed",
+                sequence
+            ));
+        }
+
+        verification.entries += 1;
+        previous = Some(entry);
+    }
+
+    if let Some(log_head) = log_head {
+        let is_at_head = previous.is_some_and(|entry| {
+            entry.record.sequence == log_head.sequence && entry.hash == log_head.hash
+        });
+        if !is_at_head {
+            return error_without_parent(&format!(
+                "Log does not end at entry {} as its head says, so its end was removed",
+                log_head.sequence
+            ));
+        }
+    }
+
+    // Entries can be cut off along with a head rewritten to match, but not signed again
+    if is_signing {
+        let last_entry = previous.unwrap();
+        match log_head {
+            Some(log_head) => check_head_signature(&log_head, &last_entry.record.signer)?,
+            None => return error_without_parent(
+                "Log ends with signed entries but has no head, so its end may have been removed",
+            ),
+        }
+    }
+
+    // Turning signing on is itself a signed entry, so the log has to end signed
+    if log_signing == LogSigning::Ed25519 && previous.is_some() && !is_signing {
+        return error_without_parent(
+            "Log does not end with signed entries as the profile says, so signatures were removed",
+        );
+    }
+
+    Ok(verification)
+}
+
+fn check_head_signature(log_head: &LogHead, signer: &Option<String>) -> Result<(), AnyError> {
+    if log_head.signer.is_none() || log_head.signer != *signer {
+        return error_without_parent("Log head was not signed by the signer of the last entry");
+    }
+
+    let pk = log_head
+        .signer
+        .as_ref()
+        .and_then(|signer| BASE64.decode(signer.as_bytes()).ok())
+        .and_then(|raw_pk| ed25519::PublicKey::from_slice(&raw_pk));
+    let signature = log_head
+        .signature
+        .as_ref()
+        .and_then(|signature| BASE64.decode(signature.as_bytes()).ok())
+        .and_then(|raw_signature| ed25519::Signature::from_bytes(&raw_signature).ok());
+    let head_message = get_head_message(log_head.sequence, &log_head.hash);
+    match (pk, signature) {
+        (Some(pk), Some(signature)) if ed25519::verify_detached(&signature, &head_message, &pk) => {
+            Ok(())
+        }
+        _ => error_without_parent("Log head has a signature that does not match"),
+    }
+}
+
+fn check_signature(entry: &Entry) -> Result<(), AnyError> {
+    let sequence = entry.record.sequence;
+    let (signer, signature) = match (&entry.record.signer, &entry.signature) {
+        (Some(signer), Some(signature)) => (signer, signature),
+        _ => {
+            return error_without_parent(&format!(
+                "Entry {} is missing either its signer or its signature",
+                sequence
+            ))
+        }
+    };
+
+    let pk = BASE64
+        .decode(signer.as_bytes())
+        .ok()
+        .and_then(|raw_pk| ed25519::PublicKey::from_slice(&raw_pk));
+    let signature = BASE64
+        .decode(signature.as_bytes())
+        .ok()
+        .and_then(|raw_signature| ed25519::Signature::from_bytes(&raw_signature).ok());
+    match (pk, signature) {
+        (Some(pk), Some(signature))
+            if ed25519::verify_detached(&signature, entry.hash.as_bytes(), &pk) =>
+        {
+            Ok(())
+        }
+        _ => error_without_parent(&format!(
+            "Entry {} has a signature that does not match",
+            sequence
+        )),
+    }
+}
+
+// Older keys may have signed entries made before keys were last made or restored, but never after
+fn check_signers(entries: &[Entry], keypair: &Keypar) -> Result<(), AnyError> {
+    let pk = keypair.signing_public_key(SIGNING_KEY_CONTEXT)?;
+    let signer = BASE64.encode(pk.as_ref());
+
+    let last_init_index = entries
+        .iter()
+        .rposition(|entry| matches!(entry.record.action.as_str(), "init" | "restore"))
+        .unwrap_or(0);
+    for entry in &entries[last_init_index..] {
+        if entry.signature.is_some() && entry.record.signer.as_ref() != Some(&signer) {
+            return error_without_parent(&format!(
+                "Entry {} was not signed by this profile keys",
+                entry.record.sequence
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Helper functions
+//
+
+// New keys come with a new profile, which does not sign until told to again
+fn stops_signing(record: &Record) -> bool {
+    match record.action.as_str() {
+        "init" | "restore" => true,
+        "configure" => record.subject == "log-signing=none",
+        _ => false,
+    }
+}
+
+fn get_log_name(profile: &Profile) -> String {
+    format!("{}.log", profile.name)
+}
+
+fn get_head_name(profile: &Profile) -> String {
+    format!("{}.head", profile.name)
+}
+
+// Told apart from entry signatures, which sign the bare hash, so neither stands in for the other
+fn get_head_message(sequence: u64, hash: &String) -> Vec<u8> {
+    format!("moy-sekret log head {} {}", sequence, hash).into_bytes()
+}
+
+fn hash_record(record: &Record) -> Result<String, AnyError> {
+    match serde_json::to_vec(&record) {
+        Ok(raw_record) => Ok(HEXLOWER.encode(sha256::hash(&raw_record).as_ref())),
+        Err(reason) => error("Could not serialize log entry", reason),
+    }
+}
+
+fn current_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_owned())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,340 @@
+use crate::{
+    error, error_without_parent, key_fingerprint, plan_setup, read_local_keypair, read_profile,
+    setup_profile, AnyError, Keypar, Plan, Report, SecretBytes,
+};
+use data_encoding::BASE32_NOPAD;
+use sodiumoxide::crypto::box_;
+use sodiumoxide::crypto::hash::sha256;
+use sodiumoxide::randombytes::randombytes;
+use sodiumoxide::utils::memcmp;
+
+const BEGIN_LINE: &str = "-----BEGIN MOY SEKRET KEY BACKUP-----";
+const END_LINE: &str = "-----END MOY SEKRET KEY BACKUP-----";
+const FORMAT_VERSION: u8 = 1;
+const FINGERPRINT_LEN: usize = 8;
+const CHECKSUM_LEN: usize = 4;
+const HEADER_LEN: usize = 3 + FINGERPRINT_LEN;
+const PAYLOAD_LEN: usize = HEADER_LEN + box_::SECRETKEYBYTES + CHECKSUM_LEN;
+const GROUP_LEN: usize = 4;
+const GROUPS_PER_LINE: usize = 6;
+
+// Custom types
+//
+
+// Every backup is a share, a plain one being simply the only share of a 1-of-1 split
+struct Share {
+    threshold: u8,
+    index: u8,
+    fingerprint: Vec<u8>,
+    value: SecretBytes,
+}
+
+// Entrypoint functions
+//
+
+pub fn export(profile_name: &String, shares: u8, threshold: u8) -> Result<Vec<String>, AnyError> {
+    if threshold == 0 || shares < threshold {
+        return error_without_parent(
+            "Backing key up failed because threshold must be between 1 and the number of shares",
+        );
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Backing key up failed while reading user profile", reason),
+    };
+
+    let keypair = match read_local_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Backing key up failed while reading key pair", reason),
+    };
+
+    let sk = keypair.secret_key().unwrap();
+    let fingerprint = get_short_fingerprint(&keypair);
+    let blocks = split_secret(&sk.0, threshold, shares)
+        .into_iter()
+        .map(|(index, value)| {
+            let share = Share {
+                threshold,
+                index,
+                fingerprint: fingerprint.to_owned(),
+                value,
+            };
+            format_block(&profile_name, &keypair, &share, shares)
+        })
+        .collect();
+
+    Ok(blocks)
+}
+
+pub fn restore(
+    profile_name: &String,
+    storage_dir: &String,
+    backup_texts: &[SecretBytes],
+    should_override: bool,
+) -> Result<Report, AnyError> {
+    match plan_setup(&profile_name, &storage_dir, should_override) {
+        Ok(_) => (),
+        Err(reason) => return error("Restoring key failed while checking profile", reason),
+    };
+
+    let mut shares = Vec::new();
+    for backup_text in backup_texts {
+        match parse_blocks(&backup_text) {
+            Ok(parsed) => shares.extend(parsed),
+            Err(reason) => return error("Restoring key failed while reading backup", reason),
+        };
+    }
+
+    let keypair = match recover_keypair(&shares) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Restoring key failed while recovering key pair", reason),
+    };
+
+    // Same way in as init, only with keys brought back rather than made
+    match setup_profile(
+        &profile_name,
+        &storage_dir,
+        keypair,
+        should_override,
+        "restore",
+    ) {
+        Ok(report) => Ok(report),
+        Err(reason) => error("Restoring key failed while setting profile up", reason),
+    }
+}
+
+// Backups are only read for real, as what gets written depends on the profile alone
+pub fn plan_restore(
+    profile_name: &String,
+    storage_dir: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    plan_setup(&profile_name, &storage_dir, should_override)
+}
+
+// Business functions
+//
+
+fn format_block(profile_name: &String, keypair: &Keypar, share: &Share, shares: u8) -> String {
+    let mut payload = Vec::with_capacity(PAYLOAD_LEN);
+    payload.push(FORMAT_VERSION);
+    payload.push(share.threshold);
+    payload.push(share.index);
+    payload.extend_from_slice(&share.fingerprint);
+    payload.extend_from_slice(&share.value);
+    let checksum = get_checksum(&payload);
+    payload.extend_from_slice(&checksum);
+    let payload = SecretBytes::new(payload);
+
+    let encoded = SecretBytes::new(BASE32_NOPAD.encode(&payload).into_bytes());
+    let groups: Vec<&str> = encoded
+        .chunks(GROUP_LEN)
+        .map(|group| std::str::from_utf8(group).unwrap())
+        .collect();
+    let lines: Vec<String> = groups
+        .chunks(GROUPS_PER_LINE)
+        .map(|line_groups| line_groups.join(" "))
+        .collect();
+
+    let mut block = vec![
+        BEGIN_LINE.to_owned(),
+        format!("Profile: {}", profile_name),
+        format!("Fingerprint: {}", key_fingerprint(keypair.public_key())),
+    ];
+    if shares > 1 {
+        block.push(format!(
+            "Share: {} of {}, {} needed",
+            share.index, shares, share.threshold
+        ));
+    }
+    block.push("".to_owned());
+    block.extend(lines);
+    block.push(END_LINE.to_owned());
+    block.join("\n") + "\n"
+}
+
+fn parse_blocks(backup_text: &[u8]) -> Result<Vec<Share>, AnyError> {
+    let text = match std::str::from_utf8(backup_text) {
+        Ok(text) => text,
+        Err(reason) => return error("Backup is not valid UTF-8", reason),
+    };
+
+    let mut shares = Vec::new();
+    let mut body: Option<String> = None;
+    for line in text.lines().map(|line| line.trim()) {
+        if line == BEGIN_LINE {
+            body = Some(String::new());
+        } else if line == END_LINE {
+            match body.take() {
+                Some(encoded) => shares.push(decode_share(&encoded)?),
+                None => return error_without_parent("Backup block ends before it begins"),
+            }
+        } else if let Some(encoded) = body.as_mut() {
+            // Header lines are only there for humans, the payload carries everything else
+            if !line.contains(':') {
+                encoded.extend(line.split_whitespace());
+            }
+        }
+    }
+
+    if body.is_some() {
+        return error_without_parent("Backup block is missing its end line");
+    }
+    if shares.is_empty() {
+        return error_without_parent("No backup block was found");
+    }
+    Ok(shares)
+}
+
+fn decode_share(encoded: &String) -> Result<Share, AnyError> {
+    let normalized = SecretBytes::new(encoded.to_uppercase().into_bytes());
+    let payload = match BASE32_NOPAD.decode(&normalized) {
+        Ok(raw_vec) => SecretBytes::new(raw_vec),
+        Err(reason) => return error("Could not decode backup block", reason),
+    };
+
+    if payload.len() != PAYLOAD_LEN {
+        return error_without_parent("Backup block has an unexpected length");
+    }
+
+    let (content, checksum) = payload.split_at(PAYLOAD_LEN - CHECKSUM_LEN);
+    if !memcmp(&get_checksum(content), checksum) {
+        return error_without_parent("Backup block checksum does not match, check for typos");
+    }
+    if content[0] != FORMAT_VERSION {
+        return error_without_parent("Backup block was made by an unsupported version");
+    }
+
+    Ok(Share {
+        threshold: content[1],
+        index: content[2],
+        fingerprint: content[3..HEADER_LEN].to_vec(),
+        value: SecretBytes::new(content[HEADER_LEN..].to_vec()),
+    })
+}
+
+fn recover_keypair(shares: &[Share]) -> Result<Keypar, AnyError> {
+    let first_share = match shares.first() {
+        Some(share) => share,
+        None => return error_without_parent("No backup was given"),
+    };
+
+    let mut unique_shares: Vec<&Share> = Vec::new();
+    for share in shares {
+        if share.threshold != first_share.threshold || share.fingerprint != first_share.fingerprint
+        {
+            return error_without_parent("Backup shares do not belong to the same key");
+        }
+        if !unique_shares.iter().any(|known| known.index == share.index) {
+            unique_shares.push(share);
+        }
+    }
+
+    let threshold = first_share.threshold as usize;
+    if unique_shares.len() < threshold {
+        return error_without_parent(&format!(
+            "Only {} of the {} needed backup shares were given",
+            unique_shares.len(),
+            threshold
+        ));
+    }
+
+    let points: Vec<(u8, &[u8])> = unique_shares[..threshold]
+        .iter()
+        .map(|share| (share.index, share.value.as_ref()))
+        .collect();
+    let sk_raw = combine_shares(&points);
+
+    let keypair = match Keypar::from_secret_only(&sk_raw) {
+        Some(keypair) => keypair,
+        None => return error_without_parent("Could not rebuild key pair from backup"),
+    };
+    if get_short_fingerprint(&keypair) != first_share.fingerprint {
+        return error_without_parent("Recovered key does not match backup fingerprint");
+    }
+
+    Ok(keypair)
+}
+
+// -- Shamir secret sharing over GF(256)
+
+pub(crate) fn split_secret(secret: &[u8], threshold: u8, shares: u8) -> Vec<(u8, SecretBytes)> {
+    // One random polynomial per byte, whose constant term is that byte of the secret
+    let mut coefficients = SecretBytes::new(randombytes(secret.len() * threshold as usize));
+    for (byte_index, byte) in secret.iter().enumerate() {
+        coefficients.as_mut()[byte_index * threshold as usize] = *byte;
+    }
+
+    (1..=shares)
+        .map(|x| {
+            let values = secret
+                .iter()
+                .enumerate()
+                .map(|(byte_index, _)| {
+                    let start = byte_index * threshold as usize;
+                    let polynomial = &coefficients[start..start + threshold as usize];
+                    polynomial
+                        .iter()
+                        .rev()
+                        .fold(0, |acc, coefficient| gf_mul(acc, x) ^ coefficient)
+                })
+                .collect();
+            (x, SecretBytes::new(values))
+        })
+        .collect()
+}
+
+pub(crate) fn combine_shares(points: &[(u8, &[u8])]) -> SecretBytes {
+    let secret_len = points.first().map(|(_, values)| values.len()).unwrap_or(0);
+    let mut secret = SecretBytes::new(vec![0; secret_len]);
+
+    // Lagrange interpolation at zero, where subtraction is xor
+    for (i, (x_i, values_i)) in points.iter().enumerate() {
+        let mut basis = 1;
+        for (j, (x_j, _)) in points.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_div(*x_j, x_j ^ x_i));
+            }
+        }
+        for (byte, value) in secret.as_mut().iter_mut().zip(values_i.iter()) {
+            *byte ^= gf_mul(*value, basis);
+        }
+    }
+
+    secret
+}
+
+// Helper functions
+//
+
+fn get_short_fingerprint(keypair: &Keypar) -> Vec<u8> {
+    let digest = sha256::hash(keypair.public_key().as_ref());
+    digest.as_ref()[..FINGERPRINT_LEN].to_vec()
+}
+
+fn get_checksum(content: &[u8]) -> Vec<u8> {
+    let digest = sha256::hash(content);
+    digest.as_ref()[..CHECKSUM_LEN].to_vec()
+}
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    // Branchless, so timing does not depend on secret bytes
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = a >> 7;
+        a = (a << 1) ^ (0x1b & 0u8.wrapping_sub(carry));
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 is the inverse of b, as every non zero element to the 255th power is one
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+    gf_mul(a, inverse)
+}
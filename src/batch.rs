@@ -0,0 +1,277 @@
+use crate::{
+    audit, decrypt_file, encrypt_file_with_keys, error, error_without_parent, get_encrypted_name,
+    get_plain_file_name, open_storage, plan_decryption, plan_encryption, progress, read_keypair,
+    read_profile, ssh, AnyError, Context, DecryptOptions, EncryptOptions, Plan, Report,
+};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Custom types
+//
+
+// One file failing does not stop the others, so each of them gets its own result
+#[derive(Debug)]
+pub struct Outcome<T = Report> {
+    pub file_path: String,
+    pub result: Result<T, AnyError>,
+}
+
+// Entrypoint functions
+//
+
+pub fn encrypt(
+    profile_name: &String,
+    patterns: &[String],
+    should_override: bool,
+    options: &EncryptOptions,
+    jobs: Option<usize>,
+) -> Result<Vec<Outcome>, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Batch encryption failed while reading user profile", reason),
+    };
+
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Batch encryption failed while reading key pair", reason),
+    };
+
+    let recipients = match ssh::parse_recipients(&options.recipients) {
+        Ok(recipients) => recipients,
+        Err(reason) => return error("Batch encryption failed while reading recipients", reason),
+    };
+
+    let file_paths = match expand_patterns(&patterns) {
+        Ok(file_paths) => file_paths,
+        Err(reason) => return error("Batch encryption failed while expanding patterns", reason),
+    };
+
+    let clashing_paths = find_clashing_paths(&file_paths, |file_path| {
+        Some(get_encrypted_name(&file_path))
+    });
+    let storage = open_storage(&profile);
+    let _task = progress::start("Encrypting", &file_paths.iter().collect::<Vec<_>>(), true);
+    run_in_parallel(&file_paths, jobs, |file_path| {
+        check_clash(&clashing_paths, &file_path)?;
+        plan_encryption(&profile, &file_path, should_override)?;
+        let report = encrypt_file_with_keys(
+            &profile,
+            storage.as_ref(),
+            &keypair,
+            &recipients,
+            &file_path,
+            should_override,
+            &options,
+        )?;
+        Ok(audit::record_done(
+            &profile,
+            Some(&keypair),
+            "encrypt",
+            &file_path,
+            report,
+        ))
+    })
+}
+
+pub fn decrypt(
+    profile_name: &String,
+    patterns: &[String],
+    dest_dir: &String,
+    should_override: bool,
+    options: &DecryptOptions,
+    jobs: Option<usize>,
+) -> Result<Vec<Outcome>, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Batch decryption failed while reading user profile", reason),
+    };
+
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Batch decryption failed while reading key pair", reason),
+    };
+
+    let file_paths = match expand_patterns(&patterns) {
+        Ok(file_paths) => file_paths,
+        Err(reason) => return error("Batch decryption failed while expanding patterns", reason),
+    };
+
+    let clashing_paths = find_clashing_paths(&file_paths, |file_path| {
+        get_plain_file_name(&file_path).ok()
+    });
+    let _task = progress::start("Decrypting", &file_paths.iter().collect::<Vec<_>>(), true);
+    run_in_parallel(&file_paths, jobs, |file_path| {
+        check_clash(&clashing_paths, &file_path)?;
+        plan_decryption(&file_path, &dest_dir, should_override)?;
+        let context = Context::for_encrypted(&file_path, Some(&profile), &options.label)?;
+        let report = decrypt_file(&keypair, &file_path, &dest_dir, should_override, &context)?;
+        Ok(audit::record_done(
+            &profile,
+            Some(&keypair),
+            "decrypt",
+            &file_path,
+            report,
+        ))
+    })
+}
+
+pub fn plan_encrypt(
+    profile_name: &String,
+    patterns: &[String],
+    should_override: bool,
+) -> Result<Vec<Outcome<Plan>>, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Batch encryption failed while reading user profile", reason),
+    };
+
+    let file_paths = match expand_patterns(&patterns) {
+        Ok(file_paths) => file_paths,
+        Err(reason) => return error("Batch encryption failed while expanding patterns", reason),
+    };
+
+    let clashing_paths = find_clashing_paths(&file_paths, |file_path| {
+        Some(get_encrypted_name(&file_path))
+    });
+    Ok(plan_each(&file_paths, |file_path| {
+        check_clash(&clashing_paths, &file_path)?;
+        plan_encryption(&profile, &file_path, should_override)
+    }))
+}
+
+pub fn plan_decrypt(
+    patterns: &[String],
+    dest_dir: &String,
+    should_override: bool,
+) -> Result<Vec<Outcome<Plan>>, AnyError> {
+    let file_paths = match expand_patterns(&patterns) {
+        Ok(file_paths) => file_paths,
+        Err(reason) => return error("Batch decryption failed while expanding patterns", reason),
+    };
+
+    let clashing_paths = find_clashing_paths(&file_paths, |file_path| {
+        get_plain_file_name(&file_path).ok()
+    });
+    Ok(plan_each(&file_paths, |file_path| {
+        check_clash(&clashing_paths, &file_path)?;
+        plan_decryption(&file_path, &dest_dir, should_override)
+    }))
+}
+
+// Business functions
+//
+
+fn run_in_parallel<F>(
+    file_paths: &[String],
+    jobs: Option<usize>,
+    process_file: F,
+) -> Result<Vec<Outcome>, AnyError>
+where
+    F: Fn(&String) -> Result<Report, AnyError> + Sync,
+{
+    // Zero threads lets rayon pick one per core
+    let pool = match ThreadPoolBuilder::new()
+        .num_threads(jobs.unwrap_or(0))
+        .build()
+    {
+        Ok(pool) => pool,
+        Err(reason) => return error("Could not start worker threads", reason),
+    };
+
+    let outcomes = pool.install(|| {
+        file_paths
+            .par_iter()
+            .map(|file_path| {
+                let result = process_file(file_path);
+                progress::complete_file();
+                Outcome {
+                    file_path: file_path.to_owned(),
+                    result,
+                }
+            })
+            .collect()
+    });
+    Ok(outcomes)
+}
+
+// Nothing is read nor written while planning, so there is no point in spreading it across cores
+fn plan_each<F>(file_paths: &[String], plan_file: F) -> Vec<Outcome<Plan>>
+where
+    F: Fn(&String) -> Result<Plan, AnyError>,
+{
+    file_paths
+        .iter()
+        .map(|file_path| Outcome {
+            file_path: file_path.to_owned(),
+            result: plan_file(file_path),
+        })
+        .collect()
+}
+
+// Helper functions
+//
+
+// Patterns matching nothing are kept as they are, so they get reported rather than skipped
+fn expand_patterns(patterns: &[String]) -> Result<Vec<String>, AnyError> {
+    let mut file_paths: Vec<String> = vec![];
+    for pattern in patterns {
+        let entries = match glob::glob(&pattern) {
+            Ok(entries) => entries,
+            Err(reason) => return error(&format!("Pattern {} is not valid", pattern), reason),
+        };
+
+        let mut matched_paths = vec![];
+        for entry in entries {
+            match entry {
+                Ok(path) => matched_paths.push(path),
+                Err(reason) => return error("Could not read matching path", reason),
+            }
+        }
+        if matched_paths.is_empty() {
+            matched_paths.push(Path::new(pattern).to_path_buf());
+        }
+
+        for path in matched_paths {
+            let file_path = format!("{}", path.display());
+            if !path.is_dir() && !file_paths.contains(&file_path) {
+                file_paths.push(file_path);
+            }
+        }
+    }
+
+    Ok(file_paths)
+}
+
+// Targets are named after the file name alone, so files of the same name in different
+// directories would land on one another, whatever order the workers happen to run in
+fn find_clashing_paths<F>(file_paths: &[String], get_target_name: F) -> Vec<String>
+where
+    F: Fn(&String) -> Option<String>,
+{
+    let mut target_counts: HashMap<String, usize> = HashMap::new();
+    for file_path in file_paths {
+        if let Some(target_name) = get_target_name(file_path) {
+            *target_counts.entry(target_name).or_insert(0) += 1;
+        }
+    }
+
+    file_paths
+        .iter()
+        .filter(|file_path| {
+            get_target_name(file_path).is_some_and(|target_name| target_counts[&target_name] > 1)
+        })
+        .cloned()
+        .collect()
+}
+
+fn check_clash(clashing_paths: &[String], file_path: &String) -> Result<(), AnyError> {
+    if clashing_paths.contains(&file_path) {
+        return error_without_parent(
+            "Batch failed because another file in it would be saved under the same name",
+        );
+    }
+
+    Ok(())
+}
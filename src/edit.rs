@@ -0,0 +1,244 @@
+use crate::{
+    audit, check_context, encode_cipher_file, error, error_without_parent, file_exists,
+    get_plain_file_name, is_armored_file, key_fingerprint, open_cipher, read_encrypted_file,
+    read_keypair, read_profile, seal_content, shred_file, storage, AnyError, Context,
+    DecryptOptions, EncryptOptions, Header, Keypar, Report, SecretBytes,
+};
+use data_encoding::HEXLOWER;
+use sodiumoxide::crypto::box_;
+use sodiumoxide::randombytes::randombytes;
+use sodiumoxide::utils::memcmp;
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Custom types
+//
+
+// Private directory holding the plain copy, shredded whatever way editing ends
+struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    fn create() -> Result<ScratchDir, AnyError> {
+        let dir_name = format!("moy-sekret-edit-{}", HEXLOWER.encode(&randombytes(8)));
+        let path = get_scratch_base_dir().join(dir_name);
+
+        match create_private_dir(&path) {
+            Ok(_) => Ok(ScratchDir { path }),
+            Err(reason) => error("Could not create private temporary directory", reason),
+        }
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        // Editors may leave swap and backup files next to the one being edited
+        if let Ok(entries) = fs::read_dir(&self.path) {
+            for entry in entries.flatten() {
+                if let Ok(file_type) = entry.file_type() {
+                    if file_type.is_file() {
+                        let _ = shred_file(&format!("{}", entry.path().display()));
+                    }
+                }
+            }
+        }
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Entrypoint functions
+//
+
+pub fn run(
+    profile_name: &String,
+    file_path: &String,
+    editor: &String,
+    options: &DecryptOptions,
+) -> Result<Report, AnyError> {
+    if !file_path.ends_with(".cz") {
+        return error_without_parent(
+            "Editing failed because source file was not made by this program (.cz)",
+        );
+    }
+
+    if !file_exists(&file_path) {
+        return error_without_parent("Editing failed because source file does not exists");
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Editing failed while reading user profile", reason),
+    };
+
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Editing failed while reading key pair", reason),
+    };
+
+    let cipher = match read_encrypted_file(&file_path) {
+        Ok(cipher) => cipher,
+        Err(reason) => return error("Editing failed while reading file", reason),
+    };
+
+    let plain_content = match open_cipher(&keypair, &cipher) {
+        Ok(content) => content,
+        Err(reason) => return error("Editing failed while decrypting file", reason),
+    };
+
+    let context = Context::for_encrypted(&file_path, Some(&profile), &options.label)?;
+    match check_context(&cipher, &context, Some(keypair.public_key())) {
+        Ok(_) => (),
+        Err(reason) => return error("Editing failed while checking file context", reason),
+    };
+    let should_armor = is_armored_file(&file_path);
+
+    let scratch_dir = match ScratchDir::create() {
+        Ok(dir) => dir,
+        Err(reason) => return error("Editing failed while preparing temporary copy", reason),
+    };
+
+    let edited_content = match edit_content(&scratch_dir, &file_path, &plain_content, &editor) {
+        Ok(content) => content,
+        Err(reason) => return error("Editing failed while running editor", reason),
+    };
+    drop(scratch_dir);
+
+    // Opening it is worth recording, even when nothing was changed
+    let mut report = Report {
+        fingerprints: vec![key_fingerprint(keypair.public_key())],
+        ..Report::default()
+    };
+    if !memcmp(&plain_content, &edited_content) {
+        // Edited version is prepared, shared and bound just like the original, only files made
+        // before headers existed fall back to the profile settings
+        let original_header = match cipher.header {
+            Some(header) => header,
+            None => Header::for_profile(&profile, &EncryptOptions::default()),
+        };
+        let header = Header {
+            context: original_header.context.to_owned().or(Some(context)),
+            ..original_header
+        };
+        match save_edited_file(&keypair, &header, &file_path, &edited_content, should_armor) {
+            Ok(_) => report.written.push(file_path.to_owned()),
+            Err(reason) => return error("Editing failed while encrypting changes", reason),
+        };
+    }
+
+    Ok(audit::record_done(
+        &profile,
+        Some(&keypair),
+        "edit",
+        &file_path,
+        report,
+    ))
+}
+
+// Business functions
+//
+
+fn edit_content(
+    scratch_dir: &ScratchDir,
+    file_path: &String,
+    plain_content: &[u8],
+    editor: &String,
+) -> Result<SecretBytes, AnyError> {
+    // Keep the original name so editors still pick the right syntax highlighting
+    let plain_file_name = get_plain_file_name(&file_path)?;
+    let plain_file_path = scratch_dir.path.join(plain_file_name);
+    write_private_file(&plain_file_path, &plain_content)?;
+
+    let mut editor_parts = editor.split_whitespace();
+    let editor_program = match editor_parts.next() {
+        Some(program) => program,
+        None => return error_without_parent("No editor was given"),
+    };
+
+    let status = Command::new(editor_program)
+        .args(editor_parts)
+        .arg(&plain_file_path)
+        .status();
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => {
+            return error_without_parent(&format!(
+                "Editor exited with {}, so changes were discarded",
+                status
+            ))
+        }
+        Err(reason) => return error("Could not launch editor", reason),
+    };
+
+    match fs::read(&plain_file_path) {
+        Ok(raw_vec) => Ok(SecretBytes::new(raw_vec)),
+        Err(reason) => error("Could not read edited file", reason),
+    }
+}
+
+fn save_edited_file(
+    keypair: &Keypar,
+    header: &Header,
+    file_path: &String,
+    edited_content: &[u8],
+    should_armor: bool,
+) -> Result<(), AnyError> {
+    let cipher = seal_content(&keypair, &header, &edited_content, box_::gen_nonce())?;
+    let cipher_data = encode_cipher_file(&cipher, should_armor)?;
+
+    // The encrypted file is the only copy there is, so it is never truncated in place
+    storage::replace_file(Path::new(file_path), &cipher_data)
+}
+
+// Helper functions
+//
+
+fn get_scratch_base_dir() -> PathBuf {
+    // Prefer memory backed filesystems, so the plain copy never reaches a disk
+    let shm_dir = Path::new("/dev/shm");
+    if cfg!(target_os = "linux") && shm_dir.is_dir() {
+        return shm_dir.to_path_buf();
+    }
+
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(runtime_dir) if Path::new(&runtime_dir).is_dir() => PathBuf::from(runtime_dir),
+        _ => env::temp_dir(),
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new().mode(0o700).create(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    fs::create_dir(path)
+}
+
+fn write_private_file(file_path: &Path, content: &[u8]) -> Result<(), AnyError> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = match options.open(file_path) {
+        Ok(file) => file,
+        Err(reason) => return error("Could not create temporary file", reason),
+    };
+
+    match file.write_all(content) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not write temporary file", reason),
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    audit, check_context, error, error_without_parent, key_fingerprint, open_cipher,
+    read_encrypted_file, read_keypair, read_profile, AnyError, Context, DecryptOptions, Report,
+    SecretBytes,
+};
+use std::process::Command;
+
+// Entrypoint functions
+//
+
+pub fn run(
+    profile_name: &String,
+    file_path: &String,
+    command: &[String],
+    options: &DecryptOptions,
+) -> Result<i32, AnyError> {
+    if !file_path.ends_with(".cz") {
+        return error_without_parent(
+            "Execution failed because environment file was not made by this program (.cz)",
+        );
+    }
+
+    if command.is_empty() {
+        return error_without_parent("Execution failed because there is no command to run");
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Execution failed while reading user profile", reason),
+    };
+
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Execution failed while reading key pair", reason),
+    };
+
+    let cipher = match read_encrypted_file(&file_path) {
+        Ok(cipher) => cipher,
+        Err(reason) => return error("Execution failed while reading environment file", reason),
+    };
+
+    // Plain content never leaves memory, it only reaches the child through its environment
+    let plain_content = match open_cipher(&keypair, &cipher) {
+        Ok(content) => content,
+        Err(reason) => return error("Execution failed while decrypting environment file", reason),
+    };
+
+    let context = Context::for_encrypted(&file_path, Some(&profile), &options.label)?;
+    match check_context(&cipher, &context, Some(keypair.public_key())) {
+        Ok(_) => (),
+        Err(reason) => {
+            return error(
+                "Execution failed while checking environment file context",
+                reason,
+            )
+        }
+    };
+
+    // Recorded before the command runs, as it may never give control back
+    let report = Report {
+        fingerprints: vec![key_fingerprint(keypair.public_key())],
+        ..Report::default()
+    };
+    match audit::record(&profile, Some(&keypair), "exec", &file_path, &report) {
+        Ok(_) => (),
+        Err(reason) => return error("Execution failed while recording it to the log", reason),
+    };
+
+    // The command has no business with the keys, so they are wiped before it starts
+    drop(keypair);
+
+    let variables = match parse_dotenv(&plain_content) {
+        Ok(variables) => variables,
+        Err(reason) => return error("Execution failed while parsing environment file", reason),
+    };
+    drop(plain_content);
+
+    // Command copies every value into an environment of its own that is never wiped,
+    // so the only copies we can wipe are ours, as soon as they are handed over
+    let mut child = Command::new(&command[0]);
+    child.args(&command[1..]);
+    for (key, value) in &variables {
+        child.env(key, std::str::from_utf8(value).unwrap_or_default());
+    }
+    drop(variables);
+
+    match child.status() {
+        Ok(status) => Ok(exit_code(status)),
+        Err(reason) => error("Execution failed while running command", reason),
+    }
+}
+
+// Business functions
+//
+
+fn parse_dotenv(content: &[u8]) -> Result<Vec<(String, SecretBytes)>, AnyError> {
+    let text = match std::str::from_utf8(content) {
+        Ok(text) => text,
+        Err(reason) => return error("Environment file is not valid UTF-8", reason),
+    };
+
+    let mut variables = Vec::new();
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_dotenv_line(line) {
+            Some(variable) => variables.push(variable),
+            None => {
+                return error_without_parent(&format!(
+                    "Could not parse line {} of environment file",
+                    index + 1
+                ))
+            }
+        }
+    }
+
+    Ok(variables)
+}
+
+fn parse_dotenv_line(line: &str) -> Option<(String, SecretBytes)> {
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let separator = line.find('=')?;
+    let key = line[..separator].trim();
+    if !is_valid_key(key) {
+        return None;
+    }
+
+    let raw_value = line[separator + 1..].trim_start();
+    let value = if let Some(quoted) = raw_value.strip_prefix('"') {
+        unescape_double_quoted(quoted)?
+    } else if let Some(quoted) = raw_value.strip_prefix('\'') {
+        let end = quoted.find('\'')?;
+        quoted[..end].to_owned()
+    } else {
+        let value = match raw_value.find(" #") {
+            Some(comment) => &raw_value[..comment],
+            None => raw_value,
+        };
+        value.trim_end().to_owned()
+    };
+
+    Some((key.to_owned(), SecretBytes::new(value.into_bytes())))
+}
+
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn unescape_double_quoted(quoted: &str) -> Option<String> {
+    // Sized up front, as growing would leave partial copies of the value behind
+    let mut value = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                other => value.push(other),
+            },
+            other => value.push(other),
+        }
+    }
+
+    // Missing closing quote
+    None
+}
+
+// Helper functions
+//
+
+#[cfg(unix)]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
@@ -0,0 +1,261 @@
+use crate::{
+    check_context, deserialize_cipher, error, error_without_parent, open_cipher, pack_payload,
+    profile_exists, read_keypair, read_profile, seal_content, serialize_cipher, AnyError, Context,
+    EncryptOptions, Header, Keypar, Profile, Report, SecretBytes,
+};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::{Nonce, NONCEBYTES};
+use sodiumoxide::crypto::hash::sha256;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process::Command;
+
+pub const FILTER_NAME: &str = "moy-sekret";
+
+// Entrypoint functions
+//
+
+// Filters are not recorded to the log, as git runs them on every status, diff and checkout
+// whether anything changed or not, and many of them at once
+
+pub fn clean(
+    profile_name: &String,
+    file_path: &String,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), AnyError> {
+    let (profile, keypair) = match read_profile_keypair(&profile_name) {
+        Ok(found) => found,
+        Err(reason) => return error("Git clean filter failed while reading key pair", reason),
+    };
+
+    let plain_content = match read_input(input) {
+        Ok(content) => content,
+        Err(reason) => return error("Git clean filter failed while reading input", reason),
+    };
+
+    // Content that is already encrypted with our keys for this very path goes through untouched
+    let context = Context::for_path(&file_path, &profile);
+    if let Ok(cipher) = deserialize_cipher(&plain_content) {
+        if open_cipher(&keypair, &cipher).is_ok()
+            && check_context(&cipher, &context, Some(keypair.public_key())).is_ok()
+        {
+            return write_output(output, &plain_content);
+        }
+    }
+
+    // Bound to its path in the repository, so tracked files cannot be swapped for each other
+    let header = Header {
+        context: Some(context),
+        ..Header::for_profile(&profile, &EncryptOptions::default())
+    };
+
+    let nonce = match derive_nonce(&keypair, &file_path, &header, &plain_content) {
+        Ok(nonce) => nonce,
+        Err(reason) => return error("Git clean filter failed while deriving nonce", reason),
+    };
+    let cipher_data = match seal_content(&keypair, &header, &plain_content, nonce)
+        .and_then(|cipher| serialize_cipher(&cipher))
+    {
+        Ok(data) => data,
+        Err(reason) => return error("Git clean filter failed while encrypting", reason),
+    };
+
+    write_output(output, &cipher_data)
+}
+
+pub fn smudge(
+    profile_name: &String,
+    file_path: &String,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), AnyError> {
+    let cipher_content = match read_input(input) {
+        Ok(content) => content,
+        Err(reason) => return error("Git smudge filter failed while reading input", reason),
+    };
+
+    // Files committed before the filter was set up are not encrypted at all
+    let cipher = match deserialize_cipher(&cipher_content) {
+        Ok(cipher) => cipher,
+        Err(_) => return write_output(output, &cipher_content),
+    };
+
+    let (profile, keypair) = match read_profile_keypair(&profile_name) {
+        Ok(found) => found,
+        Err(reason) => return error("Git smudge filter failed while reading key pair", reason),
+    };
+
+    let plain_content = match open_cipher(&keypair, &cipher) {
+        Ok(content) => content,
+        Err(reason) => return error("Git smudge filter failed while decrypting", reason),
+    };
+
+    let context = Context::for_path(&file_path, &profile);
+    match check_context(&cipher, &context, Some(keypair.public_key())) {
+        Ok(_) => (),
+        Err(reason) => {
+            return error(
+                "Git smudge filter failed while checking file context",
+                reason,
+            )
+        }
+    };
+
+    write_output(output, &plain_content)
+}
+
+pub fn setup(
+    profile_name: &String,
+    repo_dir: &String,
+    patterns: &[String],
+    program: &String,
+) -> Result<Report, AnyError> {
+    let repo_path = Path::new(repo_dir);
+    if !repo_path.join(".git").exists() {
+        return error_without_parent("Git setup failed because directory is not a git repository");
+    }
+
+    if !profile_exists(&profile_name) {
+        return error_without_parent("Git setup failed because profile does not exists");
+    }
+
+    let program = shell_quote(&program);
+    let profile = shell_quote(&profile_name);
+    let settings = [
+        (
+            format!("filter.{}.clean", FILTER_NAME),
+            format!("{} git-filter clean -p {} --path %f", program, profile),
+        ),
+        (
+            format!("filter.{}.smudge", FILTER_NAME),
+            format!("{} git-filter smudge -p {} --path %f", program, profile),
+        ),
+        (
+            format!("filter.{}.required", FILTER_NAME),
+            "true".to_owned(),
+        ),
+    ];
+    for (name, value) in settings.iter() {
+        match set_git_config(&repo_dir, &name, &value) {
+            Ok(_) => (),
+            Err(reason) => return error("Git setup failed while writing git config", reason),
+        };
+    }
+
+    let attributes_file_path = format!("{}/.gitattributes", repo_dir);
+    match add_git_attributes(&attributes_file_path, &patterns) {
+        Ok(_) => (),
+        Err(reason) => return error("Git setup failed while writing .gitattributes", reason),
+    };
+
+    Ok(Report {
+        written: vec![attributes_file_path, format!("{}/.git/config", repo_dir)],
+        ..Report::default()
+    })
+}
+
+// Business functions
+//
+
+fn read_profile_keypair(profile_name: &String) -> Result<(Profile, Keypar), AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Could not read user profile", reason),
+    };
+
+    let keypair = read_keypair(&profile)?;
+    Ok((profile, keypair))
+}
+
+fn derive_nonce(
+    keypair: &Keypar,
+    file_path: &String,
+    header: &Header,
+    plain_content: &[u8],
+) -> Result<Nonce, AnyError> {
+    // Same keys, path and content always give the same nonce, so unchanged files do not churn,
+    // while the payload is hashed as it will be sealed, so other settings give another nonce
+    let payload_data = pack_payload(&header, &plain_content)?;
+    let mut message = (file_path.len() as u64).to_le_bytes().to_vec();
+    message.extend_from_slice(file_path.as_bytes());
+    message.extend_from_slice(sha256::hash(&payload_data).as_ref());
+    let digest = match keypair.keyed_digest(b"moy-sekret git-filter", &message) {
+        Ok(digest) => digest,
+        Err(reason) => return error("Could not derive nonce", reason),
+    };
+
+    match Nonce::from_slice(&digest[..NONCEBYTES]) {
+        Some(nonce) => Ok(nonce),
+        None => error_without_parent("Could not build nonce"),
+    }
+}
+
+fn set_git_config(repo_dir: &String, name: &String, value: &String) -> Result<(), AnyError> {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repo_dir)
+        .args(&["config", "--local", name, value])
+        .status();
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => error_without_parent(&format!("git config exited with {}", status)),
+        Err(reason) => error("Could not run git", reason),
+    }
+}
+
+fn add_git_attributes(attributes_file_path: &String, patterns: &[String]) -> Result<(), AnyError> {
+    let existing = fs::read_to_string(attributes_file_path).unwrap_or_default();
+    let existing_lines: Vec<&str> = existing.lines().map(|line| line.trim()).collect();
+
+    let mut new_lines = String::new();
+    for pattern in patterns {
+        let line = format!("{} filter={}", pattern, FILTER_NAME);
+        if !existing_lines.contains(&line.as_str()) {
+            new_lines.push_str(&line);
+            new_lines.push('\n');
+        }
+    }
+    if new_lines.is_empty() {
+        return Ok(());
+    }
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        new_lines.insert(0, '\n');
+    }
+
+    let mut attributes_file = match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(attributes_file_path)
+    {
+        Ok(file) => file,
+        Err(reason) => return error("Could not open attributes file", reason),
+    };
+
+    match attributes_file.write_all(new_lines.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not write attributes file", reason),
+    }
+}
+
+// Helper functions
+//
+
+fn read_input(input: &mut dyn Read) -> Result<SecretBytes, AnyError> {
+    match SecretBytes::read_from(input, 0) {
+        Ok(content) => Ok(content),
+        Err(reason) => error("Could not read input", reason),
+    }
+}
+
+fn write_output(output: &mut dyn Write, content: &[u8]) -> Result<(), AnyError> {
+    match output.write_all(content).and_then(|_| output.flush()) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not write output", reason),
+    }
+}
+
+fn shell_quote(value: &String) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
@@ -1,18 +1,39 @@
-use data_encoding::BASE64;
+use data_encoding::{BASE64, BASE64_NOPAD};
 use dirs;
 use serde::{Deserialize, Serialize};
 use sodiumoxide::crypto::box_;
 use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::Nonce;
 use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
 use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::SecretKey;
+use sodiumoxide::crypto::generichash;
+use sodiumoxide::crypto::hash::sha256;
+use sodiumoxide::crypto::pwhash::argon2id13;
+use sodiumoxide::crypto::scalarmult::curve25519::{scalarmult_base, Scalar};
+use sodiumoxide::crypto::secretbox;
+use sodiumoxide::crypto::sign::ed25519;
+use sodiumoxide::randombytes::randombytes_into;
+use sodiumoxide::utils::{memcmp, memzero, mlock, munlock};
 use std::error::Error;
 use std::fmt;
 use std::fs;
 use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
 use std::io::prelude::*;
+use std::iter;
+use std::ops::Deref;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process;
+use std::str::FromStr;
+
+const CONTAINER_MAGIC: &[u8] = b"MOYSEK";
+const CONTAINER_VERSION: u8 = 2;
+const MIN_READ_LEN: usize = 8 * 1024;
+const SENDER_TAG_LEN: usize = 32;
+const SENDER_TAG_CONTEXT: &[u8] = b"moy-sekret sender tag";
+const KEYED_DIGEST_LEN: usize = 32;
 
 // Custom types
 //
@@ -21,6 +42,66 @@ use std::process;
 pub struct Profile {
     pub name: String,
     pub storage: String,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub padding: Padding,
+    #[serde(default)]
+    pub log_signing: audit::LogSigning,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Compression, AnyError> {
+        match value {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            _ => error_without_parent("Compression must be either none or zstd"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Padding {
+    #[default]
+    None,
+    Padme,
+}
+
+impl FromStr for Padding {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> Result<Padding, AnyError> {
+        match value {
+            "none" => Ok(Padding::None),
+            "padme" => Ok(Padding::Padme),
+            _ => error_without_parent("Padding must be either none or padme"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct EncryptOptions {
+    pub compression: Option<Compression>,
+    pub padding: Option<Padding>,
+    pub armor: bool,
+    pub recipients: Vec<String>,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DecryptOptions {
+    pub label: Option<String>,
 }
 
 #[derive(Debug)]
@@ -38,18 +119,477 @@ impl fmt::Display for Key {
     }
 }
 
-pub type Keypar = (PublicKey, SecretKey);
+pub struct Keypar {
+    pk: PublicKey,
+    holder: SecretHolder,
+}
+
+// Secret key is either kept right here or by a running agent, which then does the work
+enum SecretHolder {
+    Memory { sk: Box<SecretKey>, locked: bool },
+    Agent(agent::AgentKey),
+}
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Keypar {
+    pub fn new(pk: PublicKey, sk: SecretKey) -> Keypar {
+        // Given key wipes itself when dropped, so only the copy is left
+        Keypar::from_secret_slice(pk, &sk.0).unwrap()
+    }
+
+    fn from_secret_slice(pk: PublicKey, sk_raw: &[u8]) -> Option<Keypar> {
+        if sk_raw.len() != box_::SECRETKEYBYTES {
+            return None;
+        }
+
+        // Locked before copying, so the key never sits in memory that could be swapped out
+        // Locking may fail due to RLIMIT_MEMLOCK, in which case we still wipe on drop
+        let mut boxed_sk = Box::new(SecretKey([0; box_::SECRETKEYBYTES]));
+        let locked = mlock(&mut boxed_sk.0).is_ok();
+        boxed_sk.0.copy_from_slice(sk_raw);
+        Some(Keypar {
+            pk,
+            holder: SecretHolder::Memory {
+                sk: boxed_sk,
+                locked,
+            },
+        })
+    }
+
+    // Public key is always derivable from the secret one, which is all a backup needs to hold
+    fn from_secret_only(sk_raw: &[u8]) -> Option<Keypar> {
+        let scalar = Scalar::from_slice(sk_raw)?;
+        let pk = PublicKey(scalarmult_base(&scalar).0);
+        Keypar::from_secret_slice(pk, sk_raw)
+    }
+
+    fn from_agent(pk: PublicKey, agent_key: agent::AgentKey) -> Keypar {
+        Keypar {
+            pk,
+            holder: SecretHolder::Agent(agent_key),
+        }
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.pk
+    }
+
+    pub fn secret_key(&self) -> Option<&SecretKey> {
+        match &self.holder {
+            SecretHolder::Memory { sk, .. } => Some(sk),
+            SecretHolder::Agent(_) => None,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        match &self.holder {
+            SecretHolder::Memory { locked, .. } => *locked,
+            SecretHolder::Agent(_) => false,
+        }
+    }
+
+    pub fn is_from_agent(&self) -> bool {
+        match &self.holder {
+            SecretHolder::Memory { .. } => false,
+            SecretHolder::Agent(_) => true,
+        }
+    }
+
+    pub fn wipe(&mut self) {
+        if let SecretHolder::Memory { sk, .. } = &mut self.holder {
+            memzero(&mut sk.0);
+        }
+    }
+
+    fn seal(&self, plain_content: &[u8], nonce: &Nonce) -> Result<Vec<u8>, AnyError> {
+        match &self.holder {
+            SecretHolder::Memory { sk, .. } => Ok(box_::seal(plain_content, nonce, &self.pk, sk)),
+            SecretHolder::Agent(agent_key) => agent_key.seal(plain_content, nonce),
+        }
+    }
+
+    fn open(&self, cipher_data: &[u8], nonce: &Nonce) -> Result<SecretBytes, AnyError> {
+        self.open_from(cipher_data, nonce, &self.pk)
+    }
+
+    fn open_from(
+        &self,
+        cipher_data: &[u8],
+        nonce: &Nonce,
+        peer_pk: &PublicKey,
+    ) -> Result<SecretBytes, AnyError> {
+        match &self.holder {
+            SecretHolder::Memory { sk, .. } => match box_::open(cipher_data, nonce, peer_pk, sk) {
+                Ok(data) => Ok(SecretBytes::new(data)),
+                Err(_) => error_without_parent("Could not decrypt file"),
+            },
+            SecretHolder::Agent(agent_key) => agent_key.open_from(cipher_data, nonce, peer_pk),
+        }
+    }
+
+    // Made with the key this pair shares with the peer, so only the two of them can make it
+    fn sender_tag(&self, peer_pk: &PublicKey, message: &[u8]) -> Result<Vec<u8>, AnyError> {
+        match &self.holder {
+            SecretHolder::Memory { sk, .. } => {
+                let shared_key = box_::precompute(peer_pk, sk);
+                match keyed_hash(
+                    SENDER_TAG_LEN,
+                    shared_key.as_ref(),
+                    &[SENDER_TAG_CONTEXT, message],
+                ) {
+                    Ok(digest) => Ok(digest.as_ref().to_vec()),
+                    Err(_) => error_without_parent("Could not compute sender tag"),
+                }
+            }
+            SecretHolder::Agent(agent_key) => agent_key.sender_tag(peer_pk, message),
+        }
+    }
+
+    // Digest under a subkey bound to the given context, while the subkey itself never leaves
+    fn keyed_digest(&self, context: &[u8], message: &[u8]) -> Result<Vec<u8>, AnyError> {
+        match &self.holder {
+            SecretHolder::Memory { sk, .. } => {
+                let subkey = derive_subkey(sk, context)?;
+                match keyed_hash(KEYED_DIGEST_LEN, &subkey, &[message]) {
+                    Ok(digest) => Ok(digest.as_ref().to_vec()),
+                    Err(_) => error_without_parent("Could not compute keyed digest"),
+                }
+            }
+            SecretHolder::Agent(agent_key) => agent_key.keyed_digest(context, message),
+        }
+    }
+
+    // Signing key is derived from the box secret key, so there is no other key to keep safe
+    fn signing_public_key(&self, context: &[u8]) -> Result<ed25519::PublicKey, AnyError> {
+        match &self.holder {
+            SecretHolder::Memory { sk, .. } => Ok(derive_signing_keypair(sk, context)?.0),
+            SecretHolder::Agent(agent_key) => agent_key.signing_public_key(context),
+        }
+    }
+
+    fn sign(&self, context: &[u8], message: &[u8]) -> Result<ed25519::Signature, AnyError> {
+        match &self.holder {
+            SecretHolder::Memory { sk, .. } => {
+                let (_, signing_sk) = derive_signing_keypair(sk, context)?;
+                Ok(ed25519::sign_detached(message, &signing_sk))
+            }
+            SecretHolder::Agent(agent_key) => agent_key.sign(context, message),
+        }
+    }
+}
+
+impl Drop for Keypar {
+    fn drop(&mut self) {
+        if let SecretHolder::Memory { sk, locked: true } = &mut self.holder {
+            // munlock zeroes the region before unlocking it
+            let _ = munlock(&mut sk.0);
+        } else {
+            self.wipe();
+        }
+    }
+}
+
+impl fmt::Debug for Keypar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.holder {
+            SecretHolder::Memory { .. } => write!(f, "Keypar({:?}, SecretKey(****))", self.pk),
+            SecretHolder::Agent(_) => write!(f, "Keypar({:?}, SecretKey(agent))", self.pk),
+        }
+    }
+}
+
+pub struct SecretBytes {
+    bytes: Vec<u8>,
+    locked: bool,
+}
+
+impl SecretBytes {
+    pub fn new(mut bytes: Vec<u8>) -> SecretBytes {
+        let len = bytes.len();
+        bytes.resize(bytes.capacity(), 0);
+        let locked = !bytes.is_empty() && mlock(&mut bytes).is_ok();
+        bytes.truncate(len);
+        SecretBytes { bytes, locked }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    // Grown by hand, so every outgrown buffer is wiped rather than just given back
+    pub fn read_from(reader: &mut dyn Read, capacity: usize) -> io::Result<SecretBytes> {
+        let mut content = SecretBytes::new(vec![0; capacity.max(MIN_READ_LEN)]);
+        let mut read_len = 0;
+        loop {
+            if read_len == content.len() {
+                let mut grown = SecretBytes::new(vec![0; content.len() * 2]);
+                grown.as_mut()[..read_len].copy_from_slice(&content[..read_len]);
+                content = grown;
+            }
+            match reader.read(&mut content.as_mut()[read_len..]) {
+                Ok(0) => break,
+                Ok(chunk_len) => read_len += chunk_len,
+                Err(reason) if reason.kind() == io::ErrorKind::Interrupted => continue,
+                Err(reason) => return Err(reason),
+            }
+        }
+        content.truncate(read_len);
+        Ok(content)
+    }
+
+    // Cut off bytes stay in the buffer until it is wiped whole
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.bytes.truncate(len);
+    }
+
+    // Spare capacity may still hold what was cut off, so the whole buffer goes
+    pub fn wipe(&mut self) {
+        let len = self.bytes.len();
+        self.bytes.resize(self.bytes.capacity(), 0);
+        memzero(&mut self.bytes);
+        self.bytes.truncate(len);
+    }
+}
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for SecretBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsMut<[u8]> for SecretBytes {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.wipe();
+        if self.locked {
+            self.bytes.resize(self.bytes.capacity(), 0);
+            let _ = munlock(&mut self.bytes);
+        }
+    }
+}
+
+// Goes through the agent socket, so it must never be left behind in plain buffers
+impl Serialize for SecretBytes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretBytes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<SecretBytes, D::Error> {
+        Vec::<u8>::deserialize(deserializer).map(SecretBytes::new)
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretBytes(****; {})", self.bytes.len())
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Report {
+    pub written: Vec<String>,
+    pub removed: Vec<String>,
+    pub fingerprints: Vec<String>,
+    // Went wrong once the change was already made, so the operation still counts as done
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    pub fn merge(mut self, other: Report) -> Report {
+        self.written.extend(other.written);
+        self.removed.extend(other.removed);
+        for fingerprint in other.fingerprints {
+            if !self.fingerprints.contains(&fingerprint) {
+                self.fingerprints.push(fingerprint);
+            }
+        }
+        self.warnings.extend(other.warnings);
+        self
+    }
+}
+
+// What an operation would do on disk, worked out up front so it can be shown without doing it
+#[derive(Serialize, Debug, Default)]
+pub struct Plan {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Plan {
+    pub fn merge(mut self, other: Plan) -> Plan {
+        self.created.extend(other.created);
+        self.overwritten.extend(other.overwritten);
+        self.removed.extend(other.removed);
+        self
+    }
+
+    fn write(&mut self, file_path: String) {
+        if Path::new(&file_path).exists() {
+            self.overwritten.push(file_path);
+        } else {
+            self.created.push(file_path);
+        }
+    }
+}
+
+// Tells how the content was prepared before sealing, so opening can undo it
+// Every feature past the plain box is optional here, so files not using it look the same
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+struct Header {
+    compression: Compression,
+    padding: Padding,
+    // Keys able to open the file besides the profile one, kept so edits can seal for them again
+    recipients: Vec<PublicKey>,
+    // Set when a passphrase stands in for any key pair
+    key_derivation: Option<KeyDerivation>,
+    // Sealed along with the content, so it is authenticated just like the rest of the header
+    context: Option<Context>,
+}
+
+impl Header {
+    fn for_profile(profile: &Profile, options: &EncryptOptions) -> Header {
+        Header {
+            compression: options.compression.unwrap_or(profile.compression),
+            padding: options.padding.unwrap_or(profile.padding),
+            ..Header::default()
+        }
+    }
+
+    fn for_passphrase(options: &EncryptOptions) -> Header {
+        Header {
+            compression: options.compression.unwrap_or_default(),
+            padding: options.padding.unwrap_or_default(),
+            key_derivation: Some(KeyDerivation::generate()),
+            ..Header::default()
+        }
+    }
+}
+
+// Salt and costs the passphrase was stretched with, as the very same are needed to open it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct KeyDerivation {
+    salt: argon2id13::Salt,
+    ops_limit: u64,
+    mem_limit: u64,
+}
+
+impl KeyDerivation {
+    fn generate() -> KeyDerivation {
+        KeyDerivation {
+            salt: argon2id13::gen_salt(),
+            ops_limit: argon2id13::OPSLIMIT_INTERACTIVE.0 as u64,
+            mem_limit: argon2id13::MEMLIMIT_INTERACTIVE.0 as u64,
+        }
+    }
+}
+
+// What the file stands for, so it cannot be moved around to pass for another secret
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Context {
+    file_name: String,
+    profile: Option<String>,
+    label: Option<String>,
+}
+
+impl Context {
+    fn for_source(
+        file_path: &String,
+        profile: Option<&Profile>,
+        label: &Option<String>,
+    ) -> Context {
+        let path = Path::new(file_path);
+        Context {
+            file_name: path.file_name().unwrap().to_str().unwrap().to_owned(),
+            profile: profile.map(|profile| profile.name.to_owned()),
+            label: label.to_owned(),
+        }
+    }
+
+    // Profile is left out when there is none to open the file as, such as with an SSH key
+    fn for_encrypted(
+        file_path: &String,
+        profile: Option<&Profile>,
+        label: &Option<String>,
+    ) -> Result<Context, AnyError> {
+        Ok(Context {
+            file_name: get_plain_file_name(&file_path)?,
+            profile: profile.map(|profile| profile.name.to_owned()),
+            label: label.to_owned(),
+        })
+    }
+
+    // Secrets and tracked files are told apart by their whole path, not just its last part
+    fn for_path(path: &String, profile: &Profile) -> Context {
+        Context {
+            file_name: path.to_owned(),
+            profile: Some(profile.name.to_owned()),
+            label: None,
+        }
+    }
+}
+
+// Files made before headers existed have none, and hold the plain content right in the box
+#[derive(Debug)]
 struct Cipher {
+    version: u8,
+    header: Option<Header>,
+    key_slots: Vec<KeySlot>,
     nonce: Nonce,
     data: Vec<u8>,
 }
 
+impl Cipher {
+    fn is_passphrase_based(&self) -> bool {
+        match &self.header {
+            Some(header) => header.key_derivation.is_some(),
+            None => false,
+        }
+    }
+
+    // Only to be trusted once the slot opened, as its tag is checked along the way
+    fn sender(&self) -> Option<&PublicKey> {
+        self.key_slots.first().map(|slot| &slot.sender)
+    }
+}
+
+// File key wrapped for one recipient by a throwaway key pair, while the tag made with the key
+// the sender shares with that recipient tells who actually encrypted the file
+#[derive(Serialize, Deserialize, Debug)]
+struct KeySlot {
+    recipient: PublicKey,
+    ephemeral_pk: PublicKey,
+    nonce: Nonce,
+    wrapped_key: Vec<u8>,
+    sender: PublicKey,
+    sender_tag: Vec<u8>,
+}
+
+// What actually goes into the box, so the header cannot be changed without notice
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    header: Header,
+    content: SecretBytes,
+}
+
 // Custom error types
 //
 
-type DynError = Box<dyn Error>;
+type DynError = Box<dyn Error + Send + Sync>;
 type OptError = Option<DynError>;
 
 #[derive(Debug)]
@@ -59,19 +599,68 @@ pub struct AnyError {
 }
 
 impl AnyError {
-    fn new(details: &str, reason: OptError) -> AnyError {
+    pub fn new(details: &str, reason: OptError) -> AnyError {
         AnyError {
             details: details.to_string(),
             parent: reason,
         }
     }
 
-    fn without_parent(details: &str) -> AnyError {
+    pub fn without_parent(details: &str) -> AnyError {
         AnyError::new(details, None)
     }
+
+    pub fn chain(&self) -> Vec<String> {
+        let mut chain = vec![self.details.clone()];
+        let mut current = self.parent.as_deref();
+        while let Some(reason) = current {
+            match reason.downcast_ref::<AnyError>() {
+                Some(any_error) => {
+                    chain.push(any_error.details.clone());
+                    current = any_error.parent.as_deref();
+                }
+                None => {
+                    chain.push(reason.to_string());
+                    current = None;
+                }
+            }
+        }
+        chain
+    }
+
+    pub fn kind(&self) -> &'static str {
+        let mut root_cause: &(dyn Error + 'static) = self;
+        while let Some(any_error) = root_cause.downcast_ref::<AnyError>() {
+            match any_error.parent.as_deref() {
+                Some(reason) => root_cause = reason,
+                None => break,
+            }
+        }
+
+        if root_cause.is::<std::io::Error>() {
+            "io"
+        } else if root_cause.is::<toml::de::Error>() || root_cause.is::<toml::ser::Error>() {
+            "profile"
+        } else if root_cause.is::<bincode::Error>() {
+            "container"
+        } else if root_cause.is::<data_encoding::DecodeError>() {
+            "encoding"
+        } else if root_cause.is::<age::DecryptError>() || root_cause.is::<age::EncryptError>() {
+            "age"
+        } else {
+            "operation"
+        }
+    }
 }
 
-impl Error for AnyError {}
+impl Error for AnyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.parent {
+            Some(reason) => Some(reason.as_ref()),
+            None => None,
+        }
+    }
+}
 
 impl fmt::Display for AnyError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -82,7 +671,7 @@ impl fmt::Display for AnyError {
     }
 }
 
-pub fn error<T, U: 'static + Error>(message: &str, reason: U) -> Result<T, AnyError> {
+pub fn error<T, U: 'static + Error + Send + Sync>(message: &str, reason: U) -> Result<T, AnyError> {
     Err(AnyError::new(&message, Some(Box::new(reason))))
 }
 
@@ -97,71 +686,72 @@ pub fn init(
     profile_name: &String,
     storage_dir: &String,
     should_override: bool,
-) -> Result<(), AnyError> {
-    if !should_override {
-        if profile_exists(&profile_name) {
-            return error_without_parent("Initialization failed because profile already exists");
-        }
-    }
-
-    match create_storage_dir(&storage_dir) {
-        Ok(_) => (),
-        Err(reason) => {
-            return error(
-                "Initialization failed while creating storage for files",
-                reason,
-            )
-        }
-    };
-
-    let abs_storage_dir = expand_storage_dir(&storage_dir)?;
+) -> Result<Report, AnyError> {
+    let (pk, sk) = box_::gen_keypair();
+    setup_profile(
+        &profile_name,
+        &storage_dir,
+        Keypar::new(pk, sk),
+        should_override,
+        "init",
+    )
+}
 
-    let profile = match create_profile(&profile_name, &abs_storage_dir) {
-        Ok(obj) => obj,
-        Err(reason) => return error("Initialization failed while creating profile", reason),
+pub fn init_from_seed(
+    profile_name: &String,
+    storage_dir: &String,
+    seed: &[u8],
+    should_override: bool,
+) -> Result<Report, AnyError> {
+    let keypair = match derive_keypair(&seed) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Initialization failed while deriving key pair", reason),
     };
 
-    match create_keypair(&profile) {
-        Ok(_) => (),
-        Err(reason) => return error("Initialization failed while creating key pair", reason),
-    }
-
-    Ok(())
+    setup_profile(
+        &profile_name,
+        &storage_dir,
+        keypair,
+        should_override,
+        "init",
+    )
 }
 
 pub fn encrypt(
     profile_name: &String,
     file_path: &String,
     should_override: bool,
-) -> Result<(), AnyError> {
-    if file_path.ends_with(".cz") {
-        return error_without_parent(
-            "Encryption failed because source file was already encrypted by this program (.cz)",
-        );
-    }
-
-    if !file_exists(&file_path) {
-        return error_without_parent("Encryption failed because source file does not exists");
-    }
+) -> Result<Report, AnyError> {
+    encrypt_with_options(
+        &profile_name,
+        &file_path,
+        should_override,
+        &EncryptOptions::default(),
+    )
+}
 
+pub fn encrypt_with_options(
+    profile_name: &String,
+    file_path: &String,
+    should_override: bool,
+    options: &EncryptOptions,
+) -> Result<Report, AnyError> {
     let profile = match read_profile(&profile_name) {
         Ok(obj) => obj,
         Err(reason) => return error("Encryption failed while reading user profile", reason),
     };
 
-    let encrypted_file_path = get_encrypted_file_name(&profile, &file_path);
-    if !should_override {
-        if file_exists(&encrypted_file_path) {
-            return error_without_parent("Encryption failed because target file already exists");
-        }
-    }
+    plan_encryption(&profile, &file_path, should_override)?;
 
-    match encrypt_file(&profile, &file_path) {
-        Ok(_) => (),
+    let _task = progress::start("Encrypting", &[&file_path], true);
+    let report = match encrypt_file(&profile, &file_path, should_override, &options) {
+        Ok(report) => report,
         Err(reason) => return error("Encryption failed while doing actual encryption", reason),
     };
 
-    Ok(())
+    Ok(audit::record_done(
+        &profile, None, "encrypt", &file_path, report,
+    ))
 }
 
 pub fn decrypt(
@@ -169,35 +759,229 @@ pub fn decrypt(
     file_path: &String,
     dest_dir: &String,
     should_override: bool,
-) -> Result<(), AnyError> {
-    if !file_path.ends_with(".cz") {
-        return error_without_parent(
-            "Decryption failed because source file was not made by this program (.cz)",
-        );
-    }
-
-    if !file_exists(&file_path) {
-        return error_without_parent("Decryption failed because source file does not exists");
-    }
+) -> Result<Report, AnyError> {
+    decrypt_with_options(
+        &profile_name,
+        &file_path,
+        &dest_dir,
+        should_override,
+        &DecryptOptions::default(),
+    )
+}
 
-    let decrypted_file_path = get_decrypted_file_name(&file_path, &dest_dir);
-    if !should_override {
-        if file_exists(&decrypted_file_path) {
-            return error_without_parent("Decryption failed because target file already exists");
-        }
-    }
+pub fn decrypt_with_options(
+    profile_name: &String,
+    file_path: &String,
+    dest_dir: &String,
+    should_override: bool,
+    options: &DecryptOptions,
+) -> Result<Report, AnyError> {
+    plan_decryption(&file_path, &dest_dir, should_override)?;
 
     let profile = match read_profile(&profile_name) {
         Ok(obj) => obj,
         Err(reason) => return error("Decryption failed while reading user profile", reason),
     };
 
-    match decrypt_file(&profile, &file_path, &dest_dir) {
-        Ok(_) => (),
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Decryption failed while reading key pair", reason),
+    };
+
+    let context = Context::for_encrypted(&file_path, Some(&profile), &options.label)?;
+    let _task = progress::start("Decrypting", &[&file_path], true);
+    let report = match decrypt_file(&keypair, &file_path, &dest_dir, should_override, &context) {
+        Ok(report) => report,
         Err(reason) => return error("Decryption failed while doing actual decryption", reason),
     };
 
-    Ok(())
+    Ok(audit::record_done(
+        &profile,
+        Some(&keypair),
+        "decrypt",
+        &file_path,
+        report,
+    ))
+}
+
+pub fn encrypt_with_passphrase(
+    file_path: &String,
+    passphrase: &SecretBytes,
+    should_override: bool,
+    options: &EncryptOptions,
+) -> Result<Report, AnyError> {
+    plan_passphrase_encryption(&file_path, should_override, &options)?;
+
+    let _task = progress::start("Encrypting", &[&file_path], true);
+    match encrypt_file_with_passphrase(&file_path, &passphrase, should_override, &options) {
+        Ok(report) => Ok(report),
+        Err(reason) => error("Encryption failed while doing actual encryption", reason),
+    }
+}
+
+pub fn is_passphrase_file(file_path: &String) -> Result<bool, AnyError> {
+    let cipher = read_encrypted_file(&file_path)?;
+    Ok(cipher.is_passphrase_based())
+}
+
+pub fn decrypt_with_passphrase(
+    file_path: &String,
+    passphrase: &SecretBytes,
+    dest_dir: &String,
+    should_override: bool,
+    options: &DecryptOptions,
+) -> Result<Report, AnyError> {
+    plan_decryption(&file_path, &dest_dir, should_override)?;
+
+    let context = Context::for_encrypted(&file_path, None, &options.label)?;
+    let _task = progress::start("Decrypting", &[&file_path], true);
+    match decrypt_file_with_passphrase(
+        &file_path,
+        &passphrase,
+        &dest_dir,
+        should_override,
+        &context,
+    ) {
+        Ok(report) => Ok(report),
+        Err(reason) => error("Decryption failed while doing actual decryption", reason),
+    }
+}
+
+pub fn remove_source(profile_name: &String, file_path: &String) -> Result<Report, AnyError> {
+    if !file_exists(&file_path) {
+        return error_without_parent("Source removal failed because source file does not exists");
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Source removal failed while reading user profile", reason),
+    };
+
+    let encrypted_file_path = get_encrypted_file_name(&profile, &file_path);
+    let _task = progress::start("Verifying", &[&file_path, &encrypted_file_path], false);
+    match verify_encrypted_file(&profile, &file_path) {
+        Ok(_) => (),
+        Err(reason) => {
+            return error(
+                "Source removal failed while verifying encrypted file",
+                reason,
+            )
+        }
+    };
+
+    match shred_file(&file_path) {
+        Ok(_) => (),
+        Err(reason) => return error("Source removal failed while shredding source file", reason),
+    };
+
+    let report = Report {
+        removed: vec![file_path.to_owned()],
+        ..Report::default()
+    };
+    Ok(audit::record_done(
+        &profile,
+        None,
+        "remove-source",
+        &file_path,
+        report,
+    ))
+}
+
+pub fn configure(
+    profile_name: &String,
+    setting: &String,
+    value: &String,
+) -> Result<Report, AnyError> {
+    let mut profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Configuration failed while reading user profile", reason),
+    };
+
+    match setting.as_str() {
+        "compression" => match value.parse() {
+            Ok(compression) => profile.compression = compression,
+            Err(reason) => return error("Configuration failed because value is not valid", reason),
+        },
+        "padding" => match value.parse() {
+            Ok(padding) => profile.padding = padding,
+            Err(reason) => return error("Configuration failed because value is not valid", reason),
+        },
+        "log-signing" => match value.parse() {
+            Ok(log_signing) => profile.log_signing = log_signing,
+            Err(reason) => return error("Configuration failed because value is not valid", reason),
+        },
+        _ => return error_without_parent("Configuration failed because setting is not known"),
+    };
+
+    let profile_file_path = get_profile_file_name(&profile_name);
+    match save_profile(&profile, &profile_file_path) {
+        Ok(_) => (),
+        Err(reason) => return error("Configuration failed while saving user profile", reason),
+    };
+
+    let report = Report {
+        written: vec![profile_file_path],
+        ..Report::default()
+    };
+
+    // Recorded once saved, so turning signing on is the first signed entry
+    let subject = format!("{}={}", setting, value);
+    Ok(audit::record_done(
+        &profile,
+        None,
+        "configure",
+        &subject,
+        report,
+    ))
+}
+
+pub fn plan_init(
+    profile_name: &String,
+    storage_dir: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    plan_setup(&profile_name, &storage_dir, should_override)
+}
+
+pub fn plan_encrypt(
+    profile_name: &String,
+    file_path: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Encryption failed while reading user profile", reason),
+    };
+
+    plan_encryption(&profile, &file_path, should_override)
+}
+
+pub fn plan_encrypt_with_passphrase(
+    file_path: &String,
+    should_override: bool,
+    options: &EncryptOptions,
+) -> Result<Plan, AnyError> {
+    plan_passphrase_encryption(&file_path, should_override, &options)
+}
+
+// Where the plain file lands does not depend on the keys, so any way of decrypting shares it
+pub fn plan_decrypt(
+    file_path: &String,
+    dest_dir: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    plan_decryption(&file_path, &dest_dir, should_override)
+}
+
+pub fn plan_remove_source(file_path: &String) -> Result<Plan, AnyError> {
+    if !file_exists(&file_path) {
+        return error_without_parent("Source removal failed because source file does not exists");
+    }
+
+    Ok(Plan {
+        removed: vec![file_path.to_owned()],
+        ..Plan::default()
+    })
 }
 
 // Business functions
@@ -205,6 +989,85 @@ pub fn decrypt(
 
 // -- Profile
 
+// Action tells the log whether keys were made anew or brought back, both of which replace them
+pub(crate) fn setup_profile(
+    profile_name: &String,
+    storage_dir: &String,
+    keypair: Keypar,
+    should_override: bool,
+    action: &str,
+) -> Result<Report, AnyError> {
+    plan_setup(&profile_name, &storage_dir, should_override)?;
+
+    match create_storage_dir(&storage_dir) {
+        Ok(_) => (),
+        Err(reason) => {
+            return error(
+                "Initialization failed while creating storage for files",
+                reason,
+            )
+        }
+    };
+
+    let abs_storage_dir = expand_storage_dir(&storage_dir)?;
+
+    let profile = match create_profile(&profile_name, &abs_storage_dir) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Initialization failed while creating profile", reason),
+    };
+
+    match save_keypair(&profile, &keypair) {
+        Ok(_) => (),
+        Err(reason) => return error("Initialization failed while creating key pair", reason),
+    };
+
+    let report = Report {
+        written: vec![
+            get_profile_file_name(&profile_name),
+            get_key_file_name(&profile, Key::PublicKey),
+            get_key_file_name(&profile, Key::SecretKey),
+        ],
+        fingerprints: vec![key_fingerprint(keypair.public_key())],
+        ..Report::default()
+    };
+    Ok(audit::record_done(
+        &profile,
+        Some(&keypair),
+        action,
+        &profile_name,
+        report,
+    ))
+}
+
+pub(crate) fn plan_setup(
+    profile_name: &String,
+    storage_dir: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    if !should_override {
+        if profile_exists(&profile_name) {
+            return error_without_parent("Initialization failed because profile already exists");
+        }
+    }
+
+    let mut plan = Plan::default();
+    plan.write(get_profile_file_name(&profile_name));
+
+    // Storage is only made absolute once it exists, so a missing one is shown as given
+    let planned_storage_dir = if storage_dir_exists(&storage_dir) {
+        expand_storage_dir(&storage_dir)?
+    } else {
+        plan.created.push(storage_dir.to_owned());
+        storage_dir.to_owned()
+    };
+
+    let profile = new_profile(&profile_name, &planned_storage_dir);
+    plan.write(get_key_file_name(&profile, Key::PublicKey));
+    plan.write(get_key_file_name(&profile, Key::SecretKey));
+
+    Ok(plan)
+}
+
 pub fn profile_exists(profile_name: &String) -> bool {
     profile_file_exists(profile_name)
 }
@@ -223,11 +1086,18 @@ fn read_profile(profile_name: &String) -> Result<Profile, AnyError> {
     }
 }
 
-fn create_profile(profile_name: &String, storage_dir: &String) -> Result<Profile, AnyError> {
-    let profile = Profile {
+fn new_profile(profile_name: &String, storage_dir: &String) -> Profile {
+    Profile {
         name: profile_name.to_owned(),
         storage: storage_dir.to_owned(),
-    };
+        compression: Compression::default(),
+        padding: Padding::default(),
+        log_signing: audit::LogSigning::default(),
+    }
+}
+
+fn create_profile(profile_name: &String, storage_dir: &String) -> Result<Profile, AnyError> {
+    let profile = new_profile(&profile_name, &storage_dir);
 
     let profile_file_path = get_profile_file_name(&profile_name);
     match save_profile(&profile, &profile_file_path) {
@@ -263,245 +1133,935 @@ fn get_profile_file_name(profile_name: &String) -> String {
     }
 }
 
-fn profile_file_exists(profile_name: &String) -> bool {
-    let file_path = get_profile_file_name(&profile_name);
-    let file = Path::new(file_path.as_str());
-    file.is_file()
+fn profile_file_exists(profile_name: &String) -> bool {
+    let file_path = get_profile_file_name(&profile_name);
+    let file = Path::new(file_path.as_str());
+    file.is_file()
+}
+
+// -- Storage
+
+fn storage_dir_exists(storage_dir: &String) -> bool {
+    let path = Path::new(storage_dir);
+    path.is_dir()
+}
+
+fn create_storage_dir(storage_dir: &String) -> Result<(), AnyError> {
+    if storage_dir_exists(storage_dir) {
+        return Ok(());
+    }
+
+    let path = Path::new(storage_dir);
+    match fs::create_dir_all(path) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not create storage directory", reason),
+    }
+}
+
+// Only local storage is there for now, yet everything past this point works with any
+pub(crate) fn open_storage(profile: &Profile) -> Box<dyn storage::Storage> {
+    Box::new(storage::LocalStorage::new(&profile.storage))
+}
+
+fn expand_storage_dir(storage_dir: &String) -> Result<String, AnyError> {
+    let path_buf = PathBuf::from(storage_dir);
+    match path_buf.canonicalize() {
+        Ok(abs_path) => {
+            let path = format!("{}", abs_path.display());
+            Ok(path)
+        }
+        Err(reason) => error("Could not expand storage directory", reason),
+    }
+}
+
+// -- Key pair
+
+pub fn keypair_exists(profile: &Profile) -> bool {
+    let storage = open_storage(&profile);
+    if !storage.exists(&get_key_name(&profile.name, Key::PublicKey)) {
+        return false;
+    }
+    if !storage.exists(&get_key_name(&profile.name, Key::SecretKey)) {
+        return false;
+    }
+    true
+}
+
+fn read_keypair(profile: &Profile) -> Result<Keypar, AnyError> {
+    let pk = read_public_key(&profile)?;
+
+    // An agent already holding this profile spares us from reading the secret key at all
+    if let Some(agent_key) = agent::find_agent_key(&profile.name, &pk) {
+        return Ok(Keypar::from_agent(pk, agent_key));
+    }
+
+    read_local_keypair(&profile)
+}
+
+fn read_local_keypair(profile: &Profile) -> Result<Keypar, AnyError> {
+    load_keypair(open_storage(&profile).as_ref(), &profile.name)
+}
+
+fn read_public_key(profile: &Profile) -> Result<PublicKey, AnyError> {
+    load_public_key(open_storage(&profile).as_ref(), &profile.name)
+}
+
+fn load_keypair(storage: &dyn storage::Storage, profile_name: &String) -> Result<Keypar, AnyError> {
+    let pk = load_public_key(storage, &profile_name)?;
+
+    let sk_raw = match read_key(storage, &get_key_name(&profile_name, Key::SecretKey)) {
+        Ok(raw) => raw,
+        Err(reason) => return error("Could not read secret key", reason),
+    };
+
+    match Keypar::from_secret_slice(pk, sk_raw.as_ref()) {
+        Some(keypair) => Ok(keypair),
+        None => error_without_parent("Could not decode secret key"),
+    }
+}
+
+fn load_public_key(
+    storage: &dyn storage::Storage,
+    profile_name: &String,
+) -> Result<PublicKey, AnyError> {
+    let pk = match read_key(storage, &get_key_name(&profile_name, Key::PublicKey)) {
+        Ok(raw) => match PublicKey::from_slice(raw.as_ref()) {
+            Some(pk_obj) => pk_obj,
+            None => return error_without_parent("Could not decode public key"),
+        },
+        Err(reason) => return error("Could not read public key", reason),
+    };
+
+    Ok(pk)
+}
+
+fn read_key(storage: &dyn storage::Storage, key_name: &String) -> Result<SecretBytes, AnyError> {
+    match storage.get(&key_name) {
+        Ok(raw_base64) => {
+            let raw_base64 = SecretBytes::new(raw_base64);
+            match BASE64.decode(raw_base64.as_ref()) {
+                Ok(raw_vec) => Ok(SecretBytes::new(raw_vec)),
+                Err(reason) => error("Could not decode key file", reason),
+            }
+        }
+        Err(reason) => error("Could not read key file", reason),
+    }
+}
+
+// Each kind of key gets its own context, so a signing key can come from the same seed later on
+fn derive_keypair(seed: &[u8]) -> Result<Keypar, AnyError> {
+    let box_seed = match keyed_hash(box_::SEEDBYTES, seed, &[b"moy-sekret box key"]) {
+        Ok(digest) => box_::Seed::from_slice(digest.as_ref()).unwrap(),
+        Err(_) => return error_without_parent("Could not derive key seed"),
+    };
+
+    let (pk, sk) = box_::keypair_from_seed(&box_seed);
+    Ok(Keypar::new(pk, sk))
+}
+
+fn save_keypair(profile: &Profile, keypair: &Keypar) -> Result<(), AnyError> {
+    store_keypair(open_storage(&profile).as_ref(), &profile.name, &keypair)
+}
+
+fn store_keypair(
+    storage: &dyn storage::Storage,
+    profile_name: &String,
+    keypair: &Keypar,
+) -> Result<(), AnyError> {
+    let sk = match keypair.secret_key() {
+        Some(sk) => sk,
+        None => return error_without_parent("Could not save key pair held by agent"),
+    };
+
+    let pk_name = get_key_name(&profile_name, Key::PublicKey);
+    match save_key(storage, keypair.public_key().as_ref(), &pk_name) {
+        Ok(_) => (),
+        Err(reason) => return error("Could not save public key file", reason),
+    };
+
+    let sk_name = get_key_name(&profile_name, Key::SecretKey);
+    match save_key(storage, sk.as_ref(), &sk_name) {
+        Ok(_) => (),
+        Err(reason) => return error("Could not save secret key file", reason),
+    };
+
+    Ok(())
+}
+
+fn save_key(storage: &dyn storage::Storage, key: &[u8], key_name: &String) -> Result<(), AnyError> {
+    let key_file_base64 = SecretBytes::new(BASE64.encode(key).into_bytes());
+
+    match storage.put(&key_name, &key_file_base64) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not write key file", reason),
+    }
+}
+
+fn get_key_name(profile_name: &String, key: Key) -> String {
+    format!("{}.{}", profile_name, key)
+}
+
+fn get_key_file_name(profile: &Profile, key: Key) -> String {
+    open_storage(&profile).locate(&get_key_name(&profile.name, key))
+}
+
+pub fn key_fingerprint(pk: &PublicKey) -> String {
+    let digest = sha256::hash(pk.as_ref());
+    format!("SHA256:{}", BASE64_NOPAD.encode(digest.as_ref()))
+}
+
+// -- Encryption
+
+fn plan_encryption(
+    profile: &Profile,
+    file_path: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    if file_path.ends_with(".cz") {
+        return error_without_parent(
+            "Encryption failed because source file was already encrypted by this program (.cz)",
+        );
+    }
+
+    if !file_exists(&file_path) {
+        return error_without_parent("Encryption failed because source file does not exists");
+    }
+
+    let storage = open_storage(&profile);
+    let encrypted_name = get_encrypted_name(&file_path);
+    if !should_override {
+        if storage.exists(&encrypted_name) {
+            return error_without_parent("Encryption failed because target file already exists");
+        }
+    }
+
+    let mut plan = Plan::default();
+    if storage.exists(&encrypted_name) {
+        plan.overwritten.push(storage.locate(&encrypted_name));
+    } else {
+        plan.created.push(storage.locate(&encrypted_name));
+    }
+    Ok(plan)
+}
+
+fn plan_passphrase_encryption(
+    file_path: &String,
+    should_override: bool,
+    options: &EncryptOptions,
+) -> Result<Plan, AnyError> {
+    if file_path.ends_with(".cz") {
+        return error_without_parent(
+            "Encryption failed because source file was already encrypted by this program (.cz)",
+        );
+    }
+
+    if !file_exists(&file_path) {
+        return error_without_parent("Encryption failed because source file does not exists");
+    }
+
+    if !options.recipients.is_empty() {
+        return error_without_parent(
+            "Encryption failed because a passphrase cannot be used along with recipients",
+        );
+    }
+
+    let encrypted_file_path = get_passphrase_file_name(&file_path);
+    if !should_override {
+        if file_exists(&encrypted_file_path) {
+            return error_without_parent("Encryption failed because target file already exists");
+        }
+    }
+
+    let mut plan = Plan::default();
+    plan.write(encrypted_file_path);
+    Ok(plan)
+}
+
+fn encrypt_file(
+    profile: &Profile,
+    file_path: &String,
+    should_override: bool,
+    options: &EncryptOptions,
+) -> Result<Report, AnyError> {
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Could not encrypt file", reason),
+    };
+
+    let recipients = match ssh::parse_recipients(&options.recipients) {
+        Ok(recipients) => recipients,
+        Err(reason) => return error("Could not read recipients", reason),
+    };
+
+    let storage = open_storage(&profile);
+    encrypt_file_with_keys(
+        &profile,
+        storage.as_ref(),
+        &keypair,
+        &recipients,
+        &file_path,
+        should_override,
+        &options,
+    )
+}
+
+// Keys and recipients are given ready, so many files can be encrypted with a single read of them
+fn encrypt_file_with_keys(
+    profile: &Profile,
+    storage: &dyn storage::Storage,
+    keypair: &Keypar,
+    recipients: &[PublicKey],
+    file_path: &String,
+    should_override: bool,
+    options: &EncryptOptions,
+) -> Result<Report, AnyError> {
+    let plain_content = match progress::read_file(&file_path) {
+        Ok(content) => content,
+        Err(reason) => return error("Could not read file to encrypt", reason),
+    };
+
+    let header = Header {
+        recipients: recipients.to_vec(),
+        context: Some(Context::for_source(
+            &file_path,
+            Some(&profile),
+            &options.label,
+        )),
+        ..Header::for_profile(&profile, &options)
+    };
+    let cipher = seal_content(&keypair, &header, &plain_content, box_::gen_nonce())?;
+    let cipher_name = get_encrypted_name(&file_path);
+
+    // Checked while planning, yet another writer may have taken the name since
+    let cipher_data = encode_cipher_file(&cipher, options.armor)?;
+    let put_result = if should_override {
+        storage.put(&cipher_name, &cipher_data)
+    } else {
+        storage.put_new(&cipher_name, &cipher_data)
+    };
+    match put_result {
+        Ok(_) => progress::advance_by_file(&file_path),
+        Err(reason) => return error("Could not save encrypted file", reason),
+    };
+
+    Ok(Report {
+        written: vec![storage.locate(&cipher_name)],
+        fingerprints: vec![key_fingerprint(keypair.public_key())],
+        ..Report::default()
+    })
+}
+
+fn save_encrypted_file(
+    cipher: &Cipher,
+    output_file_path: &String,
+    should_armor: bool,
+    should_override: bool,
+) -> Result<(), AnyError> {
+    let cipher_file_path = Path::new(output_file_path);
+
+    // Checked again right here, as a file may have shown up since the plan was made
+    let mut cipher_file = match create_file_no_follow(&cipher_file_path, should_override) {
+        Ok(file) => file,
+        Err(reason) => return error("Could not create encrypted file", reason),
+    };
+
+    let cipher_data = encode_cipher_file(&cipher, should_armor)?;
+    match cipher_file.write_all(&cipher_data) {
+        Ok(_) => (),
+        Err(reason) => return error("Could not write to encrypted file", reason),
+    };
+
+    Ok(())
+}
+
+fn encode_cipher_file(cipher: &Cipher, should_armor: bool) -> Result<Vec<u8>, AnyError> {
+    let cipher_data = serialize_cipher(&cipher)?;
+    if should_armor {
+        let header_lines = vec![format!("Version: {}", cipher.version)];
+        return Ok(armor::encode(&cipher_data, &header_lines).into_bytes());
+    }
+
+    Ok(cipher_data)
+}
+
+fn encrypt_file_with_passphrase(
+    file_path: &String,
+    passphrase: &SecretBytes,
+    should_override: bool,
+    options: &EncryptOptions,
+) -> Result<Report, AnyError> {
+    let plain_content = match progress::read_file(&file_path) {
+        Ok(content) => content,
+        Err(reason) => return error("Could not read file to encrypt", reason),
+    };
+
+    let header = Header {
+        context: Some(Context::for_source(&file_path, None, &options.label)),
+        ..Header::for_passphrase(&options)
+    };
+    let cipher = seal_with_passphrase(&passphrase, &header, &plain_content, box_::gen_nonce())?;
+    let cipher_file_path = get_passphrase_file_name(&file_path);
+
+    match save_encrypted_file(&cipher, &cipher_file_path, options.armor, should_override) {
+        Ok(_) => progress::advance_by_file(&file_path),
+        Err(reason) => return error("Could not save encrypted file", reason),
+    };
+
+    Ok(Report {
+        written: vec![cipher_file_path],
+        ..Report::default()
+    })
+}
+
+fn seal_content(
+    keypair: &Keypar,
+    header: &Header,
+    plain_content: &[u8],
+    nonce: Nonce,
+) -> Result<Cipher, AnyError> {
+    let payload_data = pack_payload(&header, &plain_content)?;
+
+    let (key_slots, cipher_data) = if header.recipients.is_empty() {
+        (vec![], keypair.seal(&payload_data, &nonce)?)
+    } else {
+        seal_for_recipients(&keypair, &header.recipients, &payload_data, &nonce)?
+    };
+
+    Ok(Cipher {
+        version: CONTAINER_VERSION,
+        header: Some(header.clone()),
+        key_slots,
+        nonce,
+        data: cipher_data,
+    })
+}
+
+fn seal_with_passphrase(
+    passphrase: &[u8],
+    header: &Header,
+    plain_content: &[u8],
+    nonce: Nonce,
+) -> Result<Cipher, AnyError> {
+    let key_derivation = match &header.key_derivation {
+        Some(key_derivation) => key_derivation,
+        None => return error_without_parent("Could not seal content without key derivation"),
+    };
+    let key = derive_passphrase_key(&passphrase, &key_derivation)?;
+
+    let payload_data = pack_payload(&header, &plain_content)?;
+    let secretbox_nonce = secretbox::Nonce::from_slice(nonce.as_ref()).unwrap();
+
+    Ok(Cipher {
+        version: CONTAINER_VERSION,
+        header: Some(header.clone()),
+        key_slots: vec![],
+        nonce,
+        data: secretbox::seal(&payload_data, &secretbox_nonce, &key),
+    })
+}
+
+// Every buffer is sized up front, so none of them leaves plain copies behind as it grows
+fn pack_payload(header: &Header, plain_content: &[u8]) -> Result<SecretBytes, AnyError> {
+    let content = match header.compression {
+        Compression::None => SecretBytes::new(plain_content.to_vec()),
+        Compression::Zstd => compress_content(&plain_content)?,
+    };
+
+    let payload = Payload {
+        header: header.clone(),
+        content,
+    };
+    let payload_len = match bincode::serialized_size(&payload) {
+        Ok(len) => len as usize,
+        Err(reason) => return error("Could not serialize content to encrypt", reason),
+    };
+
+    // Trailing zeros are left alone by deserialization, as the content carries its own length
+    let padded_len = match header.padding {
+        Padding::None => payload_len,
+        Padding::Padme => get_padme_len(payload_len),
+    };
+    let mut payload_data = SecretBytes::new(Vec::with_capacity(padded_len));
+    match bincode::serialize_into(&mut payload_data.bytes, &payload) {
+        Ok(_) => (),
+        Err(reason) => return error("Could not serialize content to encrypt", reason),
+    };
+    payload_data.bytes.resize(padded_len, 0);
+    Ok(payload_data)
+}
+
+fn compress_content(plain_content: &[u8]) -> Result<SecretBytes, AnyError> {
+    let bound = zstd::zstd_safe::compress_bound(plain_content.len());
+    let mut content = SecretBytes::new(vec![0; bound]);
+    match zstd::bulk::compress_to_buffer(plain_content, content.as_mut(), 0) {
+        Ok(content_len) => content.truncate(content_len),
+        Err(reason) => return error("Could not compress content", reason),
+    };
+    Ok(content)
 }
 
-// -- Storage
+// Frames made in one go tell their size, older streamed ones are read as they come
+fn decompress_content(content: &[u8]) -> Result<SecretBytes, AnyError> {
+    let content_len = match zstd::zstd_safe::get_frame_content_size(content) {
+        Ok(Some(len)) => len as usize,
+        _ => {
+            let mut decoder = match zstd::Decoder::new(content) {
+                Ok(decoder) => decoder,
+                Err(reason) => return error("Could not decompress content", reason),
+            };
+            return match SecretBytes::read_from(&mut decoder, content.len() * 2) {
+                Ok(plain_content) => Ok(plain_content),
+                Err(reason) => error("Could not decompress content", reason),
+            };
+        }
+    };
 
-fn storage_dir_exists(storage_dir: &String) -> bool {
-    let path = Path::new(storage_dir);
-    path.is_dir()
+    let mut plain_content = SecretBytes::new(vec![0; content_len]);
+    match zstd::bulk::decompress_to_buffer(content, plain_content.as_mut()) {
+        Ok(plain_len) => plain_content.truncate(plain_len),
+        Err(reason) => return error("Could not decompress content", reason),
+    };
+    Ok(plain_content)
 }
 
-fn create_storage_dir(storage_dir: &String) -> Result<(), AnyError> {
-    if storage_dir_exists(storage_dir) {
-        return Ok(());
-    }
+fn seal_for_recipients(
+    keypair: &Keypar,
+    recipients: &[PublicKey],
+    payload_data: &[u8],
+    nonce: &Nonce,
+) -> Result<(Vec<KeySlot>, Vec<u8>), AnyError> {
+    let file_key = secretbox::gen_key();
+
+    // Both nonces are 24 bytes long, so the container one serves the secret box as well
+    let secretbox_nonce = secretbox::Nonce::from_slice(nonce.as_ref()).unwrap();
+    let cipher_data = secretbox::seal(payload_data, &secretbox_nonce, &file_key);
+
+    let mut key_slots: Vec<KeySlot> = vec![];
+    for recipient in iter::once(keypair.public_key()).chain(recipients.iter()) {
+        if key_slots.iter().any(|slot| slot.recipient == *recipient) {
+            continue;
+        }
 
-    let path = Path::new(storage_dir);
-    match fs::create_dir_all(path) {
-        Ok(_) => Ok(()),
-        Err(reason) => error("Could not create storage directory", reason),
+        let (ephemeral_pk, ephemeral_sk) = box_::gen_keypair();
+        let slot_nonce = box_::gen_nonce();
+        let mut key_slot = KeySlot {
+            recipient: *recipient,
+            ephemeral_pk,
+            nonce: slot_nonce,
+            wrapped_key: box_::seal(&file_key.0, &slot_nonce, recipient, &ephemeral_sk),
+            sender: *keypair.public_key(),
+            sender_tag: vec![],
+        };
+        key_slot.sender_tag = keypair.sender_tag(
+            recipient,
+            &get_tagged_message(&key_slot, nonce, &cipher_data),
+        )?;
+        key_slots.push(key_slot);
     }
+
+    Ok((key_slots, cipher_data))
 }
 
-fn expand_storage_dir(storage_dir: &String) -> Result<String, AnyError> {
-    let path_buf = PathBuf::from(storage_dir);
-    match path_buf.canonicalize() {
-        Ok(abs_path) => {
-            let path = format!("{}", abs_path.display());
-            Ok(path)
-        }
-        Err(reason) => error("Could not expand storage directory", reason),
-    }
+// Anyone could wrap a file key for a public key, so only the sender and the recipient
+// can tell apart a file made by the sender from one made to look like it; the content is
+// hashed first, so a tag never needs more than that to be made
+fn get_tagged_message(key_slot: &KeySlot, nonce: &Nonce, cipher_data: &[u8]) -> Vec<u8> {
+    let mut message = vec![];
+    message.extend_from_slice(key_slot.ephemeral_pk.as_ref());
+    message.extend_from_slice(key_slot.nonce.as_ref());
+    message.extend_from_slice(&(key_slot.wrapped_key.len() as u64).to_le_bytes());
+    message.extend_from_slice(&key_slot.wrapped_key);
+    message.extend_from_slice(nonce.as_ref());
+    message.extend_from_slice(sha256::hash(cipher_data).as_ref());
+    message
 }
 
-// -- Key pair
+fn serialize_cipher(cipher: &Cipher) -> Result<Vec<u8>, AnyError> {
+    let header = match &cipher.header {
+        Some(header) => header,
+        None => return error_without_parent("Could not serialize encrypted data without header"),
+    };
 
-pub fn keypair_exists(profile: &Profile) -> bool {
-    if !key_file_exists(&profile, Key::PublicKey) {
-        return false;
-    }
-    if !key_file_exists(&profile, Key::SecretKey) {
-        return false;
+    let mut cipher_content = CONTAINER_MAGIC.to_vec();
+    cipher_content.push(CONTAINER_VERSION);
+    let container = (header, &cipher.key_slots, &cipher.nonce, &cipher.data);
+    match bincode::serialize_into(&mut cipher_content, &container) {
+        Ok(_) => Ok(cipher_content),
+        Err(reason) => error("Could not serialize encrypted data", reason),
     }
-    true
 }
 
-fn read_keypair(profile: &Profile) -> Result<Keypar, AnyError> {
-    let pk_file_path = get_key_file_name(&profile, Key::PublicKey);
-    let pk = match read_key(&pk_file_path) {
-        Ok(raw) => match PublicKey::from_slice(raw.as_ref()) {
-            Some(pk_obj) => pk_obj,
-            None => return error_without_parent("Could not decode public key"),
-        },
-        Err(reason) => return error("Could not read public key", reason),
-    };
+fn get_encrypted_name(file_name: &String) -> String {
+    let path = Path::new(file_name);
+    let name = path.file_name().unwrap();
+    format!("{}.cz", name.to_str().unwrap())
+}
 
-    let sk_file_path = get_key_file_name(&profile, Key::SecretKey);
-    let sk = match read_key(&sk_file_path) {
-        Ok(raw) => match SecretKey::from_slice(raw.as_ref()) {
-            Some(sk_obj) => sk_obj,
-            None => return error_without_parent("Could not decode secret key"),
-        },
-        Err(reason) => return error("Could not read public key", reason),
-    };
-    Ok((pk, sk))
+fn get_encrypted_file_name(profile: &Profile, file_name: &String) -> String {
+    open_storage(&profile).locate(&get_encrypted_name(&file_name))
 }
 
-fn read_key(input_file_path: &String) -> Result<Vec<u8>, AnyError> {
-    let key_file_path = Path::new(input_file_path.as_str());
-    match fs::read_to_string(key_file_path) {
-        Ok(raw_base64) => match BASE64.decode(raw_base64.as_bytes()) {
-            Ok(raw_vec) => Ok(raw_vec),
-            Err(reason) => error("Could not decode key file", reason),
-        },
-        Err(reason) => error("Could not read key file", reason),
-    }
+// Made without a profile, so it stays right next to its source file
+fn get_passphrase_file_name(file_name: &String) -> String {
+    format!("{}.cz", file_name)
 }
 
-fn create_keypair(profile: &Profile) -> Result<Keypar, AnyError> {
-    let (pk, sk) = box_::gen_keypair();
+fn verify_encrypted_file(profile: &Profile, file_path: &String) -> Result<(), AnyError> {
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Could not verify encrypted file", reason),
+    };
 
-    let pk_file_path = get_key_file_name(&profile, Key::PublicKey);
-    match save_key(pk.as_ref(), &pk_file_path) {
-        Ok(_) => (),
-        Err(reason) => return error("Could not save public key file", reason),
+    let plain_content = match progress::read_file(&file_path) {
+        Ok(content) => content,
+        Err(reason) => return error("Could not read source file", reason),
     };
 
-    let sk_file_path = get_key_file_name(&profile, Key::SecretKey);
-    match save_key(sk.as_ref(), &sk_file_path) {
-        Ok(_) => (),
-        Err(reason) => return error("Could not save secret key file", reason),
+    let storage = open_storage(&profile);
+    let cipher_name = get_encrypted_name(&file_path);
+    let cipher_data = match storage.get(&cipher_name) {
+        Ok(raw_vec) => raw_vec,
+        Err(reason) => return error("Could not read encrypted file", reason),
     };
+    progress::advance_by_file(&storage.locate(&cipher_name));
+    let cipher = decode_cipher_file(cipher_data)?;
+    let decrypted_content = open_cipher(&keypair, &cipher)?;
+
+    if !memcmp(&plain_content, &decrypted_content) {
+        return error_without_parent("Encrypted file does not match source file");
+    }
 
-    Ok((pk, sk))
+    Ok(())
 }
 
-fn save_key(key: &[u8], output_file_path: &String) -> Result<(), AnyError> {
-    let key_file_path = Path::new(output_file_path.as_str());
+// -- Decryption
 
-    let mut key_file = match File::create(key_file_path) {
-        Ok(file) => file,
-        Err(reason) => return error("Could not create key file", reason),
-    };
+// -- Encryption
+
+fn plan_decryption(
+    file_path: &String,
+    dest_dir: &String,
+    should_override: bool,
+) -> Result<Plan, AnyError> {
+    if !file_path.ends_with(".cz") {
+        return error_without_parent(
+            "Decryption failed because source file was not made by this program (.cz)",
+        );
+    }
 
-    let key_file_base64 = BASE64.encode(key);
+    if !file_exists(&file_path) {
+        return error_without_parent("Decryption failed because source file does not exists");
+    }
 
-    match key_file.write_all(key_file_base64.as_bytes()) {
-        Ok(_) => (),
-        Err(reason) => return error("Could not write key file", reason),
+    let decrypted_file_path = match get_decrypted_file_name(&file_path, &dest_dir) {
+        Ok(path) => path,
+        Err(reason) => return error("Decryption failed because target file is not safe", reason),
     };
+    if !should_override {
+        if file_exists(&decrypted_file_path) {
+            return error_without_parent("Decryption failed because target file already exists");
+        }
+    }
 
-    Ok(())
+    let mut plan = Plan::default();
+    plan.write(decrypted_file_path);
+    Ok(plan)
 }
 
-fn get_key_file_name(profile: &Profile, key: Key) -> String {
-    format!("{}/{}.{}", profile.storage, profile.name, key)
-}
+fn decrypt_file(
+    keypair: &Keypar,
+    file_path: &String,
+    dest_dir: &String,
+    should_override: bool,
+    context: &Context,
+) -> Result<Report, AnyError> {
+    let cipher = read_encrypted_file(&file_path)?;
+    let plain_data = open_cipher(&keypair, &cipher)?;
+    check_context(&cipher, &context, Some(keypair.public_key()))?;
+
+    let plain_file_path = save_plain_file(&plain_data, &file_path, &dest_dir, should_override)?;
+
+    // Files shared by someone else tell who that was
+    let mut fingerprints = vec![key_fingerprint(keypair.public_key())];
+    if let Some(sender) = cipher
+        .sender()
+        .filter(|sender| *sender != keypair.public_key())
+    {
+        fingerprints.push(key_fingerprint(sender));
+    }
 
-fn key_file_exists(profile: &Profile, key: Key) -> bool {
-    let file_path = get_key_file_name(&profile, key);
-    let file = Path::new(file_path.as_str());
-    file.is_file()
+    Ok(Report {
+        written: vec![plain_file_path],
+        fingerprints,
+        ..Report::default()
+    })
 }
 
-// -- Encryption
+fn decrypt_file_with_passphrase(
+    file_path: &String,
+    passphrase: &SecretBytes,
+    dest_dir: &String,
+    should_override: bool,
+    context: &Context,
+) -> Result<Report, AnyError> {
+    let cipher = read_encrypted_file(&file_path)?;
+    let plain_data = open_with_passphrase(&passphrase, &cipher)?;
+    check_context(&cipher, &context, None)?;
+
+    let plain_file_path = save_plain_file(&plain_data, &file_path, &dest_dir, should_override)?;
+
+    Ok(Report {
+        written: vec![plain_file_path],
+        ..Report::default()
+    })
+}
 
-fn encrypt_file(profile: &Profile, file_path: &String) -> Result<(), AnyError> {
-    let (pk, sk) = match read_keypair(&profile) {
-        Ok(keypair) => keypair,
-        Err(reason) => return error("Could not encrypt file", reason),
+// Only called once the cipher was opened, as the header cannot be trusted before that,
+// while files made before contexts were bound have none to check
+fn check_context(
+    cipher: &Cipher,
+    expected: &Context,
+    opener: Option<&PublicKey>,
+) -> Result<(), AnyError> {
+    let header = match &cipher.header {
+        Some(header) => header,
+        None => return Ok(()),
     };
-
-    let plain_content = match fs::read(file_path) {
-        Ok(raw_vec) => raw_vec,
-        Err(reason) => return error("Could not read file to encrypt", reason),
+    let context = match &header.context {
+        Some(context) => context,
+        None => return Ok(()),
     };
 
-    let nonce = box_::gen_nonce();
-    let cipher_data = box_::seal(plain_content.as_ref(), &nonce, &pk, &sk);
+    if context.file_name != expected.file_name {
+        return error_without_parent(&format!(
+            "Encrypted file was made for {} and cannot stand in for {}",
+            context.file_name, expected.file_name
+        ));
+    }
 
-    let cipher_file_path = get_encrypted_file_name(&profile, &file_path);
-    let cipher = Cipher {
-        nonce: nonce,
-        data: cipher_data,
-    };
+    // Whoever the file was shared with opens it under a profile of their own
+    let is_shared_with_opener = opener.is_some_and(|pk| header.recipients.contains(pk));
+    if let (Some(profile_name), Some(expected_profile_name)) = (&context.profile, &expected.profile)
+    {
+        if profile_name != expected_profile_name && !is_shared_with_opener {
+            return error_without_parent(&format!(
+                "Encrypted file was made by profile {} and cannot be opened as {}",
+                profile_name, expected_profile_name
+            ));
+        }
+    }
 
-    match save_encrypted_file(&cipher, &cipher_file_path) {
-        Ok(_) => (),
-        Err(reason) => return error("Could not save encrypted file", reason),
+    // A bound label has to be given back, so a file cannot quietly pass for another one
+    match (&context.label, &expected.label) {
+        (Some(label), None) => error_without_parent(&format!(
+            "Encrypted file was bound to label {}, which has to be given",
+            label
+        )),
+        (label, expected_label) if label != expected_label => {
+            error_without_parent("Encrypted file was bound to another label")
+        }
+        _ => Ok(()),
+    }
+}
+
+fn save_plain_file(
+    plain_data: &[u8],
+    file_path: &String,
+    dest_dir: &String,
+    should_override: bool,
+) -> Result<String, AnyError> {
+    let plain_file_name = get_plain_file_name(&file_path)?;
+    match save_decrypted_file(&plain_data, &dest_dir, &plain_file_name, should_override) {
+        Ok(path) => {
+            progress::advance_by_file(&file_path);
+            Ok(format!("{}", path.display()))
+        }
+        Err(reason) => error("Could not save decrypted file", reason),
+    }
+}
+
+fn read_encrypted_file(file_path: &String) -> Result<Cipher, AnyError> {
+    let cipher_content = match progress::read_file(&file_path) {
+        Ok(content) => content.to_vec(),
+        Err(reason) => return error("Could not read file to decrypt", reason),
     };
 
-    Ok(())
+    decode_cipher_file(cipher_content)
 }
 
-fn save_encrypted_file(cipher: &Cipher, output_file_path: &String) -> Result<(), AnyError> {
-    let cipher_file_path = Path::new(output_file_path);
+fn decode_cipher_file(mut cipher_content: Vec<u8>) -> Result<Cipher, AnyError> {
+    if armor::is_armored(&cipher_content) {
+        cipher_content = armor::decode(&cipher_content)?;
+    }
 
-    let mut cipher_file = match File::create(cipher_file_path) {
-        Ok(file) => file,
-        Err(reason) => return error("Could not create encrypted file", reason),
-    };
+    deserialize_cipher(&cipher_content)
+}
 
-    let cipher_data = match bincode::serialize(cipher) {
-        Ok(data) => data,
-        Err(reason) => return error("Could not serialize encrypted data", reason),
-    };
+fn is_armored_file(file_path: &String) -> bool {
+    match fs::read(file_path) {
+        Ok(content) => armor::is_armored(&content),
+        Err(_) => false,
+    }
+}
 
-    match cipher_file.write_all(&cipher_data) {
-        Ok(_) => (),
-        Err(reason) => return error("Could not write to encrypted file", reason),
+fn deserialize_cipher(cipher_content: &[u8]) -> Result<Cipher, AnyError> {
+    if !cipher_content.starts_with(CONTAINER_MAGIC) {
+        return deserialize_legacy_cipher(&cipher_content);
+    }
+
+    let versioned_content = &cipher_content[CONTAINER_MAGIC.len()..];
+    let (version, content) = match versioned_content.split_first() {
+        Some((version, content)) => (*version, content),
+        None => return error_without_parent("Encrypted data is truncated"),
     };
 
-    Ok(())
-}
+    if version != CONTAINER_VERSION {
+        return error_without_parent("Encrypted data was made by an unsupported version");
+    }
 
-fn get_encrypted_file_name(profile: &Profile, file_name: &String) -> String {
-    let path = Path::new(file_name);
-    let name = path.file_name().unwrap();
-    format!("{}/{}.cz", profile.storage, name.to_str().unwrap())
+    match bincode::deserialize(content) {
+        Ok((header, key_slots, nonce, data)) => Ok(Cipher {
+            version,
+            header: Some(header),
+            key_slots,
+            nonce,
+            data,
+        }),
+        Err(reason) => error("Could not deserialize encrypted data", reason),
+    }
 }
 
-// -- Decryption
+fn deserialize_legacy_cipher(cipher_content: &[u8]) -> Result<Cipher, AnyError> {
+    match bincode::deserialize(cipher_content) {
+        Ok((nonce, data)) => Ok(Cipher {
+            version: 1,
+            header: None,
+            key_slots: vec![],
+            nonce,
+            data,
+        }),
+        Err(reason) => error("Could not deserialize encrypted data", reason),
+    }
+}
 
-// -- Encryption
+fn open_cipher(keypair: &Keypar, cipher: &Cipher) -> Result<SecretBytes, AnyError> {
+    if cipher.is_passphrase_based() {
+        return error_without_parent("File is protected by a passphrase, not by a key pair");
+    }
 
-fn decrypt_file(profile: &Profile, file_path: &String, dest_dir: &String) -> Result<(), AnyError> {
-    let (pk, sk) = match read_keypair(&profile) {
-        Ok(keypair) => keypair,
-        Err(reason) => return error("Could not encrypt file", reason),
+    let payload_data = if cipher.key_slots.is_empty() {
+        keypair.open(cipher.data.as_ref(), &cipher.nonce)?
+    } else {
+        open_for_recipient(&keypair, &cipher)?
     };
+    unpack_payload(&cipher, payload_data)
+}
 
-    let cipher_content = match fs::read(file_path) {
-        Ok(raw_vec) => raw_vec,
-        Err(reason) => return error("Could not read file to decrypt", reason),
+fn open_with_passphrase(passphrase: &[u8], cipher: &Cipher) -> Result<SecretBytes, AnyError> {
+    let key_derivation = match cipher
+        .header
+        .as_ref()
+        .and_then(|h| h.key_derivation.as_ref())
+    {
+        Some(key_derivation) => key_derivation,
+        None => return error_without_parent("File is not protected by a passphrase"),
     };
+    let key = derive_passphrase_key(&passphrase, &key_derivation)?;
 
-    let cipher: Cipher = match bincode::deserialize(&cipher_content) {
-        Ok(data) => data,
-        Err(reason) => return error("Could not deserialize encrypted data", reason),
+    let secretbox_nonce = secretbox::Nonce::from_slice(cipher.nonce.as_ref()).unwrap();
+    let payload_data = match secretbox::open(&cipher.data, &secretbox_nonce, &key) {
+        Ok(data) => SecretBytes::new(data),
+        Err(_) => return error_without_parent("Could not decrypt file, is the passphrase right?"),
     };
+    unpack_payload(&cipher, payload_data)
+}
 
-    let plain_data = match box_::open(cipher.data.as_ref(), &cipher.nonce, &pk, &sk) {
-        Ok(data) => data,
-        Err(_) => return error_without_parent("Could not decrypt file"),
+fn unpack_payload(cipher: &Cipher, payload_data: SecretBytes) -> Result<SecretBytes, AnyError> {
+    let header = match &cipher.header {
+        Some(header) => header,
+        None => return Ok(payload_data),
     };
 
-    let plain_file_path = get_decrypted_file_name(&file_path, &dest_dir);
-    match save_decrypted_file(&plain_data, &plain_file_path) {
-        Ok(_) => (),
-        Err(reason) => return error("Could not save decrypted file", reason),
+    let payload: Payload = match bincode::deserialize(&payload_data) {
+        Ok(payload) => payload,
+        Err(reason) => return error("Could not deserialize decrypted content", reason),
     };
+    if payload.header != *header {
+        return error_without_parent("Encrypted file header was tampered with");
+    }
 
-    Ok(())
+    match payload.header.compression {
+        Compression::None => Ok(payload.content),
+        Compression::Zstd => decompress_content(&payload.content),
+    }
 }
 
-fn save_decrypted_file(plain_data: &[u8], output_file_path: &String) -> Result<(), AnyError> {
-    let plain_file_path = Path::new(output_file_path);
+fn open_for_recipient(keypair: &Keypar, cipher: &Cipher) -> Result<SecretBytes, AnyError> {
+    let key_slot = cipher
+        .key_slots
+        .iter()
+        .find(|slot| slot.recipient == *keypair.public_key());
+    let key_slot = match key_slot {
+        Some(slot) => slot,
+        None => return error_without_parent("File was not encrypted for this key"),
+    };
 
-    create_dir_if_not_exists(&format!("{}", plain_file_path.parent().unwrap().display()))?;
+    let tagged_message = get_tagged_message(&key_slot, &cipher.nonce, &cipher.data);
+    let sender_tag = keypair.sender_tag(&key_slot.sender, &tagged_message)?;
+    if !memcmp(&sender_tag, &key_slot.sender_tag) {
+        return error_without_parent("File was not encrypted by the key it claims to come from");
+    }
 
-    let mut plain_file = match File::create(plain_file_path) {
-        Ok(file) => file,
-        Err(reason) => return error("Could not create plain file", reason),
+    let file_key = keypair.open_from(
+        &key_slot.wrapped_key,
+        &key_slot.nonce,
+        &key_slot.ephemeral_pk,
+    )?;
+    let file_key = match secretbox::Key::from_slice(&file_key) {
+        Some(key) => key,
+        None => return error_without_parent("Could not unwrap file key"),
     };
 
+    let secretbox_nonce = secretbox::Nonce::from_slice(cipher.nonce.as_ref()).unwrap();
+    match secretbox::open(&cipher.data, &secretbox_nonce, &file_key) {
+        Ok(data) => Ok(SecretBytes::new(data)),
+        Err(_) => error_without_parent("Could not decrypt file"),
+    }
+}
+
+fn save_decrypted_file(
+    plain_data: &[u8],
+    dest_dir: &String,
+    plain_file_name: &String,
+    should_override: bool,
+) -> Result<PathBuf, AnyError> {
+    create_dir_if_not_exists(&dest_dir)?;
+
+    let plain_file_path = confine_to_dir(&dest_dir, Path::new(plain_file_name))?;
+    let mut plain_file = create_file_no_follow(&plain_file_path, should_override)?;
+
     match plain_file.write_all(&plain_data) {
         Ok(_) => (),
         Err(reason) => return error("Could not write to plain file", reason),
     };
 
-    Ok(())
+    Ok(plain_file_path)
+}
+
+fn get_decrypted_file_name(file_name: &String, dest_dir: &String) -> Result<String, AnyError> {
+    let name = get_plain_file_name(&file_name)?;
+    Ok(format!("{}/{}", dest_dir, name))
 }
 
-fn get_decrypted_file_name(file_name: &String, dest_dir: &String) -> String {
+fn get_plain_file_name(file_name: &String) -> Result<String, AnyError> {
     let path = Path::new(file_name);
-    let name = path.file_stem().unwrap();
-    format!("{}/{}", dest_dir, name.to_str().unwrap())
+    let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(name) => name.to_owned(),
+        None => return error_without_parent("Could not get plain file name"),
+    };
+
+    check_relative_name(Path::new(&name))?;
+    Ok(name)
 }
 
 // Helper functions
@@ -537,6 +2097,254 @@ fn create_dir_if_not_exists(given_dir: &String) -> Result<(), AnyError> {
     Ok(())
 }
 
+fn shred_file(file_path: &String) -> Result<(), AnyError> {
+    let path = Path::new(file_path);
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(reason) => return error("Could not read file metadata", reason),
+    };
+    if !metadata.file_type().is_file() {
+        return error_without_parent("Refusing to shred something that is not a regular file");
+    }
+
+    let mut file = match OpenOptions::new().write(true).open(path) {
+        Ok(file) => file,
+        Err(reason) => return error("Could not open file to shred", reason),
+    };
+
+    // Best effort only, since journaling and copy-on-write filesystems may keep old blocks
+    let mut remaining = metadata.len();
+    let mut chunk = vec![0u8; 64 * 1024];
+    while remaining > 0 {
+        let size = std::cmp::min(remaining, chunk.len() as u64) as usize;
+        randombytes_into(&mut chunk[..size]);
+        match file.write_all(&chunk[..size]) {
+            Ok(_) => (),
+            Err(reason) => return error("Could not overwrite file", reason),
+        };
+        remaining -= size as u64;
+    }
+
+    match file.sync_all() {
+        Ok(_) => (),
+        Err(reason) => return error("Could not flush overwritten file", reason),
+    };
+    drop(file);
+
+    match fs::remove_file(path) {
+        Ok(_) => Ok(()),
+        Err(reason) => error("Could not remove file", reason),
+    }
+}
+
+// -- Text
+
+// Piped passphrases usually come with the line ending of whoever wrote them
+fn trim_line_ending(text: &[u8]) -> &[u8] {
+    match text.strip_suffix(b"\n") {
+        Some(trimmed) => trimmed.strip_suffix(b"\r").unwrap_or(trimmed),
+        None => text,
+    }
+}
+
+// -- Padding
+
+// PADMÉ keeps overhead under 12% while leaving only O(log log n) bits of the length visible
+fn get_padme_len(len: usize) -> usize {
+    if len < 2 {
+        return len;
+    }
+
+    let exponent = usize::BITS - 1 - len.leading_zeros();
+    let exponent_bits = u32::BITS - exponent.leading_zeros();
+    let last_bits = exponent - exponent_bits;
+    let bit_mask = (1usize << last_bits) - 1;
+    (len + bit_mask) & !bit_mask
+}
+
+// -- Hashing
+
+// Subkey bound to the given context, so a secret key is never used twice for different things
+fn derive_subkey(sk: &SecretKey, context: &[u8]) -> Result<SecretBytes, AnyError> {
+    match keyed_hash(32, &sk.0, &[context]) {
+        Ok(digest) => Ok(SecretBytes::new(digest.as_ref().to_vec())),
+        Err(_) => error_without_parent("Could not derive key"),
+    }
+}
+
+fn derive_signing_keypair(
+    sk: &SecretKey,
+    context: &[u8],
+) -> Result<(ed25519::PublicKey, ed25519::SecretKey), AnyError> {
+    let seed = derive_subkey(sk, context)?;
+    match ed25519::Seed::from_slice(&seed) {
+        Some(seed) => Ok(ed25519::keypair_from_seed(&seed)),
+        None => error_without_parent("Could not derive signing key"),
+    }
+}
+
+fn keyed_hash(out_len: usize, key: &[u8], parts: &[&[u8]]) -> Result<generichash::Digest, ()> {
+    let mut state = generichash::State::new(Some(out_len), Some(key))?;
+    for part in parts {
+        state.update(part)?;
+    }
+    state.finalize()
+}
+
+// -- Passphrase
+
+// Costs are read from the file, so they are capped before libsodium allocates that much memory
+fn derive_passphrase_key(
+    passphrase: &[u8],
+    key_derivation: &KeyDerivation,
+) -> Result<secretbox::Key, AnyError> {
+    if key_derivation.ops_limit > argon2id13::OPSLIMIT_SENSITIVE.0 as u64
+        || key_derivation.mem_limit > argon2id13::MEMLIMIT_SENSITIVE.0 as u64
+    {
+        return error_without_parent("Passphrase key derivation costs are beyond the limits");
+    }
+
+    let mut key = secretbox::Key([0; secretbox::KEYBYTES]);
+    match argon2id13::derive_key(
+        &mut key.0,
+        trim_line_ending(passphrase),
+        &key_derivation.salt,
+        argon2id13::OpsLimit(key_derivation.ops_limit as usize),
+        argon2id13::MemLimit(key_derivation.mem_limit as usize),
+    ) {
+        Ok(_) => Ok(key),
+        Err(_) => error_without_parent("Could not derive key from passphrase"),
+    }
+}
+
+// -- Path confinement
+
+fn check_relative_name(name: &Path) -> Result<(), AnyError> {
+    if name.as_os_str().is_empty() {
+        return error_without_parent("File name is empty");
+    }
+
+    for component in name.components() {
+        match component {
+            Component::Normal(_) => (),
+            _ => {
+                return error_without_parent(&format!(
+                    "File name {} escapes its destination directory",
+                    name.display()
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn confine_to_dir(base_dir: &String, relative_name: &Path) -> Result<PathBuf, AnyError> {
+    check_relative_name(relative_name)?;
+
+    let abs_base_dir = match Path::new(base_dir).canonicalize() {
+        Ok(path) => path,
+        Err(reason) => return error("Could not resolve destination directory", reason),
+    };
+
+    // Walk down one component at a time so no symlink is ever followed on the way
+    let mut current_path = abs_base_dir.clone();
+    let mut components = relative_name.components().peekable();
+    while let Some(component) = components.next() {
+        current_path.push(component);
+        let is_last = components.peek().is_none();
+
+        match fs::symlink_metadata(&current_path) {
+            Ok(metadata) => {
+                if metadata.file_type().is_symlink() {
+                    return error_without_parent(&format!(
+                        "Refusing to follow symlink at {}",
+                        current_path.display()
+                    ));
+                }
+                if !is_last && !metadata.is_dir() {
+                    return error_without_parent(&format!(
+                        "Path {} is not a directory",
+                        current_path.display()
+                    ));
+                }
+                if is_last && metadata.is_dir() {
+                    return error_without_parent(&format!(
+                        "Path {} is a directory",
+                        current_path.display()
+                    ));
+                }
+            }
+            Err(_) if !is_last => match fs::create_dir(&current_path) {
+                Ok(_) => (),
+                Err(reason) => return error("Could not create directory", reason),
+            },
+            Err(_) => (),
+        }
+    }
+
+    let abs_parent_dir = match current_path.parent().map(|parent| parent.canonicalize()) {
+        Some(Ok(path)) => path,
+        Some(Err(reason)) => return error("Could not resolve target directory", reason),
+        None => return error_without_parent("Target file has no parent directory"),
+    };
+    if !abs_parent_dir.starts_with(&abs_base_dir) {
+        return error_without_parent(&format!(
+            "Path {} escapes its destination directory",
+            current_path.display()
+        ));
+    }
+
+    Ok(current_path)
+}
+
+fn create_file_no_follow(file_path: &Path, should_override: bool) -> Result<File, AnyError> {
+    if should_override {
+        match fs::symlink_metadata(file_path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return error_without_parent(&format!(
+                    "Refusing to follow symlink at {}",
+                    file_path.display()
+                ))
+            }
+            Ok(_) => match fs::remove_file(file_path) {
+                Ok(_) => (),
+                Err(reason) => return error("Could not remove existing file", reason),
+            },
+            Err(_) => (),
+        }
+    }
+
+    // create_new never follows a symlink, even a dangling one planted in the meantime
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(file_path)
+    {
+        Ok(file) => Ok(file),
+        Err(reason) => error("Could not create file", reason),
+    }
+}
+
+// Modules
+//
+
+pub mod age_format;
+pub mod agent;
+mod armor;
+pub mod audit;
+pub mod backup;
+pub mod batch;
+pub mod edit;
+pub mod exec;
+pub mod git_filter;
+pub mod progress;
+pub mod recovery;
+pub mod secret_store;
+pub mod ssh;
+pub mod storage;
+pub mod watch;
+
 // Unit tests
 //
 
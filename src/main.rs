@@ -1,28 +1,447 @@
-use clap::{App, Arg};
-use console::Style;
-use dialoguer::Confirm;
-use moy_sekret::{decrypt, encrypt, exit_normal, exit_with_error, init, AnyError};
+use clap::{App, Arg, ArgMatches};
+use console::{Style, Term};
+use data_encoding::BASE64;
+use dialoguer::{Confirm, Password};
+use moy_sekret::age_format::AgeOptions;
+use moy_sekret::batch::Outcome;
+use moy_sekret::progress::ProgressBar;
+use moy_sekret::{
+    age_format, agent, audit, backup, batch, configure, decrypt_with_options,
+    decrypt_with_passphrase, edit, encrypt_with_options, encrypt_with_passphrase, exec,
+    exit_normal, exit_with_error, git_filter, init, init_from_seed, is_passphrase_file,
+    plan_decrypt, plan_encrypt, plan_encrypt_with_passphrase, plan_init, plan_remove_source,
+    progress, recovery, remove_source, secret_store, ssh, watch, AnyError, DecryptOptions,
+    EncryptOptions, Plan, Report, SecretBytes,
+};
+use serde_json::json;
+use std::env;
+use std::io;
+use std::io::prelude::*;
+use std::process;
 
 // Macros
 //
 
 macro_rules! confirm_override {
-    ($warning_override:expr, $warning_unrecoverable:expr) => {
+    ($prompting:expr, $output:expr, $warning_override:expr, $warning_unrecoverable:expr) => {
         let red_alert = Style::new().red();
-        println!(
+        let warning = format!(
             concat!($warning_override, "\n", $warning_unrecoverable),
             OVERRIDE = red_alert.apply_to("override"),
             UNRECOVERABLE = red_alert.apply_to("unrecoverable")
         );
-        let confirm = Confirm::new()
-            .with_prompt("Are you sure about that?")
-            .interact();
-        if let Ok(false) = confirm {
-            exit_normal("Okay. Safe move.");
+        $output.warn(&warning);
+        if !$prompting.assume_yes {
+            if !$prompting.can_prompt() {
+                $output.failure(AnyError::new(
+                    "Could not ask for confirmation",
+                    Some(Box::new(AnyError::without_parent(
+                        "There is no terminal to prompt on or input is disabled, use --yes to proceed",
+                    ))),
+                ));
+            }
+            let confirm = Confirm::new()
+                .with_prompt("Are you sure about that?")
+                .interact();
+            match confirm {
+                Ok(true) => (),
+                Ok(false) => $output.cancelled(),
+                Err(reason) => $output.failure(AnyError::new(
+                    "Could not ask for confirmation",
+                    Some(Box::new(reason)),
+                )),
+            }
         }
     };
 }
 
+// Custom types
+//
+
+struct Prompting {
+    assume_yes: bool,
+    no_input: bool,
+}
+
+impl Prompting {
+    fn from_matches(matches: &ArgMatches) -> Prompting {
+        Prompting {
+            assume_yes: matches.is_present("yes"),
+            no_input: matches.is_present("no-input"),
+        }
+    }
+
+    fn can_prompt(&self) -> bool {
+        // Confirm prompts on stderr, so there must be a terminal behind it
+        !self.no_input && Term::stderr().is_term()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+struct Output {
+    format: OutputFormat,
+    command: String,
+}
+
+impl Output {
+    fn from_matches(matches: &ArgMatches) -> Output {
+        let format = match matches.value_of("output") {
+            Some("json") => OutputFormat::Json,
+            _ => OutputFormat::Text,
+        };
+        let mut command_names = Vec::new();
+        let mut current_matches = matches;
+        while let (name, Some(sub_matches)) = current_matches.subcommand() {
+            command_names.push(name);
+            current_matches = sub_matches;
+        }
+        Output {
+            format,
+            command: command_names.join(" "),
+        }
+    }
+
+    fn warn(&self, warning: &str) {
+        // Keep stdout clean for whoever is parsing the JSON document
+        match self.format {
+            OutputFormat::Text => println!("{}", warning),
+            OutputFormat::Json => eprintln!("{}", warning),
+        }
+    }
+
+    fn success(&self, message: &str, report: &Report) {
+        match self.format {
+            OutputFormat::Text => {
+                println!("{}", message);
+                print_warnings(report);
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "command": self.command,
+                    "status": "ok",
+                    "message": message,
+                    "written": report.written,
+                    "removed": report.removed,
+                    "fingerprints": report.fingerprints,
+                    "warnings": report.warnings,
+                })
+            ),
+        }
+    }
+
+    // Only told once the keys it recreates exist, and as part of the result scripts read
+    fn recovery_phrase(&self, message: &str, report: &Report, phrase: &[u8]) {
+        let phrase = String::from_utf8_lossy(phrase);
+        match self.format {
+            OutputFormat::Text => {
+                println!(
+                    "Write this recovery phrase down and keep it safe, it recreates your keys:\n\n{}\n",
+                    phrase
+                );
+                self.success(message, report);
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "command": self.command,
+                    "status": "ok",
+                    "message": message,
+                    "written": report.written,
+                    "removed": report.removed,
+                    "fingerprints": report.fingerprints,
+                    "warnings": report.warnings,
+                    "recovery_phrase": phrase,
+                })
+            ),
+        }
+    }
+
+    fn value(&self, value: &[u8]) {
+        match self.format {
+            OutputFormat::Text => {
+                let stdout = io::stdout();
+                let mut handle = stdout.lock();
+                let _ = handle.write_all(value).and_then(|_| handle.flush());
+            }
+            OutputFormat::Json => {
+                let value = match std::str::from_utf8(value) {
+                    Ok(text) => json!({ "value": text }),
+                    Err(_) => json!({ "value_base64": BASE64.encode(value) }),
+                };
+                println!(
+                    "{}",
+                    json!({
+                        "command": self.command,
+                        "status": "ok",
+                        "secret": value,
+                    })
+                );
+            }
+        }
+    }
+
+    fn listing(&self, items: &[String]) {
+        match self.format {
+            OutputFormat::Text => {
+                for item in items {
+                    println!("{}", item);
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "command": self.command,
+                    "status": "ok",
+                    "items": items,
+                })
+            ),
+        }
+    }
+
+    fn outcomes(&self, outcomes: &[Outcome]) {
+        let failed_count = outcomes
+            .iter()
+            .filter(|outcome| outcome.result.is_err())
+            .count();
+        let succeeded_count = outcomes.len() - failed_count;
+
+        match self.format {
+            OutputFormat::Text => {
+                for outcome in outcomes {
+                    match &outcome.result {
+                        Ok(report) => {
+                            println!("ok      {}", outcome.file_path);
+                            print_warnings(report);
+                        }
+                        Err(reason) => println!("failed  {}: {}", outcome.file_path, reason),
+                    }
+                }
+                println!("{} succeeded, {} failed", succeeded_count, failed_count);
+            }
+            OutputFormat::Json => {
+                let files: Vec<serde_json::Value> = outcomes
+                    .iter()
+                    .map(|outcome| match &outcome.result {
+                        Ok(report) => json!({
+                            "file": outcome.file_path,
+                            "status": "ok",
+                            "written": report.written,
+                            "fingerprints": report.fingerprints,
+                            "warnings": report.warnings,
+                        }),
+                        Err(reason) => json!({
+                            "file": outcome.file_path,
+                            "status": "error",
+                            "error": error_json(reason),
+                        }),
+                    })
+                    .collect();
+                println!(
+                    "{}",
+                    json!({
+                        "command": self.command,
+                        "status": if failed_count == 0 { "ok" } else { "error" },
+                        "succeeded": succeeded_count,
+                        "failed": failed_count,
+                        "files": files,
+                    })
+                );
+            }
+        }
+
+        // Scripts only need the exit code to know whether every file made it
+        if failed_count > 0 {
+            process::exit(666);
+        }
+    }
+
+    // One line per file as it goes, since watching has no end to summarize at
+    fn outcome(&self, outcome: &Outcome) {
+        match self.format {
+            OutputFormat::Text => match &outcome.result {
+                Ok(report) => {
+                    println!("ok      {}", outcome.file_path);
+                    print_warnings(report);
+                }
+                Err(reason) => println!("failed  {}: {}", outcome.file_path, reason),
+            },
+            OutputFormat::Json => {
+                let line = match &outcome.result {
+                    Ok(report) => json!({
+                        "command": self.command,
+                        "file": outcome.file_path,
+                        "status": "ok",
+                        "written": report.written,
+                        "removed": report.removed,
+                        "fingerprints": report.fingerprints,
+                        "warnings": report.warnings,
+                    }),
+                    Err(reason) => json!({
+                        "command": self.command,
+                        "file": outcome.file_path,
+                        "status": "error",
+                        "error": error_json(reason),
+                    }),
+                };
+                println!("{}", line);
+            }
+        }
+    }
+
+    fn entries(&self, entries: &[audit::Entry]) {
+        match self.format {
+            OutputFormat::Text => {
+                for entry in entries {
+                    let record = &entry.record;
+                    println!(
+                        "{:>5}  {}  {}  {}  {}{}",
+                        record.sequence,
+                        format_time(record.time),
+                        record.user,
+                        record.action,
+                        record.subject,
+                        if entry.signature.is_some() {
+                            "  (signed)"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "command": self.command,
+                    "status": "ok",
+                    "entries": entries,
+                })
+            ),
+        }
+    }
+
+    fn plan(&self, plan: &Plan) {
+        match self.format {
+            OutputFormat::Text => {
+                println!("Dry run, nothing was touched");
+                for line in plan_lines(plan) {
+                    println!("{}", line);
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({
+                    "command": self.command,
+                    "status": "ok",
+                    "dry_run": true,
+                    "created": plan.created,
+                    "overwritten": plan.overwritten,
+                    "removed": plan.removed,
+                })
+            ),
+        }
+    }
+
+    fn planned_outcomes(&self, outcomes: &[Outcome<Plan>]) {
+        let failed_count = outcomes
+            .iter()
+            .filter(|outcome| outcome.result.is_err())
+            .count();
+        let succeeded_count = outcomes.len() - failed_count;
+
+        match self.format {
+            OutputFormat::Text => {
+                println!("Dry run, nothing was touched");
+                for outcome in outcomes {
+                    match &outcome.result {
+                        Ok(plan) => {
+                            for line in plan_lines(plan) {
+                                println!("{}", line);
+                            }
+                        }
+                        Err(reason) => println!("failed     {}: {}", outcome.file_path, reason),
+                    }
+                }
+                println!(
+                    "{} would succeed, {} would fail",
+                    succeeded_count, failed_count
+                );
+            }
+            OutputFormat::Json => {
+                let files: Vec<serde_json::Value> = outcomes
+                    .iter()
+                    .map(|outcome| match &outcome.result {
+                        Ok(plan) => json!({
+                            "file": outcome.file_path,
+                            "status": "ok",
+                            "created": plan.created,
+                            "overwritten": plan.overwritten,
+                            "removed": plan.removed,
+                        }),
+                        Err(reason) => json!({
+                            "file": outcome.file_path,
+                            "status": "error",
+                            "error": error_json(reason),
+                        }),
+                    })
+                    .collect();
+                println!(
+                    "{}",
+                    json!({
+                        "command": self.command,
+                        "status": if failed_count == 0 { "ok" } else { "error" },
+                        "dry_run": true,
+                        "succeeded": succeeded_count,
+                        "failed": failed_count,
+                        "files": files,
+                    })
+                );
+            }
+        }
+
+        // Same exit code as the real run would give, so scripts can check before going ahead
+        if failed_count > 0 {
+            process::exit(666);
+        }
+    }
+
+    fn cancelled(&self) {
+        match self.format {
+            OutputFormat::Text => exit_normal("Okay. Safe move."),
+            OutputFormat::Json => exit_normal(
+                &json!({
+                    "command": self.command,
+                    "status": "cancelled",
+                    "message": "Okay. Safe move.",
+                })
+                .to_string(),
+            ),
+        }
+    }
+
+    fn failure(&self, reason: AnyError) {
+        match self.format {
+            OutputFormat::Text => generic_exit_with_error(reason),
+            OutputFormat::Json => {
+                println!(
+                    "{}",
+                    json!({
+                        "command": self.command,
+                        "status": "error",
+                        "error": error_json(&reason),
+                    })
+                );
+                process::exit(666);
+            }
+        }
+    }
+}
+
 // Main
 //
 
@@ -34,10 +453,58 @@ fn main() {
         .takes_value(true)
         .value_name("PROFILE")
         .required(true);
+    let secret_path_arg = Arg::with_name("path")
+        .about("hierarchical path of the secret, such as db/prod/password")
+        .index(1)
+        .value_name("PATH")
+        .required(true);
+    let socket_arg = Arg::with_name("socket")
+        .about("path to the agent socket, defaults to $MOY_SEKRET_AGENT_SOCK or one in the user runtime directory")
+        .long("socket")
+        .takes_value(true)
+        .value_name("SOCKET");
+    let dry_run_arg = Arg::with_name("dry-run")
+        .about("Should it only tell which files and keys would be created, overwritten or removed, touching nothing, or not")
+        .long("dry-run");
+    let ttl_arg = Arg::with_name("ttl")
+        .about("seconds to keep keys for, 0 meaning until removed")
+        .long("ttl")
+        .takes_value(true)
+        .value_name("SECONDS");
     let mut app = App::new("Moy Sekret")
         .version("1.0")
         .author("Leandro Silva <leandrodoze@gmail.com>")
         .about("You know, that is kind of... secret.")
+        .arg(
+            Arg::with_name("yes")
+                .about("Should it skip confirmation prompts or not")
+                .short('y')
+                .long("yes")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("no-input")
+                .about("Should it fail rather than prompt for confirmation or not")
+                .long("no-input")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .about("Should it hide progress bars or not")
+                .short('q')
+                .long("quiet")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .about("format of the results printed out")
+                .long("output")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
         .subcommand(
             App::new("init")
                 .about("Initializes the app for a give profile.")
@@ -58,14 +525,56 @@ fn main() {
                         .about("Should it override existing profile and keys or not")
                         .short('o')
                         .long("override"),
+                )
+                .arg(
+                    Arg::with_name("new-recovery-phrase")
+                        .about("Should it derive keys from a newly generated recovery phrase, printed out to be written down, or not")
+                        .long("new-recovery-phrase")
+                        .conflicts_with_all(&["recovery-phrase", "from-seed"]),
+                )
+                .arg(
+                    Arg::with_name("recovery-phrase")
+                        .about("Should it derive keys from an existing recovery phrase, read from stdin or prompted for, or not")
+                        .long("recovery-phrase")
+                        .conflicts_with("from-seed"),
+                )
+                .arg(
+                    Arg::with_name("from-seed")
+                        .about("Should it derive keys from a hex encoded seed, read from stdin or prompted for, or not")
+                        .long("from-seed"),
+                )
+                .arg(
+                    &dry_run_arg,
                 ),
         )
         .subcommand(
-            App::new("encrypt")
-                .about("Encrypts a source file, saves it to the target repository directory and keeps the original one.")
+            App::new("config")
+                .about("Changes a setting of a given profile, such as its default compression or padding.")
                 .arg(
                     &profile_arg,
                 )
+                .arg(
+                    Arg::with_name("setting")
+                        .about("name of the setting to change")
+                        .index(1)
+                        .value_name("SETTING")
+                        .possible_values(&["compression", "padding", "log-signing"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("value")
+                        .about("new value of the setting")
+                        .index(2)
+                        .value_name("VALUE")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("encrypt")
+                .about("Encrypts a source file, saves it to the target repository directory and keeps the original one, unless asked to remove it.")
+                .arg(
+                    profile_arg.clone().required(false).required_unless("passphrase"),
+                )
                 .arg(
                     Arg::with_name("file")
                         .about("path to the source file to be encrypted")
@@ -80,13 +589,66 @@ fn main() {
                         .about("Should it override existing encrypted file or not")
                         .short('o')
                         .long("override"),
+                )
+                .arg(
+                    Arg::with_name("remove-source")
+                        .about("Should it verify the encrypted file, then overwrite and delete the source file or not")
+                        .short('r')
+                        .long("remove-source"),
+                )
+                .arg(
+                    Arg::with_name("compression")
+                        .about("compression applied before encrypting, overriding the profile default")
+                        .long("compression")
+                        .takes_value(true)
+                        .value_name("COMPRESSION")
+                        .possible_values(&["none", "zstd"]),
+                )
+                .arg(
+                    Arg::with_name("padding")
+                        .about("padding applied to hide the file size, overriding the profile default")
+                        .long("padding")
+                        .takes_value(true)
+                        .value_name("PADDING")
+                        .possible_values(&["none", "padme"]),
+                )
+                .arg(
+                    Arg::with_name("armor")
+                        .about("Should it write the encrypted file as a text block, ready to paste into emails and tickets, or not")
+                        .short('a')
+                        .long("armor"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .about("ssh-ed25519 public key, as in an authorized_keys line or file, that should also be able to decrypt")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("KEY")
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("passphrase")
+                        .about("Should it encrypt to a passphrase, read from stdin or prompted for, saving the file next to the source instead of using a profile or not")
+                        .long("passphrase")
+                        .conflicts_with_all(&["profile", "to", "remove-source"]),
+                )
+                .arg(
+                    Arg::with_name("label")
+                        .about("label bound to the encrypted file, such as prod or staging, to be given again on decryption")
+                        .long("label")
+                        .takes_value(true)
+                        .value_name("LABEL"),
+                )
+                .arg(
+                    &dry_run_arg,
                 ),
         )
         .subcommand(
             App::new("decrypt")
                 .about("Decrypts a source file, saves it plain to a target directory and keeps the encrypted one.")
                 .arg(
-                    &profile_arg,
+                    profile_arg.clone().required(false),
                 )
                 .arg(
                     Arg::with_name("file")
@@ -111,15 +673,592 @@ fn main() {
                         .about("Should it override existing plain file or not")
                         .short('o')
                         .long("override"),
+                )
+                .arg(
+                    Arg::with_name("ssh-key")
+                        .about("OpenSSH Ed25519 private key file to decrypt with, instead of the profile keys")
+                        .long("ssh-key")
+                        .takes_value(true)
+                        .value_name("KEY_FILE")
+                        .conflicts_with("profile"),
+                )
+                .arg(
+                    Arg::with_name("label")
+                        .about("label the encrypted file must have been bound to, refusing it otherwise")
+                        .long("label")
+                        .takes_value(true)
+                        .value_name("LABEL"),
+                )
+                .arg(
+                    &dry_run_arg,
+                ),
+        )
+        .subcommand(
+            App::new("edit")
+                .about("Decrypts a file to a private temporary copy, opens it in $VISUAL or $EDITOR and encrypts it back if changed.")
+                .arg(
+                    &profile_arg,
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .about("path to the encrypted file to be edited")
+                        .short('f')
+                        .long("file")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("label")
+                        .about("label the encrypted file must have been bound to, refusing it otherwise")
+                        .long("label")
+                        .takes_value(true)
+                        .value_name("LABEL"),
+                ),
+        )
+        .subcommand(
+            App::new("exec")
+                .about("Runs a command with variables from an encrypted dotenv file, never writing them to disk.")
+                .arg(
+                    &profile_arg,
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .about("path to the encrypted dotenv file")
+                        .short('f')
+                        .long("file")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("label")
+                        .about("label the encrypted file must have been bound to, refusing it otherwise")
+                        .long("label")
+                        .takes_value(true)
+                        .value_name("LABEL"),
+                )
+                .arg(
+                    Arg::with_name("command")
+                        .about("command to run, given after --")
+                        .multiple(true)
+                        .last(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("secret")
+                .about("Keeps individual secret values, encrypted under a hierarchical path.")
+                .subcommand(
+                    App::new("set")
+                        .about("Encrypts a value read from stdin, or prompted for, under a given path.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            &secret_path_arg,
+                        )
+                        .arg(
+                            Arg::with_name("override")
+                                .about("Should it override existing secret or not")
+                                .short('o')
+                                .long("override"),
+                        ),
+                )
+                .subcommand(
+                    App::new("get")
+                        .about("Decrypts the value under a given path and prints it out.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            &secret_path_arg,
+                        ),
+                )
+                .subcommand(
+                    App::new("list")
+                        .about("Lists the paths of all secrets, optionally under a given prefix.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            Arg::with_name("prefix")
+                                .about("prefix of the secret paths to list")
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    App::new("rm")
+                        .about("Removes the secret under a given path.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            &secret_path_arg,
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("git-filter")
+                .about("Runs as a git clean/smudge filter, encrypting on commit and decrypting on checkout.")
+                .arg(
+                    Arg::with_name("mode")
+                        .about("which side of the filter to run")
+                        .index(1)
+                        .possible_values(&["clean", "smudge"])
+                        .required(true),
+                )
+                .arg(
+                    &profile_arg,
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .about("path of the file being filtered, as given by git's %f")
+                        .long("path")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("git-setup")
+                .about("Sets up a git repository to encrypt matching files at rest using this profile.")
+                .arg(
+                    &profile_arg,
+                )
+                .arg(
+                    Arg::with_name("repo")
+                        .about("path to the git repository")
+                        .short('r')
+                        .long("repo")
+                        .takes_value(true)
+                        .value_name("REPO")
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("pattern")
+                        .about("gitattributes pattern of files to be encrypted")
+                        .long("pattern")
+                        .takes_value(true)
+                        .value_name("PATTERN")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("backup-key")
+                .about("Prints the secret key of a profile as a printable backup, optionally split into shares of which only some are needed to restore it.")
+                .arg(
+                    &profile_arg,
+                )
+                .arg(
+                    Arg::with_name("shares")
+                        .about("number of shares to split the key into")
+                        .long("shares")
+                        .takes_value(true)
+                        .value_name("SHARES")
+                        .requires("threshold"),
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .about("number of shares needed to restore the key")
+                        .long("threshold")
+                        .takes_value(true)
+                        .value_name("THRESHOLD")
+                        .requires("shares"),
+                ),
+        )
+        .subcommand(
+            App::new("restore-key")
+                .about("Rebuilds the key pair of a profile from backup files, or stdin, and rewrites the profile.")
+                .arg(
+                    &profile_arg,
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .about("target directory where to store keys and encrypted files")
+                        .short('d')
+                        .long("dir")
+                        .takes_value(true)
+                        .value_name("DIR")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("override")
+                        .about("Should it override existing profile and keys or not")
+                        .short('o')
+                        .long("override"),
+                )
+                .arg(
+                    Arg::with_name("backup")
+                        .about("files holding the backup or enough of its shares")
+                        .index(1)
+                        .value_name("BACKUP")
+                        .multiple(true),
+                )
+                .arg(
+                    &dry_run_arg,
+                ),
+        )
+        .subcommand(
+            App::new("age")
+                .about("Reads and writes files in the age format, to exchange them with age users.")
+                .subcommand(
+                    App::new("encrypt")
+                        .about("Encrypts a source file to this profile and any given age recipient, or to a passphrase, saving it as .age to the target repository directory.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            Arg::with_name("file")
+                                .about("path to the source file to be encrypted")
+                                .short('f')
+                                .long("file")
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("override")
+                                .about("Should it override existing encrypted file or not")
+                                .short('o')
+                                .long("override"),
+                        )
+                        .arg(
+                            &dry_run_arg,
+                        )
+                        .arg(
+                            Arg::with_name("recipient")
+                                .about("age recipient, such as age1..., that should also be able to decrypt")
+                                .long("recipient")
+                                .takes_value(true)
+                                .value_name("RECIPIENT")
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name("passphrase")
+                                .about("Should it encrypt to a passphrase, read from stdin or prompted for, instead of keys or not")
+                                .long("passphrase")
+                                .conflicts_with("recipient"),
+                        )
+                        .arg(
+                            Arg::with_name("armor")
+                                .about("Should it write the encrypted file as age armored text or not")
+                                .short('a')
+                                .long("armor"),
+                        ),
+                )
+                .subcommand(
+                    App::new("decrypt")
+                        .about("Decrypts an age file, armored or not, and saves it plain to a target directory.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            Arg::with_name("file")
+                                .about("path to the age file to be decrypted")
+                                .short('f')
+                                .long("file")
+                                .takes_value(true)
+                                .value_name("FILE")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("dest")
+                                .about("target directory to where save the decrypted file")
+                                .short('d')
+                                .long("dest")
+                                .takes_value(true)
+                                .value_name("DEST")
+                                .default_value("."),
+                        )
+                        .arg(
+                            Arg::with_name("override")
+                                .about("Should it override existing plain file or not")
+                                .short('o')
+                                .long("override"),
+                        )
+                        .arg(
+                            Arg::with_name("passphrase")
+                                .about("Should it decrypt with a passphrase, read from stdin or prompted for, or not")
+                                .long("passphrase"),
+                        ),
+                )
+                .subcommand(
+                    App::new("recipient")
+                        .about("Prints the public key of a profile as an age recipient, to be given to age users.")
+                        .arg(
+                            &profile_arg,
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("batch")
+                .about("Encrypts or decrypts many files at once, reading keys a single time and spreading the work across cores.")
+                .subcommand(
+                    App::new("encrypt")
+                        .about("Encrypts every file given or matched by a glob pattern, saving them to the target repository directory.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            Arg::with_name("files")
+                                .about("files or glob patterns, such as reports/*.pdf, to be encrypted")
+                                .index(1)
+                                .value_name("FILES")
+                                .multiple(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("override")
+                                .about("Should it override existing encrypted files or not")
+                                .short('o')
+                                .long("override"),
+                        )
+                        .arg(
+                            Arg::with_name("jobs")
+                                .about("number of files to work on at the same time, defaults to one per core")
+                                .short('j')
+                                .long("jobs")
+                                .takes_value(true)
+                                .value_name("JOBS"),
+                        )
+                        .arg(
+                            Arg::with_name("compression")
+                                .about("compression applied before encrypting, overriding the profile default")
+                                .long("compression")
+                                .takes_value(true)
+                                .value_name("COMPRESSION")
+                                .possible_values(&["none", "zstd"]),
+                        )
+                        .arg(
+                            Arg::with_name("padding")
+                                .about("padding applied to hide the file size, overriding the profile default")
+                                .long("padding")
+                                .takes_value(true)
+                                .value_name("PADDING")
+                                .possible_values(&["none", "padme"]),
+                        )
+                        .arg(
+                            Arg::with_name("armor")
+                                .about("Should it write the encrypted files as text blocks or not")
+                                .short('a')
+                                .long("armor"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .about("ssh-ed25519 public key, as in an authorized_keys line or file, that should also be able to decrypt")
+                                .long("to")
+                                .takes_value(true)
+                                .value_name("KEY")
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name("label")
+                                .about("label bound to every encrypted file, to be given again on decryption")
+                                .long("label")
+                                .takes_value(true)
+                                .value_name("LABEL"),
+                        )
+                        .arg(
+                            &dry_run_arg,
+                        ),
+                )
+                .subcommand(
+                    App::new("decrypt")
+                        .about("Decrypts every file given or matched by a glob pattern, saving them plain to a target directory.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            Arg::with_name("files")
+                                .about("files or glob patterns, such as storage/*.cz, to be decrypted")
+                                .index(1)
+                                .value_name("FILES")
+                                .multiple(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("dest")
+                                .about("target directory to where save the decrypted files")
+                                .short('d')
+                                .long("dest")
+                                .takes_value(true)
+                                .value_name("DEST")
+                                .default_value("."),
+                        )
+                        .arg(
+                            Arg::with_name("override")
+                                .about("Should it override existing plain files or not")
+                                .short('o')
+                                .long("override"),
+                        )
+                        .arg(
+                            Arg::with_name("jobs")
+                                .about("number of files to work on at the same time, defaults to one per core")
+                                .short('j')
+                                .long("jobs")
+                                .takes_value(true)
+                                .value_name("JOBS"),
+                        )
+                        .arg(
+                            Arg::with_name("label")
+                                .about("label every encrypted file must have been bound to, refusing it otherwise")
+                                .long("label")
+                                .takes_value(true)
+                                .value_name("LABEL"),
+                        )
+                        .arg(
+                            &dry_run_arg,
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("watch")
+                .about("Watches a drop folder and encrypts every file put or changed in there, until stopped.")
+                .arg(
+                    &profile_arg,
+                )
+                .arg(
+                    Arg::with_name("dir")
+                        .about("directory to watch for new or changed files")
+                        .index(1)
+                        .value_name("DIR")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("remove-source")
+                        .about("Should it verify each encrypted file, then overwrite and delete its source file or not")
+                        .short('r')
+                        .long("remove-source"),
+                )
+                .arg(
+                    Arg::with_name("compression")
+                        .about("compression applied before encrypting, overriding the profile default")
+                        .long("compression")
+                        .takes_value(true)
+                        .value_name("COMPRESSION")
+                        .possible_values(&["none", "zstd"]),
+                )
+                .arg(
+                    Arg::with_name("padding")
+                        .about("padding applied to hide the file size, overriding the profile default")
+                        .long("padding")
+                        .takes_value(true)
+                        .value_name("PADDING")
+                        .possible_values(&["none", "padme"]),
+                )
+                .arg(
+                    Arg::with_name("armor")
+                        .about("Should it write the encrypted files as text blocks or not")
+                        .short('a')
+                        .long("armor"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .about("ssh-ed25519 public key, as in an authorized_keys line or file, that should also be able to decrypt")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("KEY")
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("label")
+                        .about("label bound to every encrypted file, to be given again on decryption")
+                        .long("label")
+                        .takes_value(true)
+                        .value_name("LABEL"),
+                ),
+        )
+        .subcommand(
+            App::new("log")
+                .about("Shows or verifies the hash-chained log of what was done with a profile.")
+                .subcommand(
+                    App::new("show")
+                        .about("Prints every entry of the log, oldest first.")
+                        .arg(
+                            &profile_arg,
+                        ),
+                )
+                .subcommand(
+                    App::new("verify")
+                        .about("Checks that no entry of the log was edited, reordered or removed, signatures included.")
+                        .arg(
+                            &profile_arg,
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("agent")
+                .about("Keeps unlocked keys in locked memory for a while and serves other commands through a Unix socket.")
+                .subcommand(
+                    App::new("start")
+                        .about("Runs the agent in the foreground until it is stopped.")
+                        .arg(
+                            &socket_arg,
+                        )
+                        .arg(
+                            &ttl_arg,
+                        ),
+                )
+                .subcommand(
+                    App::new("add")
+                        .about("Loads the keys of a profile into the agent.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            &socket_arg,
+                        )
+                        .arg(
+                            &ttl_arg,
+                        ),
+                )
+                .subcommand(
+                    App::new("remove")
+                        .about("Makes the agent forget the keys of a profile.")
+                        .arg(
+                            &profile_arg,
+                        )
+                        .arg(
+                            &socket_arg,
+                        ),
+                )
+                .subcommand(
+                    App::new("list")
+                        .about("Lists the profiles whose keys the agent holds.")
+                        .arg(
+                            &socket_arg,
+                        ),
+                )
+                .subcommand(
+                    App::new("stop")
+                        .about("Makes the agent forget every key and exit.")
+                        .arg(
+                            &socket_arg,
+                        ),
                 ),
         );
 
     let matches = app.get_matches_mut();
+    let prompting = Prompting::from_matches(&matches);
+    let output = Output::from_matches(&matches);
+    let is_json = output.format == OutputFormat::Json;
+    if let Some(progress_bar) = ProgressBar::for_output(matches.is_present("quiet"), is_json) {
+        progress::install(Box::new(progress_bar));
+    }
     match matches.subcommand() {
         ("init", Some(sub_matches)) => {
             let should_override = sub_matches.is_present("override");
-            if should_override {
+            let should_dry_run = sub_matches.is_present("dry-run");
+            if should_override && !should_dry_run {
                 confirm_override!(
+                    prompting,
+                    output,
                     "This operation will {OVERRIDE} any key you have got with this profile.",
                     "This is {UNRECOVERABLE} and you may lose access to any file you have encrypted with those keys."
                 );
@@ -128,47 +1267,727 @@ fn main() {
             let profile = sub_matches.value_of("profile").unwrap().to_owned();
             let storage_dir = sub_matches.value_of("dir").unwrap().to_owned();
 
-            match init(&profile, &storage_dir, should_override) {
-                Ok(()) => println!(
-                    "Key pair created with success at {} directory",
-                    &storage_dir
-                ),
-                Err(reason) => generic_exit_with_error(reason),
+            // Keys land in the same files however they are made, so no phrase or seed is needed
+            if should_dry_run {
+                return match plan_init(&profile, &storage_dir, should_override) {
+                    Ok(plan) => output.plan(&plan),
+                    Err(reason) => output.failure(reason),
+                };
+            }
+
+            let mut new_phrase = None;
+            let seed = if sub_matches.is_present("new-recovery-phrase") {
+                let phrase = match recovery::generate_phrase() {
+                    Ok(phrase) => phrase,
+                    Err(reason) => return output.failure(reason),
+                };
+                let seed = recovery::seed_from_phrase(&phrase);
+                new_phrase = Some(phrase);
+                Some(seed)
+            } else if sub_matches.is_present("recovery-phrase") {
+                Some(
+                    read_secret_value(&prompting, "Recovery phrase", false)
+                        .and_then(|phrase| recovery::seed_from_phrase(&phrase)),
+                )
+            } else if sub_matches.is_present("from-seed") {
+                Some(
+                    read_secret_value(&prompting, "Seed (hex)", false)
+                        .and_then(|hex_seed| recovery::seed_from_hex(&hex_seed)),
+                )
+            } else {
+                None
+            };
+
+            let result = match seed {
+                Some(Ok(seed)) => init_from_seed(&profile, &storage_dir, &seed, should_override),
+                Some(Err(reason)) => Err(reason),
+                None => init(&profile, &storage_dir, should_override),
+            };
+            let message = format!(
+                "Key pair created with success at {} directory",
+                &storage_dir
+            );
+            match (result, new_phrase) {
+                (Ok(report), Some(phrase)) => output.recovery_phrase(&message, &report, &phrase),
+                (Ok(report), None) => output.success(&message, &report),
+                (Err(reason), _) => output.failure(reason),
+            }
+        }
+        ("config", Some(sub_matches)) => {
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let setting = sub_matches.value_of("setting").unwrap().to_owned();
+            let value = sub_matches.value_of("value").unwrap().to_owned();
+
+            match configure(&profile, &setting, &value) {
+                Ok(report) => output.success("Profile succesfully configured", &report),
+                Err(reason) => output.failure(reason),
             }
         }
         ("encrypt", Some(sub_matches)) => {
             let should_override = sub_matches.is_present("override");
-            if should_override {
+            let should_dry_run = sub_matches.is_present("dry-run");
+            if should_override && !should_dry_run {
                 confirm_override!(
+                    prompting,
+                    output,
                     "This operation will {OVERRIDE} the existing encrypted file.",
                     "This is {UNRECOVERABLE}, please be sure what you are about to do."
                 );
             }
 
-            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let should_remove_source = sub_matches.is_present("remove-source");
+            if should_remove_source && !should_dry_run {
+                confirm_override!(
+                    prompting,
+                    output,
+                    "This operation will {OVERRIDE} and delete the original plain file once encrypted.",
+                    "This is {UNRECOVERABLE}, so be sure you can still decrypt it later."
+                );
+            }
+
             let file_path = sub_matches.value_of("file").unwrap().to_owned();
 
-            match encrypt(&profile, &file_path, should_override) {
-                Ok(()) => println!("Encryption succesfully done"),
-                Err(reason) => generic_exit_with_error(reason),
+            let options = EncryptOptions {
+                compression: sub_matches
+                    .value_of("compression")
+                    .map(|compression| compression.parse().unwrap()),
+                padding: sub_matches
+                    .value_of("padding")
+                    .map(|padding| padding.parse().unwrap()),
+                armor: sub_matches.is_present("armor"),
+                recipients: sub_matches
+                    .values_of("to")
+                    .map(|recipients| recipients.map(|recipient| recipient.to_owned()).collect())
+                    .unwrap_or_default(),
+                label: sub_matches.value_of("label").map(|label| label.to_owned()),
+            };
+
+            if sub_matches.is_present("passphrase") {
+                if should_dry_run {
+                    return match plan_encrypt_with_passphrase(&file_path, should_override, &options)
+                    {
+                        Ok(plan) => output.plan(&plan),
+                        Err(reason) => output.failure(reason),
+                    };
+                }
+
+                let passphrase = match read_secret_value(&prompting, "Passphrase", true) {
+                    Ok(passphrase) => passphrase,
+                    Err(reason) => return output.failure(reason),
+                };
+                return match encrypt_with_passphrase(
+                    &file_path,
+                    &passphrase,
+                    should_override,
+                    &options,
+                ) {
+                    Ok(report) => output.success("Encryption succesfully done", &report),
+                    Err(reason) => output.failure(reason),
+                };
+            }
+
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            if should_dry_run {
+                let result = plan_encrypt(&profile, &file_path, should_override).and_then(|plan| {
+                    if should_remove_source {
+                        Ok(plan.merge(plan_remove_source(&file_path)?))
+                    } else {
+                        Ok(plan)
+                    }
+                });
+                return match result {
+                    Ok(plan) => output.plan(&plan),
+                    Err(reason) => output.failure(reason),
+                };
+            }
+
+            let report = match encrypt_with_options(&profile, &file_path, should_override, &options)
+            {
+                Ok(report) => report,
+                Err(reason) => return output.failure(reason),
+            };
+
+            if should_remove_source {
+                match remove_source(&profile, &file_path) {
+                    Ok(removal_report) => output.success(
+                        "Encryption succesfully done and source file removed",
+                        &report.merge(removal_report),
+                    ),
+                    Err(reason) => output.failure(reason),
+                }
+            } else {
+                output.success("Encryption succesfully done", &report);
             }
         }
         ("decrypt", Some(sub_matches)) => {
             let should_override = sub_matches.is_present("override");
-            if should_override {
+            let should_dry_run = sub_matches.is_present("dry-run");
+            if should_override && !should_dry_run {
                 confirm_override!(
+                    prompting,
+                    output,
                     "This operation will {OVERRIDE} the existing plain file.",
                     "This is {UNRECOVERABLE}, please be sure what you are about to do."
                 );
             }
 
-            let profile = sub_matches.value_of("profile").unwrap().to_owned();
             let file_path = sub_matches.value_of("file").unwrap().to_owned();
             let dest_dir = sub_matches.value_of("dest").unwrap().to_owned();
+            let options = DecryptOptions {
+                label: sub_matches.value_of("label").map(|label| label.to_owned()),
+            };
+
+            if should_dry_run {
+                return match plan_decrypt(&file_path, &dest_dir, should_override) {
+                    Ok(plan) => output.plan(&plan),
+                    Err(reason) => output.failure(reason),
+                };
+            }
+
+            // Files protected by a passphrase say so in their header, and need no keys at all
+            if let Ok(true) = is_passphrase_file(&file_path) {
+                let passphrase = match read_secret_value(&prompting, "Passphrase", false) {
+                    Ok(passphrase) => passphrase,
+                    Err(reason) => return output.failure(reason),
+                };
+                return match decrypt_with_passphrase(
+                    &file_path,
+                    &passphrase,
+                    &dest_dir,
+                    should_override,
+                    &options,
+                ) {
+                    Ok(report) => output.success("Decryption succesfully done", &report),
+                    Err(reason) => output.failure(reason),
+                };
+            }
+
+            let result = match sub_matches.value_of("ssh-key") {
+                Some(key_path) => {
+                    let key_path = key_path.to_owned();
+                    let passphrase = match ssh::is_encrypted_key(&key_path) {
+                        Ok(true) => {
+                            match read_secret_value(&prompting, "SSH key passphrase", false) {
+                                Ok(passphrase) => Some(passphrase),
+                                Err(reason) => return output.failure(reason),
+                            }
+                        }
+                        Ok(false) => None,
+                        Err(reason) => return output.failure(reason),
+                    };
+                    ssh::decrypt(
+                        &key_path,
+                        passphrase.as_ref(),
+                        &file_path,
+                        &dest_dir,
+                        should_override,
+                        &options,
+                    )
+                }
+                None => match sub_matches.value_of("profile") {
+                    Some(profile) => decrypt_with_options(
+                        &profile.to_owned(),
+                        &file_path,
+                        &dest_dir,
+                        should_override,
+                        &options,
+                    ),
+                    None => Err(AnyError::without_parent(
+                        "Decryption needs a profile, unless done with an SSH key or a passphrase",
+                    )),
+                },
+            };
+            match result {
+                Ok(report) => output.success("Decryption succesfully done", &report),
+                Err(reason) => output.failure(reason),
+            }
+        }
+        ("edit", Some(sub_matches)) => {
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let file_path = sub_matches.value_of("file").unwrap().to_owned();
+            let editor = env::var("VISUAL")
+                .or_else(|_| env::var("EDITOR"))
+                .unwrap_or_else(|_| "vi".to_owned());
+
+            let options = DecryptOptions {
+                label: sub_matches.value_of("label").map(|label| label.to_owned()),
+            };
+
+            match edit::run(&profile, &file_path, &editor, &options) {
+                Ok(report) if report.written.is_empty() => {
+                    output.success("Nothing changed, so nothing was encrypted", &report)
+                }
+                Ok(report) => output.success("Changes succesfully encrypted", &report),
+                Err(reason) => output.failure(reason),
+            }
+        }
+        ("exec", Some(sub_matches)) => {
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let file_path = sub_matches.value_of("file").unwrap().to_owned();
+            let command: Vec<String> = sub_matches
+                .values_of("command")
+                .unwrap()
+                .map(|arg| arg.to_owned())
+                .collect();
+            let options = DecryptOptions {
+                label: sub_matches.value_of("label").map(|label| label.to_owned()),
+            };
+
+            match exec::run(&profile, &file_path, &command, &options) {
+                Ok(code) => process::exit(code),
+                Err(reason) => output.failure(reason),
+            }
+        }
+        ("secret", Some(secret_matches)) => match secret_matches.subcommand() {
+            ("set", Some(sub_matches)) => {
+                let should_override = sub_matches.is_present("override");
+                if should_override {
+                    confirm_override!(
+                        prompting,
+                        output,
+                        "This operation will {OVERRIDE} the existing secret.",
+                        "This is {UNRECOVERABLE}, please be sure what you are about to do."
+                    );
+                }
+
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let secret_path = sub_matches.value_of("path").unwrap().to_owned();
+                let value = match read_secret_value(&prompting, "Secret value", true) {
+                    Ok(value) => value,
+                    Err(reason) => return output.failure(reason),
+                };
+
+                match secret_store::set(&profile, &secret_path, &value, should_override) {
+                    Ok(report) => output.success("Secret succesfully set", &report),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            ("get", Some(sub_matches)) => {
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let secret_path = sub_matches.value_of("path").unwrap().to_owned();
+
+                match secret_store::get(&profile, &secret_path) {
+                    Ok(value) => output.value(&value),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            ("list", Some(sub_matches)) => {
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let prefix = sub_matches.value_of("prefix").unwrap_or("").to_owned();
+
+                match secret_store::list(&profile, &prefix) {
+                    Ok(secret_paths) => output.listing(&secret_paths),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            ("rm", Some(sub_matches)) => {
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let secret_path = sub_matches.value_of("path").unwrap().to_owned();
+
+                match secret_store::remove(&profile, &secret_path) {
+                    Ok(report) => output.success("Secret succesfully removed", &report),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            _ => app.print_help().unwrap(),
+        },
+        ("git-filter", Some(sub_matches)) => {
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let file_path = sub_matches.value_of("path").unwrap().to_owned();
+            let result = {
+                let stdin = io::stdin();
+                let stdout = io::stdout();
+                let mut input = stdin.lock();
+                let mut filtered = stdout.lock();
+
+                match sub_matches.value_of("mode") {
+                    Some("clean") => {
+                        git_filter::clean(&profile, &file_path, &mut input, &mut filtered)
+                    }
+                    _ => git_filter::smudge(&profile, &file_path, &mut input, &mut filtered),
+                }
+            };
+            // Git discards the filtered content when the filter fails, so the report can use stdout
+            if let Err(reason) = result {
+                output.failure(reason);
+            }
+        }
+        ("git-setup", Some(sub_matches)) => {
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let repo_dir = sub_matches.value_of("repo").unwrap().to_owned();
+            let patterns: Vec<String> = sub_matches
+                .values_of("pattern")
+                .unwrap()
+                .map(|pattern| pattern.to_owned())
+                .collect();
+            let program = match env::current_exe() {
+                Ok(path) => format!("{}", path.display()),
+                Err(_) => "moy-sekret".to_owned(),
+            };
+
+            match git_filter::setup(&profile, &repo_dir, &patterns, &program) {
+                Ok(report) => output.success("Git filter set up with success", &report),
+                Err(reason) => output.failure(reason),
+            }
+        }
+        ("backup-key", Some(sub_matches)) => {
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let (shares, threshold) = match (
+                parse_count(&sub_matches, "shares"),
+                parse_count(&sub_matches, "threshold"),
+            ) {
+                (Ok(shares), Ok(threshold)) => (shares.unwrap_or(1), threshold.unwrap_or(1)),
+                (Err(reason), _) | (_, Err(reason)) => return output.failure(reason),
+            };
+
+            match backup::export(&profile, shares, threshold) {
+                Ok(blocks) => output.listing(&blocks),
+                Err(reason) => output.failure(reason),
+            }
+        }
+        ("restore-key", Some(sub_matches)) => {
+            let should_override = sub_matches.is_present("override");
+            let should_dry_run = sub_matches.is_present("dry-run");
+            if should_override && !should_dry_run {
+                confirm_override!(
+                    prompting,
+                    output,
+                    "This operation will {OVERRIDE} any key you have got with this profile.",
+                    "This is {UNRECOVERABLE} and you may lose access to any file you have encrypted with those keys."
+                );
+            }
+
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let storage_dir = sub_matches.value_of("dir").unwrap().to_owned();
+
+            if should_dry_run {
+                return match backup::plan_restore(&profile, &storage_dir, should_override) {
+                    Ok(plan) => output.plan(&plan),
+                    Err(reason) => output.failure(reason),
+                };
+            }
+
+            let backup_texts = match read_backup_texts(&sub_matches) {
+                Ok(texts) => texts,
+                Err(reason) => return output.failure(reason),
+            };
+
+            match backup::restore(&profile, &storage_dir, &backup_texts, should_override) {
+                Ok(report) => output.success(
+                    &format!(
+                        "Key pair restored with success at {} directory",
+                        &storage_dir
+                    ),
+                    &report,
+                ),
+                Err(reason) => output.failure(reason),
+            }
+        }
+        ("age", Some(age_matches)) => match age_matches.subcommand() {
+            ("encrypt", Some(sub_matches)) => {
+                let should_override = sub_matches.is_present("override");
+                let should_dry_run = sub_matches.is_present("dry-run");
+                if should_override && !should_dry_run {
+                    confirm_override!(
+                        prompting,
+                        output,
+                        "This operation will {OVERRIDE} the existing encrypted file.",
+                        "This is {UNRECOVERABLE}, please be sure what you are about to do."
+                    );
+                }
+
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let file_path = sub_matches.value_of("file").unwrap().to_owned();
+
+                if should_dry_run {
+                    return match age_format::plan_encrypt(&profile, &file_path, should_override) {
+                        Ok(plan) => output.plan(&plan),
+                        Err(reason) => output.failure(reason),
+                    };
+                }
+
+                let passphrase = if sub_matches.is_present("passphrase") {
+                    match read_secret_value(&prompting, "Passphrase", true) {
+                        Ok(passphrase) => Some(passphrase),
+                        Err(reason) => return output.failure(reason),
+                    }
+                } else {
+                    None
+                };
+
+                let options = AgeOptions {
+                    recipients: sub_matches
+                        .values_of("recipient")
+                        .map(|recipients| {
+                            recipients.map(|recipient| recipient.to_owned()).collect()
+                        })
+                        .unwrap_or_default(),
+                    passphrase,
+                    armor: sub_matches.is_present("armor"),
+                };
+
+                match age_format::encrypt(&profile, &file_path, &options, should_override) {
+                    Ok(report) => output.success("Encryption succesfully done", &report),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            ("decrypt", Some(sub_matches)) => {
+                let should_override = sub_matches.is_present("override");
+                if should_override {
+                    confirm_override!(
+                        prompting,
+                        output,
+                        "This operation will {OVERRIDE} the existing plain file.",
+                        "This is {UNRECOVERABLE}, please be sure what you are about to do."
+                    );
+                }
+
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let file_path = sub_matches.value_of("file").unwrap().to_owned();
+                let dest_dir = sub_matches.value_of("dest").unwrap().to_owned();
+                let passphrase = if sub_matches.is_present("passphrase") {
+                    match read_secret_value(&prompting, "Passphrase", false) {
+                        Ok(passphrase) => Some(passphrase),
+                        Err(reason) => return output.failure(reason),
+                    }
+                } else {
+                    None
+                };
+
+                match age_format::decrypt(
+                    &profile,
+                    &file_path,
+                    &dest_dir,
+                    passphrase.as_ref(),
+                    should_override,
+                ) {
+                    Ok(report) => output.success("Decryption succesfully done", &report),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            ("recipient", Some(sub_matches)) => {
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+
+                match age_format::recipient(&profile) {
+                    Ok(recipient) => output.listing(&[recipient]),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            _ => app.print_help().unwrap(),
+        },
+        ("batch", Some(batch_matches)) => match batch_matches.subcommand() {
+            ("encrypt", Some(sub_matches)) => {
+                let should_override = sub_matches.is_present("override");
+                let should_dry_run = sub_matches.is_present("dry-run");
+                if should_override && !should_dry_run {
+                    confirm_override!(
+                        prompting,
+                        output,
+                        "This operation will {OVERRIDE} any existing encrypted file.",
+                        "This is {UNRECOVERABLE}, please be sure what you are about to do."
+                    );
+                }
+
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let patterns: Vec<String> = sub_matches
+                    .values_of("files")
+                    .unwrap()
+                    .map(|pattern| pattern.to_owned())
+                    .collect();
+                if should_dry_run {
+                    return match batch::plan_encrypt(&profile, &patterns, should_override) {
+                        Ok(outcomes) => output.planned_outcomes(&outcomes),
+                        Err(reason) => output.failure(reason),
+                    };
+                }
+
+                let jobs = match parse_jobs(&sub_matches) {
+                    Ok(jobs) => jobs,
+                    Err(reason) => return output.failure(reason),
+                };
+
+                let options = EncryptOptions {
+                    compression: sub_matches
+                        .value_of("compression")
+                        .map(|compression| compression.parse().unwrap()),
+                    padding: sub_matches
+                        .value_of("padding")
+                        .map(|padding| padding.parse().unwrap()),
+                    armor: sub_matches.is_present("armor"),
+                    recipients: sub_matches
+                        .values_of("to")
+                        .map(|recipients| {
+                            recipients.map(|recipient| recipient.to_owned()).collect()
+                        })
+                        .unwrap_or_default(),
+                    label: sub_matches.value_of("label").map(|label| label.to_owned()),
+                };
+
+                match batch::encrypt(&profile, &patterns, should_override, &options, jobs) {
+                    Ok(outcomes) => output.outcomes(&outcomes),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            ("decrypt", Some(sub_matches)) => {
+                let should_override = sub_matches.is_present("override");
+                let should_dry_run = sub_matches.is_present("dry-run");
+                if should_override && !should_dry_run {
+                    confirm_override!(
+                        prompting,
+                        output,
+                        "This operation will {OVERRIDE} any existing plain file.",
+                        "This is {UNRECOVERABLE}, please be sure what you are about to do."
+                    );
+                }
+
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                let patterns: Vec<String> = sub_matches
+                    .values_of("files")
+                    .unwrap()
+                    .map(|pattern| pattern.to_owned())
+                    .collect();
+                let dest_dir = sub_matches.value_of("dest").unwrap().to_owned();
+                if should_dry_run {
+                    return match batch::plan_decrypt(&patterns, &dest_dir, should_override) {
+                        Ok(outcomes) => output.planned_outcomes(&outcomes),
+                        Err(reason) => output.failure(reason),
+                    };
+                }
+
+                let jobs = match parse_jobs(&sub_matches) {
+                    Ok(jobs) => jobs,
+                    Err(reason) => return output.failure(reason),
+                };
+
+                let options = DecryptOptions {
+                    label: sub_matches.value_of("label").map(|label| label.to_owned()),
+                };
+
+                match batch::decrypt(
+                    &profile,
+                    &patterns,
+                    &dest_dir,
+                    should_override,
+                    &options,
+                    jobs,
+                ) {
+                    Ok(outcomes) => output.outcomes(&outcomes),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            _ => app.print_help().unwrap(),
+        },
+        ("watch", Some(sub_matches)) => {
+            let should_remove_source = sub_matches.is_present("remove-source");
+            if should_remove_source {
+                confirm_override!(
+                    prompting,
+                    output,
+                    "This operation will {OVERRIDE} and delete every plain file once encrypted.",
+                    "This is {UNRECOVERABLE}, so be sure you can still decrypt them later."
+                );
+            }
+
+            let profile = sub_matches.value_of("profile").unwrap().to_owned();
+            let dir = sub_matches.value_of("dir").unwrap().to_owned();
 
-            match decrypt(&profile, &file_path, &dest_dir, should_override) {
-                Ok(()) => println!("Decryption succesfully done"),
-                Err(reason) => generic_exit_with_error(reason),
+            let options = EncryptOptions {
+                compression: sub_matches
+                    .value_of("compression")
+                    .map(|compression| compression.parse().unwrap()),
+                padding: sub_matches
+                    .value_of("padding")
+                    .map(|padding| padding.parse().unwrap()),
+                armor: sub_matches.is_present("armor"),
+                recipients: sub_matches
+                    .values_of("to")
+                    .map(|recipients| recipients.map(|recipient| recipient.to_owned()).collect())
+                    .unwrap_or_default(),
+                label: sub_matches.value_of("label").map(|label| label.to_owned()),
+            };
+
+            output.warn(&format!("Watching {}, press Ctrl+C to stop", dir));
+            let result = watch::run(&profile, &dir, should_remove_source, &options, |outcome| {
+                output.outcome(&outcome);
+                true
+            });
+            if let Err(reason) = result {
+                output.failure(reason);
+            }
+        }
+        ("log", Some(log_matches)) => match log_matches.subcommand() {
+            ("show", Some(sub_matches)) => {
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+
+                match audit::show(&profile) {
+                    Ok(entries) => output.entries(&entries),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            ("verify", Some(sub_matches)) => {
+                let profile = sub_matches.value_of("profile").unwrap().to_owned();
+
+                match audit::verify(&profile) {
+                    Ok(verification) => output.success(
+                        &format!(
+                            "Log succesfully verified, {} entries of which {} signed",
+                            verification.entries, verification.signed
+                        ),
+                        &Report::default(),
+                    ),
+                    Err(reason) => output.failure(reason),
+                }
+            }
+            _ => app.print_help().unwrap(),
+        },
+        ("agent", Some(agent_matches)) => {
+            let (agent_command, sub_matches) = match agent_matches.subcommand() {
+                (name, Some(sub_matches)) => (name, sub_matches),
+                _ => return app.print_help().unwrap(),
+            };
+            let socket_path = sub_matches
+                .value_of("socket")
+                .map(|socket_path| socket_path.to_owned())
+                .unwrap_or_else(agent::socket_path);
+            let ttl_secs = match parse_ttl(&sub_matches) {
+                Ok(ttl_secs) => ttl_secs,
+                Err(reason) => return output.failure(reason),
+            };
+
+            match agent_command {
+                "start" => {
+                    output.warn(&format!("Agent listening on {}", socket_path));
+                    let default_ttl_secs = ttl_secs.unwrap_or(agent::DEFAULT_TTL_SECS);
+                    match agent::start(&socket_path, default_ttl_secs) {
+                        Ok(_) => output.success("Agent succesfully stopped", &Report::default()),
+                        Err(reason) => output.failure(reason),
+                    }
+                }
+                "add" => {
+                    let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                    match agent::add(&socket_path, &profile, ttl_secs) {
+                        Ok(report) => output.success("Keys succesfully added to agent", &report),
+                        Err(reason) => output.failure(reason),
+                    }
+                }
+                "remove" => {
+                    let profile = sub_matches.value_of("profile").unwrap().to_owned();
+                    match agent::remove(&socket_path, &profile) {
+                        Ok(report) => {
+                            output.success("Keys succesfully removed from agent", &report)
+                        }
+                        Err(reason) => output.failure(reason),
+                    }
+                }
+                "list" => match agent::list(&socket_path) {
+                    Ok(profile_names) => output.listing(&profile_names),
+                    Err(reason) => output.failure(reason),
+                },
+                "stop" => match agent::stop(&socket_path) {
+                    Ok(report) => output.success("Agent succesfully stopped", &report),
+                    Err(reason) => output.failure(reason),
+                },
+                _ => unreachable!(),
             }
         }
         ("", None) => app.print_help().unwrap(),
@@ -176,6 +1995,171 @@ fn main() {
     }
 }
 
+fn parse_count(matches: &ArgMatches, name: &str) -> Result<Option<u8>, AnyError> {
+    match matches.value_of(name) {
+        Some(raw_count) => match raw_count.parse::<u8>() {
+            Ok(count) => Ok(Some(count)),
+            Err(reason) => Err(AnyError::new(
+                &format!("Could not parse --{} as a number from 0 to 255", name),
+                Some(Box::new(reason)),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+fn read_backup_texts(matches: &ArgMatches) -> Result<Vec<SecretBytes>, AnyError> {
+    let file_paths = match matches.values_of("backup") {
+        Some(file_paths) => file_paths,
+        None => {
+            return match SecretBytes::read_from(&mut io::stdin(), 0) {
+                Ok(text) => Ok(vec![text]),
+                Err(reason) => Err(AnyError::new(
+                    "Could not read backup from stdin",
+                    Some(Box::new(reason)),
+                )),
+            };
+        }
+    };
+
+    let mut texts = Vec::new();
+    for file_path in file_paths {
+        match std::fs::read(file_path) {
+            Ok(text) => texts.push(SecretBytes::new(text)),
+            Err(reason) => {
+                return Err(AnyError::new(
+                    &format!("Could not read backup file {}", file_path),
+                    Some(Box::new(reason)),
+                ))
+            }
+        }
+    }
+    Ok(texts)
+}
+
+fn parse_jobs(matches: &ArgMatches) -> Result<Option<usize>, AnyError> {
+    match matches.value_of("jobs") {
+        Some(raw_jobs) => match raw_jobs.parse::<usize>() {
+            Ok(jobs) if jobs > 0 => Ok(Some(jobs)),
+            Ok(_) => Err(AnyError::without_parent(
+                "Could not use --jobs of 0, as at least one file must be worked on",
+            )),
+            Err(reason) => Err(AnyError::new(
+                "Could not parse --jobs as a number of files",
+                Some(Box::new(reason)),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+fn parse_ttl(matches: &ArgMatches) -> Result<Option<u64>, AnyError> {
+    match matches.value_of("ttl") {
+        Some(raw_ttl) => match raw_ttl.parse::<u64>() {
+            Ok(ttl_secs) => Ok(Some(ttl_secs)),
+            Err(reason) => Err(AnyError::new(
+                "Could not parse --ttl as a number of seconds",
+                Some(Box::new(reason)),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+fn read_secret_value(
+    prompting: &Prompting,
+    prompt: &str,
+    should_confirm: bool,
+) -> Result<SecretBytes, AnyError> {
+    if prompting.can_prompt() && Term::stdout().is_term() {
+        let mut password = Password::new();
+        password.with_prompt(prompt);
+        if should_confirm {
+            password.with_confirmation(
+                format!("Confirm {}", prompt.to_lowercase()),
+                "Values do not match",
+            );
+        }
+        return match password.interact() {
+            Ok(value) => Ok(SecretBytes::new(value.into_bytes())),
+            Err(reason) => Err(AnyError::new(
+                &format!("Could not read {}", prompt.to_lowercase()),
+                Some(Box::new(reason)),
+            )),
+        };
+    }
+
+    match SecretBytes::read_from(&mut io::stdin(), 0) {
+        Ok(value) => Ok(value),
+        Err(reason) => Err(AnyError::new(
+            &format!("Could not read {} from stdin", prompt.to_lowercase()),
+            Some(Box::new(reason)),
+        )),
+    }
+}
+
+fn plan_lines(plan: &Plan) -> Vec<String> {
+    let created = plan
+        .created
+        .iter()
+        .map(|path| format!("create     {}", path));
+    let overwritten = plan
+        .overwritten
+        .iter()
+        .map(|path| format!("overwrite  {}", path));
+    let removed = plan
+        .removed
+        .iter()
+        .map(|path| format!("remove     {}", path));
+    created.chain(overwritten).chain(removed).collect()
+}
+
+// Days to civil date as in the proleptic Gregorian calendar, so no time crate is needed
+fn format_time(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs_of_day = unix_secs % 86_400;
+
+    let shifted_days = days + 719_468;
+    let era = shifted_days.div_euclid(146_097);
+    let day_of_era = shifted_days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+// The change is done by then, so these go to stderr next to the success and not in its place
+fn print_warnings(report: &Report) {
+    for warning in &report.warnings {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+fn error_json(reason: &AnyError) -> serde_json::Value {
+    json!({
+        "kind": reason.kind(),
+        "message": reason.to_string(),
+        "chain": reason.chain(),
+    })
+}
+
 fn generic_exit_with_error(reason: AnyError) {
     // Should give it a real better implementation any time soon
     exit_with_error("Something went really bad here", reason);
@@ -0,0 +1,254 @@
+use crate::SecretBytes;
+use console::{Style, Term};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const CHUNK_LEN: usize = 1024 * 1024;
+const PROGRESS_DELAY: Duration = Duration::from_millis(250);
+const PROGRESS_REFRESH: Duration = Duration::from_millis(100);
+const PROGRESS_WIDTH: usize = 30;
+
+// Whoever installs one decides how progress looks, if shown at all
+static REPORTER: OnceLock<Box<dyn Reporter>> = OnceLock::new();
+
+// Custom types
+//
+
+pub trait Reporter: Send + Sync {
+    fn start(&self, action: &str, total_files: u64, total_bytes: u64);
+    fn advance(&self, bytes: u64);
+    fn complete_file(&self);
+    fn finish(&self);
+}
+
+// Drawn on stderr and wiped once done, so stdout is left to whatever the command prints
+pub struct ProgressBar {
+    term: Term,
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Default)]
+struct ProgressState {
+    action: String,
+    total_files: u64,
+    total_bytes: u64,
+    done_files: u64,
+    done_bytes: u64,
+    started_at: Option<Instant>,
+    drawn_at: Option<Instant>,
+}
+
+// Tells how far reading got, a chunk at a time, to whoever shows progress
+struct ReportingReader<'a> {
+    file: File,
+    reporter: Option<&'a dyn Reporter>,
+}
+
+impl ProgressBar {
+    pub fn for_output(is_quiet: bool, is_json: bool) -> Option<ProgressBar> {
+        let term = Term::stderr();
+        if !should_show(is_quiet, is_json, term.is_term()) {
+            return None;
+        }
+
+        Some(ProgressBar {
+            term,
+            state: Mutex::new(ProgressState::default()),
+        })
+    }
+
+    fn draw(&self, state: &mut ProgressState) {
+        let started_at = match state.started_at {
+            Some(started_at) => started_at,
+            None => return,
+        };
+
+        // Quick operations are over before a bar could be of any help
+        let now = Instant::now();
+        let elapsed = now.duration_since(started_at);
+        if elapsed < PROGRESS_DELAY {
+            return;
+        }
+        if let Some(drawn_at) = state.drawn_at {
+            if now.duration_since(drawn_at) < PROGRESS_REFRESH {
+                return;
+            }
+        }
+
+        state.drawn_at = Some(now);
+        let line = format_progress(&state, elapsed);
+        let _ = self
+            .term
+            .clear_line()
+            .and_then(|_| self.term.write_str(&line));
+    }
+}
+
+impl Reporter for ProgressBar {
+    fn start(&self, action: &str, total_files: u64, total_bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        *state = ProgressState {
+            action: action.to_owned(),
+            total_files,
+            total_bytes,
+            started_at: Some(Instant::now()),
+            ..ProgressState::default()
+        };
+    }
+
+    fn advance(&self, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.done_bytes += bytes;
+        self.draw(&mut state);
+    }
+
+    fn complete_file(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.done_files += 1;
+        self.draw(&mut state);
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.drawn_at.is_some() {
+            let _ = self.term.clear_line();
+        }
+        *state = ProgressState::default();
+    }
+}
+
+impl<'a> Read for ReportingReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk_len = buf.len().min(CHUNK_LEN);
+        let read_len = self.file.read(&mut buf[..chunk_len])?;
+        if let Some(reporter) = self.reporter {
+            reporter.advance(read_len as u64);
+        }
+        Ok(read_len)
+    }
+}
+
+// Ends the task however the operation returns, so nothing is left half drawn
+pub(crate) struct Task;
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        if let Some(reporter) = REPORTER.get() {
+            reporter.finish();
+        }
+    }
+}
+
+// Entrypoint functions
+//
+
+pub fn install(reporter: Box<dyn Reporter>) {
+    let _ = REPORTER.set(reporter);
+}
+
+// Scripts asking for quiet or JSON output get nothing on stderr they did not ask for
+pub fn should_show(is_quiet: bool, is_json: bool, is_term: bool) -> bool {
+    !is_quiet && !is_json && is_term
+}
+
+// Business functions
+//
+
+// Files are read in, then their result is written out, unless they are only read to be checked
+pub(crate) fn start(action: &str, file_paths: &[&String], should_write: bool) -> Task {
+    if let Some(reporter) = REPORTER.get() {
+        let total_bytes: u64 = file_paths
+            .iter()
+            .filter_map(|file_path| fs::metadata(file_path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        let passes = if should_write { 2 } else { 1 };
+        reporter.start(action, file_paths.len() as u64, total_bytes * passes);
+    }
+    Task
+}
+
+// For steps done in one go, counted as much as the file they stand for
+pub(crate) fn advance_by_file(file_path: &String) {
+    if let Some(reporter) = REPORTER.get() {
+        if let Ok(metadata) = fs::metadata(file_path) {
+            reporter.advance(metadata.len());
+        }
+    }
+}
+
+pub(crate) fn complete_file() {
+    if let Some(reporter) = REPORTER.get() {
+        reporter.complete_file();
+    }
+}
+
+// Same as fs::read, only telling how far it got along the way and wiping whatever it outgrows
+pub(crate) fn read_file(file_path: &String) -> io::Result<SecretBytes> {
+    let file = File::open(file_path)?;
+    // A byte to spare, so reaching the end of a file that did not change never grows the buffer
+    let capacity = file.metadata()?.len() as usize + 1;
+    let mut reader = ReportingReader {
+        file,
+        reporter: REPORTER.get().map(|reporter| reporter.as_ref()),
+    };
+    SecretBytes::read_from(&mut reader, capacity)
+}
+
+// Helper functions
+//
+
+fn format_progress(state: &ProgressState, elapsed: Duration) -> String {
+    // Bytes tell it best, yet a batch of empty files only has its count to go by
+    let ratio = if state.total_bytes > 0 {
+        state.done_bytes as f64 / state.total_bytes as f64
+    } else if state.total_files > 0 {
+        state.done_files as f64 / state.total_files as f64
+    } else {
+        0.0
+    };
+    let ratio = ratio.min(1.0);
+
+    let done_width = (ratio * PROGRESS_WIDTH as f64) as usize;
+    let mut line = format!(
+        "{} [{}{}] {}/{}",
+        state.action,
+        Style::new().green().apply_to("#".repeat(done_width)),
+        Style::new()
+            .dim()
+            .apply_to("-".repeat(PROGRESS_WIDTH - done_width)),
+        format_bytes(state.done_bytes.min(state.total_bytes)),
+        format_bytes(state.total_bytes)
+    );
+    if state.total_files > 1 {
+        line.push_str(&format!(
+            ", {}/{} files",
+            state.done_files, state.total_files
+        ));
+    }
+    if ratio > 0.0 && ratio < 1.0 {
+        let eta_secs = elapsed.as_secs_f64() * (1.0 - ratio) / ratio;
+        line.push_str(&format!(", ETA {}s", eta_secs.ceil() as u64));
+    }
+    line
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit_index = 0;
+    while value >= 1024.0 && unit_index < units.len() - 1 {
+        value /= 1024.0;
+        unit_index += 1;
+    }
+
+    if unit_index == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", value, units[unit_index])
+    }
+}
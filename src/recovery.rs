@@ -0,0 +1,69 @@
+use crate::{error, error_without_parent, AnyError, SecretBytes};
+use bip39::Mnemonic;
+use data_encoding::HEXLOWER_PERMISSIVE;
+use sodiumoxide::randombytes::randombytes;
+use sodiumoxide::utils::memzero;
+
+const PHRASE_ENTROPY_LEN: usize = 32;
+const MIN_SEED_LEN: usize = 16;
+
+// Entrypoint functions
+//
+
+pub fn generate_phrase() -> Result<SecretBytes, AnyError> {
+    let entropy = SecretBytes::new(randombytes(PHRASE_ENTROPY_LEN));
+    match Mnemonic::from_entropy(&entropy) {
+        Ok(mnemonic) => Ok(SecretBytes::new(mnemonic.to_string().into_bytes())),
+        Err(reason) => error("Could not generate recovery phrase", reason),
+    }
+}
+
+pub fn seed_from_phrase(phrase: &[u8]) -> Result<SecretBytes, AnyError> {
+    let phrase = match std::str::from_utf8(phrase) {
+        Ok(text) => text,
+        Err(reason) => return error("Recovery phrase is not valid UTF-8", reason),
+    };
+
+    // Words are often written down in capitals or spread over several lines
+    let words: Vec<String> = phrase
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect();
+    let normalized = words.join(" ");
+    let parsed = Mnemonic::parse_normalized(&normalized);
+
+    // Handed over to secret buffers only to be wiped whole, spare capacity included
+    for word in words {
+        drop(SecretBytes::new(word.into_bytes()));
+    }
+    drop(SecretBytes::new(normalized.into_bytes()));
+
+    let mnemonic = match parsed {
+        Ok(mnemonic) => mnemonic,
+        Err(reason) => return error("Recovery phrase is not valid", reason),
+    };
+
+    let mut raw_seed = mnemonic.to_seed_normalized("");
+    let seed = SecretBytes::new(raw_seed.to_vec());
+    memzero(&mut raw_seed);
+    Ok(seed)
+}
+
+pub fn seed_from_hex(hex_seed: &[u8]) -> Result<SecretBytes, AnyError> {
+    let trimmed: Vec<u8> = hex_seed
+        .iter()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .cloned()
+        .collect();
+    let trimmed = SecretBytes::new(trimmed);
+
+    let seed = match HEXLOWER_PERMISSIVE.decode(&trimmed) {
+        Ok(raw_vec) => SecretBytes::new(raw_vec),
+        Err(reason) => return error("Seed is not valid hex", reason),
+    };
+
+    if seed.len() < MIN_SEED_LEN {
+        return error_without_parent(&format!("Seed must have at least {} bytes", MIN_SEED_LEN));
+    }
+    Ok(seed)
+}
@@ -0,0 +1,229 @@
+use crate::storage::Storage;
+use crate::{
+    audit, check_context, check_relative_name, deserialize_cipher, error, error_without_parent,
+    open_cipher, open_storage, read_keypair, read_profile, seal_content, serialize_cipher,
+    AnyError, Context, EncryptOptions, Header, Profile, Report, SecretBytes,
+};
+use sodiumoxide::crypto::box_;
+use std::path::Path;
+
+const SECRETS_DIR: &str = "secrets";
+
+// Entrypoint functions
+//
+
+pub fn set(
+    profile_name: &String,
+    secret_path: &String,
+    value: &[u8],
+    should_override: bool,
+) -> Result<Report, AnyError> {
+    if let Err(reason) = check_secret_path(&secret_path) {
+        return error(
+            "Setting secret failed because its path is not valid",
+            reason,
+        );
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Setting secret failed while reading user profile", reason),
+    };
+
+    let storage = open_storage(&profile);
+    if !should_override && storage.exists(&get_secret_name(&secret_path)) {
+        return error_without_parent("Setting secret failed because secret already exists");
+    }
+
+    let report = match save_secret(
+        storage.as_ref(),
+        &profile,
+        &secret_path,
+        &value,
+        should_override,
+    ) {
+        Ok(report) => report,
+        Err(reason) => return error("Setting secret failed while saving it", reason),
+    };
+
+    Ok(audit::record_done(
+        &profile,
+        None,
+        "secret-set",
+        &secret_path,
+        report,
+    ))
+}
+
+pub fn get(profile_name: &String, secret_path: &String) -> Result<SecretBytes, AnyError> {
+    if let Err(reason) = check_secret_path(&secret_path) {
+        return error(
+            "Getting secret failed because its path is not valid",
+            reason,
+        );
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Getting secret failed while reading user profile", reason),
+    };
+
+    let storage = open_storage(&profile);
+    if !storage.exists(&get_secret_name(&secret_path)) {
+        return error_without_parent("Getting secret failed because secret does not exists");
+    }
+
+    let value = match open_secret(storage.as_ref(), &profile, &secret_path) {
+        Ok(value) => value,
+        Err(reason) => return error("Getting secret failed while decrypting it", reason),
+    };
+
+    match audit::record(
+        &profile,
+        None,
+        "secret-get",
+        &secret_path,
+        &Report::default(),
+    ) {
+        Ok(_) => Ok(value),
+        Err(reason) => error(
+            "Getting secret failed while recording it to the log",
+            reason,
+        ),
+    }
+}
+
+pub fn list(profile_name: &String, prefix: &String) -> Result<Vec<String>, AnyError> {
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Listing secrets failed while reading user profile", reason),
+    };
+
+    let names = match open_storage(&profile).list() {
+        Ok(names) => names,
+        Err(reason) => return error("Listing secrets failed while walking storage", reason),
+    };
+
+    let mut secret_paths: Vec<String> = names.iter().filter_map(get_secret_path).collect();
+    secret_paths.retain(|secret_path| secret_path.starts_with(prefix.as_str()));
+    secret_paths.sort();
+    Ok(secret_paths)
+}
+
+pub fn remove(profile_name: &String, secret_path: &String) -> Result<Report, AnyError> {
+    if let Err(reason) = check_secret_path(&secret_path) {
+        return error(
+            "Removing secret failed because its path is not valid",
+            reason,
+        );
+    }
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Removing secret failed while reading user profile", reason),
+    };
+
+    let storage = open_storage(&profile);
+    let secret_name = get_secret_name(&secret_path);
+    if !storage.exists(&secret_name) {
+        return error_without_parent("Removing secret failed because secret does not exists");
+    }
+
+    match storage.delete(&secret_name) {
+        Ok(_) => (),
+        Err(reason) => return error("Removing secret failed while deleting it", reason),
+    };
+
+    let report = Report {
+        removed: vec![storage.locate(&secret_name)],
+        ..Report::default()
+    };
+    Ok(audit::record_done(
+        &profile,
+        None,
+        "secret-remove",
+        &secret_path,
+        report,
+    ))
+}
+
+// Business functions
+//
+
+fn save_secret(
+    storage: &dyn Storage,
+    profile: &Profile,
+    secret_path: &String,
+    value: &[u8],
+    should_override: bool,
+) -> Result<Report, AnyError> {
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Could not encrypt secret", reason),
+    };
+
+    // Bound to its path, so one secret file cannot be swapped for another
+    let header = Header {
+        context: Some(Context::for_path(&secret_path, &profile)),
+        ..Header::for_profile(&profile, &EncryptOptions::default())
+    };
+    let cipher = seal_content(&keypair, &header, &value, box_::gen_nonce())?;
+    let cipher_data = serialize_cipher(&cipher)?;
+
+    let secret_name = get_secret_name(&secret_path);
+    let put_result = if should_override {
+        storage.put(&secret_name, &cipher_data)
+    } else {
+        storage.put_new(&secret_name, &cipher_data)
+    };
+    match put_result {
+        Ok(_) => (),
+        Err(reason) => return error("Could not save secret file", reason),
+    };
+
+    Ok(Report {
+        written: vec![storage.locate(&secret_name)],
+        ..Report::default()
+    })
+}
+
+fn open_secret(
+    storage: &dyn Storage,
+    profile: &Profile,
+    secret_path: &String,
+) -> Result<SecretBytes, AnyError> {
+    let keypair = match read_keypair(&profile) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Could not decrypt secret", reason),
+    };
+
+    let cipher_content = match storage.get(&get_secret_name(&secret_path)) {
+        Ok(raw_vec) => raw_vec,
+        Err(reason) => return error("Could not read secret file", reason),
+    };
+
+    let cipher = deserialize_cipher(&cipher_content)?;
+    let value = open_cipher(&keypair, &cipher)?;
+    let context = Context::for_path(&secret_path, &profile);
+    check_context(&cipher, &context, Some(keypair.public_key()))?;
+    Ok(value)
+}
+
+fn check_secret_path(secret_path: &String) -> Result<(), AnyError> {
+    check_relative_name(Path::new(secret_path))?;
+    if secret_path.ends_with('/') {
+        return error_without_parent("Secret path must not end with a slash");
+    }
+    Ok(())
+}
+
+fn get_secret_name(secret_path: &String) -> String {
+    format!("{}/{}.cz", SECRETS_DIR, secret_path)
+}
+
+fn get_secret_path(name: &String) -> Option<String> {
+    name.strip_prefix(SECRETS_DIR)?
+        .strip_prefix('/')?
+        .strip_suffix(".cz")
+        .map(|secret_path| secret_path.to_owned())
+}
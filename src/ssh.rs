@@ -0,0 +1,130 @@
+use crate::{
+    decrypt_file, error, error_without_parent, file_exists, plan_decryption, progress,
+    trim_line_ending, AnyError, Context, DecryptOptions, Keypar, Report, SecretBytes,
+};
+use sodiumoxide::crypto::box_::curve25519xsalsa20poly1305::PublicKey;
+use sodiumoxide::crypto::sign::ed25519;
+use ssh_key::authorized_keys::{AuthorizedKeys, Entry};
+use ssh_key::PrivateKey;
+use std::fs;
+use std::str::FromStr;
+
+// Entrypoint functions
+//
+
+pub fn is_encrypted_key(key_path: &String) -> Result<bool, AnyError> {
+    let private_key = read_private_key(&key_path)?;
+    Ok(private_key.is_encrypted())
+}
+
+pub fn decrypt(
+    key_path: &String,
+    passphrase: Option<&SecretBytes>,
+    file_path: &String,
+    dest_dir: &String,
+    should_override: bool,
+    options: &DecryptOptions,
+) -> Result<Report, AnyError> {
+    plan_decryption(&file_path, &dest_dir, should_override)?;
+
+    let keypair = match read_keypair(&key_path, passphrase) {
+        Ok(keypair) => keypair,
+        Err(reason) => return error("Decryption failed while reading SSH key", reason),
+    };
+
+    let context = Context::for_encrypted(&file_path, None, &options.label)?;
+    let _task = progress::start("Decrypting", &[&file_path], true);
+    match decrypt_file(&keypair, &file_path, &dest_dir, should_override, &context) {
+        Ok(report) => Ok(report),
+        Err(reason) => error("Decryption failed while doing actual decryption", reason),
+    }
+}
+
+// Business functions
+//
+
+pub(crate) fn parse_recipients(recipients: &[String]) -> Result<Vec<PublicKey>, AnyError> {
+    let mut parsed = vec![];
+    for recipient in recipients {
+        // Either an authorized_keys line as is, or a file holding some of them
+        if file_exists(&recipient) {
+            let content = match fs::read_to_string(&recipient) {
+                Ok(content) => content,
+                Err(reason) => return error("Could not read recipients file", reason),
+            };
+            for entry in AuthorizedKeys::new(&content) {
+                match entry {
+                    Ok(entry) => parsed.push(to_curve25519_pk(&entry)?),
+                    Err(reason) => return error("Could not parse recipients file", reason),
+                }
+            }
+        } else {
+            match Entry::from_str(recipient.trim()) {
+                Ok(entry) => parsed.push(to_curve25519_pk(&entry)?),
+                Err(reason) => return error("Could not parse OpenSSH public key", reason),
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn read_keypair(key_path: &String, passphrase: Option<&SecretBytes>) -> Result<Keypar, AnyError> {
+    let mut private_key = read_private_key(&key_path)?;
+    if private_key.is_encrypted() {
+        let passphrase = match passphrase {
+            Some(passphrase) => trim_line_ending(&passphrase),
+            None => return error_without_parent("SSH key is protected by a passphrase"),
+        };
+        private_key = match private_key.decrypt(passphrase) {
+            Ok(decrypted_key) => decrypted_key,
+            Err(reason) => return error("Could not unlock SSH key", reason),
+        };
+    }
+
+    let ssh_keypair = match private_key.key_data().ed25519() {
+        Some(keypair) => keypair,
+        None => return error_without_parent("SSH key is not an Ed25519 one"),
+    };
+
+    // OpenSSH keeps the seed followed by the public key, just like libsodium does
+    let ed25519_sk = ed25519::SecretKey(ssh_keypair.to_bytes());
+    let sk = match ed25519::to_curve25519_sk(&ed25519_sk) {
+        Ok(sk) => sk,
+        Err(_) => return error_without_parent("Could not convert SSH secret key"),
+    };
+    let pk = match ed25519::to_curve25519_pk(&ed25519::PublicKey(ssh_keypair.public.0)) {
+        Ok(pk) => pk,
+        Err(_) => return error_without_parent("Could not convert SSH public key"),
+    };
+
+    Ok(Keypar::new(pk, sk))
+}
+
+// Helper functions
+//
+
+fn read_private_key(key_path: &String) -> Result<PrivateKey, AnyError> {
+    let content = match fs::read(&key_path) {
+        Ok(raw_vec) => SecretBytes::new(raw_vec),
+        Err(reason) => return error("Could not read SSH key file", reason),
+    };
+
+    match PrivateKey::from_openssh(&content) {
+        Ok(private_key) => Ok(private_key),
+        Err(reason) => error("Could not parse OpenSSH private key", reason),
+    }
+}
+
+// Ed25519 points map onto Curve25519 ones, so the same key can receive boxes too
+fn to_curve25519_pk(entry: &Entry) -> Result<PublicKey, AnyError> {
+    let ssh_pk = match entry.public_key().key_data().ed25519() {
+        Some(pk) => pk,
+        None => return error_without_parent("Only ssh-ed25519 keys can be used as recipients"),
+    };
+
+    match ed25519::to_curve25519_pk(&ed25519::PublicKey(ssh_pk.0)) {
+        Ok(pk) => Ok(pk),
+        Err(_) => error_without_parent("Could not convert OpenSSH public key"),
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{check_relative_name, confine_to_dir, error, error_without_parent, AnyError};
+use data_encoding::HEXLOWER;
+use sodiumoxide::randombytes::randombytes;
+use sodiumoxide::utils::memzero;
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// Custom types
+//
+
+// Keys, encrypted files, secrets and the log go through here by name only, so where they end
+// up is up to the backend. Names may have slashes in them, but never leave the storage.
+pub trait Storage: Send + Sync {
+    fn put(&self, name: &str, content: &[u8]) -> Result<(), AnyError>;
+    // Fails rather than replaces when the item is already there, so two writers never clash
+    fn put_new(&self, name: &str, content: &[u8]) -> Result<(), AnyError>;
+    fn append(&self, name: &str, content: &[u8]) -> Result<(), AnyError>;
+    fn get(&self, name: &str) -> Result<Vec<u8>, AnyError>;
+    fn list(&self) -> Result<Vec<String>, AnyError>;
+    fn delete(&self, name: &str) -> Result<(), AnyError>;
+    fn exists(&self, name: &str) -> bool;
+    fn modified(&self, name: &str) -> Result<SystemTime, AnyError>;
+    // Keeps other processes from touching the item until the lock is dropped
+    fn lock(&self, name: &str) -> Result<StorageLock, AnyError>;
+    // Where an item lives, as told to users in reports
+    fn locate(&self, name: &str) -> String;
+}
+
+// Let go of once dropped, along with whatever the backend held it with
+#[derive(Default)]
+pub struct StorageLock {
+    _file: Option<File>,
+}
+
+// Plain files under the profile storage directory, as it has always been
+pub struct LocalStorage {
+    dir: String,
+}
+
+impl LocalStorage {
+    pub fn new(dir: &String) -> LocalStorage {
+        LocalStorage {
+            dir: dir.to_owned(),
+        }
+    }
+
+    // Walks down without following any symlink, so an item never resolves outside the directory
+    fn find(&self, name: &str) -> Result<PathBuf, AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        let mut path = PathBuf::from(&self.dir);
+        for component in Path::new(name).components() {
+            path.push(component);
+            if let Ok(metadata) = fs::symlink_metadata(&path) {
+                if metadata.file_type().is_symlink() {
+                    return error_without_parent(&format!(
+                        "Refusing to follow symlink at {}",
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        Ok(path)
+    }
+
+    fn collect_names(&self, current_dir: &Path, names: &mut Vec<String>) -> Result<(), AnyError> {
+        let entries = match fs::read_dir(current_dir) {
+            Ok(entries) => entries,
+            Err(reason) => return error("Could not read storage directory", reason),
+        };
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(reason) => return error("Could not read storage directory entry", reason),
+            };
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(reason) => return error("Could not read storage directory entry", reason),
+            };
+
+            let entry_path = entry.path();
+            if file_type.is_dir() {
+                self.collect_names(&entry_path, names)?;
+            } else if file_type.is_file() {
+                if let Some(name) = get_relative_name(Path::new(&self.dir), &entry_path) {
+                    names.push(name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Directories only exist for the items in them
+    fn remove_empty_parent_dirs(&self, path: &Path) {
+        let base_dir = Path::new(&self.dir);
+        let mut current_dir = path.parent();
+        while let Some(dir) = current_dir {
+            if dir == base_dir || !dir.starts_with(base_dir) {
+                break;
+            }
+            // Fails, and so stops, as soon as a directory still has something in it
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+            current_dir = dir.parent();
+        }
+    }
+}
+
+impl Storage for LocalStorage {
+    fn put(&self, name: &str, content: &[u8]) -> Result<(), AnyError> {
+        let path = confine_to_dir(&self.dir, Path::new(name))?;
+        replace_file(&path, content)
+    }
+
+    fn put_new(&self, name: &str, content: &[u8]) -> Result<(), AnyError> {
+        let path = confine_to_dir(&self.dir, Path::new(name))?;
+        let temp_path = write_temp_file(&path, content)?;
+
+        // Linking never replaces what is there, and is seen whole or not at all
+        let linked = fs::hard_link(&temp_path, &path);
+        let _ = fs::remove_file(&temp_path);
+        match linked {
+            Ok(_) => Ok(()),
+            Err(reason) => error("Could not save stored file", reason),
+        }
+    }
+
+    fn append(&self, name: &str, content: &[u8]) -> Result<(), AnyError> {
+        let path = confine_to_dir(&self.dir, Path::new(name))?;
+
+        let mut file = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => file,
+            Err(reason) => return error("Could not open stored file", reason),
+        };
+        match file.write_all(content) {
+            Ok(_) => Ok(()),
+            Err(reason) => error("Could not append to stored file", reason),
+        }
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>, AnyError> {
+        let path = self.find(name)?;
+
+        match fs::read(path) {
+            Ok(content) => Ok(content),
+            Err(reason) => error("Could not read stored file", reason),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, AnyError> {
+        let mut names = vec![];
+        self.collect_names(Path::new(&self.dir), &mut names)?;
+
+        names.sort();
+        Ok(names)
+    }
+
+    fn delete(&self, name: &str) -> Result<(), AnyError> {
+        let path = self.find(name)?;
+
+        match fs::remove_file(&path) {
+            Ok(_) => (),
+            Err(reason) => return error("Could not delete stored file", reason),
+        };
+        self.remove_empty_parent_dirs(&path);
+        Ok(())
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        match self.find(name).map(fs::symlink_metadata) {
+            Ok(Ok(metadata)) => metadata.file_type().is_file(),
+            _ => false,
+        }
+    }
+
+    fn modified(&self, name: &str) -> Result<SystemTime, AnyError> {
+        let path = self.find(name)?;
+
+        match fs::symlink_metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified_at) => Ok(modified_at),
+            Err(reason) => error("Could not read stored file time", reason),
+        }
+    }
+
+    // Made empty if missing, as there has to be a file to hold the lock on
+    fn lock(&self, name: &str) -> Result<StorageLock, AnyError> {
+        let path = confine_to_dir(&self.dir, Path::new(name))?;
+
+        let file = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => file,
+            Err(reason) => return error("Could not open stored file", reason),
+        };
+        match lock_file(&file) {
+            Ok(_) => Ok(StorageLock { _file: Some(file) }),
+            Err(reason) => error("Could not lock stored file", reason),
+        }
+    }
+
+    fn locate(&self, name: &str) -> String {
+        format!("{}/{}", self.dir, name)
+    }
+}
+
+// Nothing ever reaches the disk, which suits tests and throwaway profiles
+#[derive(Default)]
+pub struct MemoryStorage {
+    items: Mutex<BTreeMap<String, MemoryItem>>,
+}
+
+struct MemoryItem {
+    content: Vec<u8>,
+    modified_at: SystemTime,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+// Keys may be among the items, so they are wiped rather than just let go
+impl Drop for MemoryItem {
+    fn drop(&mut self) {
+        memzero(&mut self.content);
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn put(&self, name: &str, content: &[u8]) -> Result<(), AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        let item = MemoryItem {
+            content: content.to_vec(),
+            modified_at: SystemTime::now(),
+        };
+        self.items.lock().unwrap().insert(name.to_owned(), item);
+        Ok(())
+    }
+
+    fn put_new(&self, name: &str, content: &[u8]) -> Result<(), AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        let mut items = self.items.lock().unwrap();
+        if items.contains_key(name) {
+            return error_without_parent(&format!("Stored item {} already exists", name));
+        }
+        let item = MemoryItem {
+            content: content.to_vec(),
+            modified_at: SystemTime::now(),
+        };
+        items.insert(name.to_owned(), item);
+        Ok(())
+    }
+
+    fn append(&self, name: &str, content: &[u8]) -> Result<(), AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        let mut items = self.items.lock().unwrap();
+        let item = items.entry(name.to_owned()).or_insert_with(|| MemoryItem {
+            content: vec![],
+            modified_at: SystemTime::now(),
+        });
+        item.content.extend_from_slice(content);
+        item.modified_at = SystemTime::now();
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Result<Vec<u8>, AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        match self.items.lock().unwrap().get(name) {
+            Some(item) => Ok(item.content.to_owned()),
+            None => error_without_parent(&format!("Stored item {} does not exists", name)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, AnyError> {
+        Ok(self.items.lock().unwrap().keys().cloned().collect())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        match self.items.lock().unwrap().remove(name) {
+            Some(_) => Ok(()),
+            None => error_without_parent(&format!("Stored item {} does not exists", name)),
+        }
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        check_relative_name(Path::new(name)).is_ok()
+            && self.items.lock().unwrap().contains_key(name)
+    }
+
+    fn modified(&self, name: &str) -> Result<SystemTime, AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        match self.items.lock().unwrap().get(name) {
+            Some(item) => Ok(item.modified_at),
+            None => error_without_parent(&format!("Stored item {} does not exists", name)),
+        }
+    }
+
+    // Nothing outside this process can reach these items, so there is nobody to keep out
+    fn lock(&self, name: &str) -> Result<StorageLock, AnyError> {
+        check_relative_name(Path::new(name))?;
+
+        Ok(StorageLock::default())
+    }
+
+    fn locate(&self, name: &str) -> String {
+        format!("memory:{}", name)
+    }
+}
+
+// Helper functions
+//
+
+// Renaming replaces a symlink planted in the meantime rather than following it
+pub(crate) fn replace_file(path: &Path, content: &[u8]) -> Result<(), AnyError> {
+    let temp_path = write_temp_file(&path, content)?;
+    match fs::rename(&temp_path, &path) {
+        Ok(_) => Ok(()),
+        Err(reason) => {
+            let _ = fs::remove_file(&temp_path);
+            error("Could not save stored file", reason)
+        }
+    }
+}
+
+// Written aside first, so readers never see half an item nor lose it on a crash
+fn write_temp_file(path: &Path, content: &[u8]) -> Result<PathBuf, AnyError> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_name = format!(".{}.{}.tmp", file_name, HEXLOWER.encode(&randombytes(8)));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut temp_file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+    {
+        Ok(file) => file,
+        Err(reason) => return error("Could not create stored file", reason),
+    };
+    match temp_file
+        .write_all(content)
+        .and_then(|_| temp_file.sync_all())
+    {
+        Ok(_) => Ok(temp_path),
+        Err(reason) => {
+            let _ = fs::remove_file(&temp_path);
+            error("Could not write stored file", reason)
+        }
+    }
+}
+
+fn get_relative_name(base_dir: &Path, path: &Path) -> Option<String> {
+    let relative_path = path.strip_prefix(base_dir).ok()?;
+    let components: Vec<&str> = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<&str>>>()?;
+    Some(components.join("/"))
+}
+
+// Let go of once the file is closed
+#[cfg(unix)]
+fn lock_file(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let reason = io::Error::last_os_error();
+        if reason.kind() != io::ErrorKind::Interrupted {
+            return Err(reason);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_file(_file: &File) -> io::Result<()> {
+    Ok(())
+}
@@ -1,9 +1,480 @@
 mod tests {
     #[allow(unused_imports)]
     use crate::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use storage::Storage;
+
+    // Tells whether a given buffer was all zeros by the time it was given back
+    struct WatchingAllocator;
+
+    // Kept per thread, as tests run in parallel and may free the same address on another one
+    thread_local! {
+        static WATCHED_ADDRESS: Cell<usize> = const { Cell::new(0) };
+        static WATCHED_WIPED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    unsafe impl GlobalAlloc for WatchingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let address = ptr as usize;
+            // Thread locals may already be gone while the thread winds down
+            let is_watched = WATCHED_ADDRESS
+                .try_with(|watched| {
+                    let is_watched = watched.get() == address;
+                    if is_watched {
+                        watched.set(0);
+                    }
+                    is_watched
+                })
+                .unwrap_or(false);
+            if is_watched {
+                let freed = std::slice::from_raw_parts(ptr, layout.size());
+                let _ = WATCHED_WIPED.try_with(|wiped| wiped.set(freed.iter().all(|b| *b == 0)));
+            }
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: WatchingAllocator = WatchingAllocator;
+
+    fn watch_until_freed(address: usize) {
+        WATCHED_WIPED.with(|wiped| wiped.set(false));
+        WATCHED_ADDRESS.with(|watched| watched.set(address));
+    }
+
+    fn was_watched_wiped() -> bool {
+        WATCHED_WIPED.with(|wiped| wiped.get())
+    }
 
     #[test]
     fn should_be_true() {
         assert_eq!(true, true);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_wipe_secret_bytes_contents() {
+        let mut secret = SecretBytes::new(b"very secret content".to_vec());
+        assert_eq!(b"very secret content", &secret[..]);
+
+        secret.wipe();
+        assert_eq!(19, secret.len());
+        assert!(secret.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn should_wipe_keypair_secret_key() {
+        let (pk, sk) = box_::gen_keypair();
+        let mut keypair = Keypar::new(pk, sk);
+        assert!(keypair
+            .secret_key()
+            .unwrap()
+            .0
+            .iter()
+            .any(|byte| *byte != 0));
+
+        keypair.wipe();
+        assert_eq!(pk, *keypair.public_key());
+        assert!(keypair
+            .secret_key()
+            .unwrap()
+            .0
+            .iter()
+            .all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn should_wipe_secret_bytes_on_drop() {
+        let mut raw = Vec::with_capacity(64);
+        raw.extend_from_slice(b"very secret content, cut short later");
+        raw.truncate(11);
+        let secret = SecretBytes::new(raw);
+
+        watch_until_freed(secret.as_ptr() as usize);
+        drop(secret);
+        assert!(was_watched_wiped());
+    }
+
+    #[test]
+    fn should_wipe_keypair_secret_key_on_drop() {
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+
+        watch_until_freed(keypair.secret_key().unwrap().0.as_ptr() as usize);
+        drop(keypair);
+        assert!(was_watched_wiped());
+    }
+
+    // Hands out the same byte until it runs out, watching the first buffer it was given
+    struct WatchingReader {
+        remaining: usize,
+        is_watching: bool,
+    }
+
+    impl std::io::Read for WatchingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if !self.is_watching {
+                watch_until_freed(buf.as_ptr() as usize);
+                self.is_watching = true;
+            }
+            let chunk_len = self.remaining.min(buf.len());
+            buf[..chunk_len].fill(b's');
+            self.remaining -= chunk_len;
+            Ok(chunk_len)
+        }
+    }
+
+    #[test]
+    fn should_wipe_outgrown_buffers_when_reading_secrets() {
+        let mut reader = WatchingReader {
+            remaining: MIN_READ_LEN * 3,
+            is_watching: false,
+        };
+
+        let secret = SecretBytes::read_from(&mut reader, 0).unwrap();
+        assert_eq!(MIN_READ_LEN * 3, secret.len());
+        assert!(secret.iter().all(|byte| *byte == b's'));
+        assert!(was_watched_wiped());
+    }
+
+    #[test]
+    fn should_hide_secrets_from_debug_output() {
+        let secret = SecretBytes::new(b"hunter2".to_vec());
+        assert_eq!("SecretBytes(****; 7)", format!("{:?}", secret));
+
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+        assert!(format!("{:?}", keypair).ends_with("SecretKey(****))"));
+    }
+
+    fn temp_dir_for(test_name: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("moy-sekret-{}-{}", test_name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        format!("{}", dir.display())
+    }
+
+    #[test]
+    fn should_reject_hostile_file_names() {
+        let hostile_names = vec![
+            "",
+            ".",
+            "..",
+            "../evil",
+            "a/../../evil",
+            "/etc/passwd",
+            "./../evil",
+        ];
+        for name in hostile_names {
+            assert!(
+                check_relative_name(Path::new(name)).is_err(),
+                "Should have rejected {:?}",
+                name
+            );
+        }
+
+        assert!(check_relative_name(Path::new("important.txt")).is_ok());
+        assert!(check_relative_name(Path::new("nested/important.txt")).is_ok());
+    }
+
+    #[test]
+    fn should_reject_encrypted_file_names_that_escape_destination() {
+        let file_name = String::from("storage/...cz");
+        assert!(get_decrypted_file_name(&file_name, &String::from("dest")).is_err());
+
+        let file_name = String::from("storage/important.txt.cz");
+        assert_eq!(
+            "dest/important.txt",
+            get_decrypted_file_name(&file_name, &String::from("dest")).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_confine_path_to_destination_directory() {
+        let dest_dir = temp_dir_for("confine");
+
+        let path = confine_to_dir(&dest_dir, Path::new("nested/important.txt")).unwrap();
+        let abs_dest_dir = Path::new(&dest_dir).canonicalize().unwrap();
+        assert!(path.starts_with(&abs_dest_dir));
+        assert!(abs_dest_dir.join("nested").is_dir());
+
+        assert!(confine_to_dir(&dest_dir, Path::new("../important.txt")).is_err());
+
+        let _ = fs::remove_dir_all(&dest_dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_refuse_to_follow_symlinks_inside_destination() {
+        use std::os::unix::fs::symlink;
+
+        let dest_dir = temp_dir_for("symlink");
+        let outside_dir = temp_dir_for("symlink-outside");
+        symlink(&outside_dir, Path::new(&dest_dir).join("link_dir")).unwrap();
+        symlink(
+            Path::new(&outside_dir).join("victim.txt"),
+            Path::new(&dest_dir).join("link_file"),
+        )
+        .unwrap();
+
+        assert!(confine_to_dir(&dest_dir, Path::new("link_dir/important.txt")).is_err());
+        assert!(confine_to_dir(&dest_dir, Path::new("link_file")).is_err());
+
+        let link_path = Path::new(&dest_dir).join("link_file");
+        assert!(create_file_no_follow(&link_path, false).is_err());
+        assert!(create_file_no_follow(&link_path, true).is_err());
+        assert!(!Path::new(&outside_dir).join("victim.txt").exists());
+
+        let _ = fs::remove_dir_all(&dest_dir);
+        let _ = fs::remove_dir_all(&outside_dir);
+    }
+
+    #[test]
+    fn should_expose_error_chain_and_kind() {
+        let io_reason = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let inner = AnyError::new("Could not read file", Some(Box::new(io_reason)));
+        let outer = AnyError::new("Encryption failed", Some(Box::new(inner)));
+
+        assert_eq!(
+            vec!["Encryption failed", "Could not read file", "gone"],
+            outer.chain()
+        );
+        assert_eq!("io", outer.kind());
+        assert_eq!("operation", AnyError::without_parent("Nope").kind());
+    }
+
+    #[test]
+    fn should_fingerprint_public_keys() {
+        let (pk, _) = box_::gen_keypair();
+        let fingerprint = key_fingerprint(&pk);
+        assert!(fingerprint.starts_with("SHA256:"));
+        assert_eq!(fingerprint, key_fingerprint(&pk));
+    }
+
+    #[test]
+    fn should_recover_secret_from_any_threshold_of_shares() {
+        let secret = b"thirty two bytes of secret key!!";
+        let shares = backup::split_secret(secret, 3, 5);
+        assert_eq!(5, shares.len());
+
+        let pick = |indexes: &[usize]| -> Vec<(u8, &[u8])> {
+            indexes
+                .iter()
+                .map(|index| (shares[*index].0, shares[*index].1.as_ref()))
+                .collect()
+        };
+        assert_eq!(&secret[..], &backup::combine_shares(&pick(&[0, 1, 2]))[..]);
+        assert_eq!(&secret[..], &backup::combine_shares(&pick(&[4, 0, 3]))[..]);
+        assert_ne!(&secret[..], &backup::combine_shares(&pick(&[1, 3]))[..]);
+    }
+
+    #[test]
+    fn should_rebuild_keypair_from_secret_key_only() {
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::from_secret_only(&sk.0).unwrap();
+        assert_eq!(pk, *keypair.public_key());
+    }
+
+    #[test]
+    fn should_follow_bip39_when_turning_phrase_into_seed() {
+        let phrase = b"abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let seed = recovery::seed_from_phrase(phrase).unwrap();
+        assert_eq!(
+            "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc19a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4",
+            data_encoding::HEXLOWER.encode(&seed)
+        );
+    }
+
+    #[test]
+    fn should_open_files_made_before_container_headers() {
+        let (pk, sk) = box_::gen_keypair();
+        let nonce = box_::gen_nonce();
+        let legacy_data = box_::seal(b"from an older version", &nonce, &pk, &sk);
+        let legacy_content = bincode::serialize(&(nonce, legacy_data)).unwrap();
+
+        let keypair = Keypar::new(pk, sk);
+        let cipher = deserialize_cipher(&legacy_content).unwrap();
+        assert!(cipher.header.is_none());
+        assert_eq!(
+            b"from an older version",
+            &open_cipher(&keypair, &cipher).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn should_not_open_cipher_whose_header_was_changed() {
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+        let header = Header {
+            compression: Compression::Zstd,
+            ..Header::default()
+        };
+
+        let cipher = seal_content(&keypair, &header, b"squeeze me", box_::gen_nonce()).unwrap();
+        let cipher_content = serialize_cipher(&cipher).unwrap();
+        assert!(cipher_content.starts_with(CONTAINER_MAGIC));
+
+        let mut cipher = deserialize_cipher(&cipher_content).unwrap();
+        assert_eq!(b"squeeze me", &open_cipher(&keypair, &cipher).unwrap()[..]);
+
+        cipher.header = Some(Header::default());
+        assert!(open_cipher(&keypair, &cipher).is_err());
+    }
+
+    #[test]
+    fn should_pad_lengths_following_padme() {
+        let expected = [
+            (0, 0),
+            (1, 1),
+            (9, 10),
+            (100, 104),
+            (1000, 1024),
+            (1025, 1088),
+            (65537, 67584),
+        ];
+        for (len, padded_len) in expected.iter() {
+            assert_eq!(*padded_len, get_padme_len(*len));
+        }
+    }
+
+    #[test]
+    fn should_strip_padding_when_opening() {
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+        let header = Header {
+            padding: Padding::Padme,
+            ..Header::default()
+        };
+
+        let content = vec![7u8; 1010];
+        let cipher = seal_content(&keypair, &header, &content, box_::gen_nonce()).unwrap();
+        let unpadded =
+            seal_content(&keypair, &Header::default(), &content, box_::gen_nonce()).unwrap();
+        assert!(cipher.data.len() > unpadded.data.len());
+        assert_eq!(&content[..], &open_cipher(&keypair, &cipher).unwrap()[..]);
+    }
+
+    #[test]
+    fn should_open_key_slots_only_for_listed_recipients() {
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+        let (other_pk, other_sk) = box_::gen_keypair();
+        let other_keypair = Keypar::new(other_pk, other_sk);
+        let (stranger_pk, stranger_sk) = box_::gen_keypair();
+        let stranger_keypair = Keypar::new(stranger_pk, stranger_sk);
+
+        let header = Header {
+            recipients: vec![other_pk, pk],
+            ..Header::default()
+        };
+        let cipher = seal_content(&keypair, &header, b"for both", box_::gen_nonce()).unwrap();
+        assert_eq!(2, cipher.key_slots.len());
+
+        let cipher = deserialize_cipher(&serialize_cipher(&cipher).unwrap()).unwrap();
+        assert_eq!(b"for both", &open_cipher(&keypair, &cipher).unwrap()[..]);
+        assert_eq!(
+            b"for both",
+            &open_cipher(&other_keypair, &cipher).unwrap()[..]
+        );
+        assert!(open_cipher(&stranger_keypair, &cipher).is_err());
+    }
+
+    #[test]
+    fn should_not_open_key_slots_from_another_sender() {
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+        let (other_pk, other_sk) = box_::gen_keypair();
+        let other_keypair = Keypar::new(other_pk, other_sk);
+        let (forger_pk, forger_sk) = box_::gen_keypair();
+        let forger_keypair = Keypar::new(forger_pk, forger_sk);
+
+        let header = Header {
+            recipients: vec![other_pk],
+            ..Header::default()
+        };
+        let mut cipher = seal_content(&keypair, &header, b"from me", box_::gen_nonce()).unwrap();
+        assert_eq!(Some(&pk), cipher.sender());
+
+        // Anyone can wrap a file key for the recipient, but cannot tag it as someone else
+        let mut forged_cipher =
+            seal_content(&forger_keypair, &header, b"from me", box_::gen_nonce()).unwrap();
+        for key_slot in forged_cipher.key_slots.iter_mut() {
+            key_slot.sender = pk;
+        }
+        assert!(open_cipher(&other_keypair, &forged_cipher).is_err());
+
+        // Nor keep a genuine tag over content of their own
+        cipher.data = forged_cipher.data;
+        assert!(open_cipher(&other_keypair, &cipher).is_err());
+    }
+
+    #[test]
+    fn should_not_open_unknown_container_versions() {
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+        let cipher =
+            seal_content(&keypair, &Header::default(), b"newer", box_::gen_nonce()).unwrap();
+        let mut cipher_content = serialize_cipher(&cipher).unwrap();
+        assert_eq!(CONTAINER_VERSION, cipher_content[CONTAINER_MAGIC.len()]);
+
+        cipher_content[CONTAINER_MAGIC.len()] = CONTAINER_VERSION + 1;
+        assert!(deserialize_cipher(&cipher_content).is_err());
+    }
+
+    #[test]
+    fn should_not_derive_passphrase_key_beyond_cost_limits() {
+        let mut key_derivation = KeyDerivation::generate();
+        key_derivation.mem_limit = u64::MAX;
+
+        assert!(derive_passphrase_key(b"whatever", &key_derivation).is_err());
+    }
+
+    #[test]
+    fn should_keep_keypair_in_any_storage() {
+        let storage = storage::MemoryStorage::new();
+        let (pk, sk) = box_::gen_keypair();
+        let profile_name = "memory".to_owned();
+        store_keypair(&storage, &profile_name, &Keypar::new(pk, sk)).unwrap();
+
+        assert!(storage.exists("memory.pk"));
+        assert!(storage.exists("memory.sk"));
+        let keypair = load_keypair(&storage, &profile_name).unwrap();
+        assert_eq!(pk, *keypair.public_key());
+    }
+
+    #[test]
+    fn should_encrypt_into_any_storage() {
+        let storage = storage::MemoryStorage::new();
+        let (pk, sk) = box_::gen_keypair();
+        let keypair = Keypar::new(pk, sk);
+        let profile = new_profile(&"memory".to_owned(), &"nowhere".to_owned());
+        let file_path = format!(
+            "{}/moy-sekret-memory-{}.txt",
+            std::env::temp_dir().display(),
+            process::id()
+        );
+        fs::write(&file_path, b"never stored on disk").unwrap();
+
+        let report = encrypt_file_with_keys(
+            &profile,
+            &storage,
+            &keypair,
+            &[],
+            &file_path,
+            false,
+            &EncryptOptions::default(),
+        );
+        fs::remove_file(&file_path).unwrap();
+
+        let cipher_name = get_encrypted_name(&file_path);
+        assert_eq!(vec![storage.locate(&cipher_name)], report.unwrap().written);
+        let cipher = decode_cipher_file(storage.get(&cipher_name).unwrap()).unwrap();
+        let plain_content = open_cipher(&keypair, &cipher).unwrap();
+        assert_eq!(b"never stored on disk", &plain_content[..]);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::batch::Outcome;
+use crate::{
+    encrypt_with_options, error, error_without_parent, get_encrypted_name, open_storage,
+    read_profile, remove_source, AnyError, EncryptOptions, Profile,
+};
+use notify::event::{AccessKind, AccessMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+// Files still being written keep changing, so they are only picked once left alone for a while
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+// Entrypoint functions
+//
+
+// Runs until the given closure returns false, which it is asked after every file
+pub fn run<F>(
+    profile_name: &String,
+    dir: &String,
+    should_remove_source: bool,
+    options: &EncryptOptions,
+    mut on_outcome: F,
+) -> Result<(), AnyError>
+where
+    F: FnMut(Outcome) -> bool,
+{
+    if !Path::new(dir).is_dir() {
+        return error_without_parent("Watching failed because directory does not exists");
+    }
+
+    // Changes come with absolute paths, so files found on start should look the same
+    let dir = match Path::new(dir).canonicalize() {
+        Ok(abs_path) => format!("{}", abs_path.display()),
+        Err(reason) => return error("Watching failed while expanding directory", reason),
+    };
+
+    let profile = match read_profile(&profile_name) {
+        Ok(obj) => obj,
+        Err(reason) => return error("Watching failed while reading user profile", reason),
+    };
+
+    // Keys and the log would count as dropped files, and every entry made would fire again
+    let storage_dir = match Path::new(&profile.storage).canonicalize() {
+        Ok(abs_path) => abs_path,
+        Err(reason) => return error("Watching failed while expanding storage directory", reason),
+    };
+    if storage_dir.starts_with(&dir) {
+        return error_without_parent(
+            "Watching failed because directory holds the profile storage directory",
+        );
+    }
+
+    // Watching starts before catching up, so nothing dropped in between is missed
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = match RecommendedWatcher::new(sender, Config::default()) {
+        Ok(watcher) => watcher,
+        Err(reason) => return error("Watching failed while setting watcher up", reason),
+    };
+    match watcher.watch(Path::new(&dir), RecursiveMode::NonRecursive) {
+        Ok(_) => (),
+        Err(reason) => return error("Watching failed while registering directory", reason),
+    };
+
+    let file_paths = match find_outdated_files(&profile, &dir) {
+        Ok(file_paths) => file_paths,
+        Err(reason) => return error("Watching failed while looking for dropped files", reason),
+    };
+    for file_path in file_paths {
+        let outcome =
+            encrypt_dropped_file(&profile_name, &file_path, should_remove_source, &options);
+        if !on_outcome(outcome) {
+            return Ok(());
+        }
+    }
+
+    let mut pending: HashMap<String, Instant> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(QUIET_PERIOD) {
+            Ok(Ok(event)) => {
+                if changes_content(&event) {
+                    for path in event.paths {
+                        pending.insert(format!("{}", path.display()), Instant::now());
+                    }
+                }
+            }
+            Ok(Err(reason)) => return error("Watching failed while receiving changes", reason),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                return error_without_parent("Watching failed because watcher stopped")
+            }
+        };
+
+        let settled_paths: Vec<String> = pending
+            .iter()
+            .filter(|(_, changed_at)| changed_at.elapsed() >= QUIET_PERIOD)
+            .map(|(file_path, _)| file_path.to_owned())
+            .collect();
+        for file_path in settled_paths {
+            pending.remove(&file_path);
+            if !is_droppable_file(Path::new(&file_path)) {
+                continue;
+            }
+
+            let outcome =
+                encrypt_dropped_file(&profile_name, &file_path, should_remove_source, &options);
+            if !on_outcome(outcome) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Business functions
+//
+
+// Stored copies are always overridden, as a file changed again must replace its older version
+fn encrypt_dropped_file(
+    profile_name: &String,
+    file_path: &String,
+    should_remove_source: bool,
+    options: &EncryptOptions,
+) -> Outcome {
+    let result =
+        encrypt_with_options(&profile_name, &file_path, true, &options).and_then(|report| {
+            if should_remove_source {
+                Ok(report.merge(remove_source(&profile_name, &file_path)?))
+            } else {
+                Ok(report)
+            }
+        });
+
+    Outcome {
+        file_path: file_path.to_owned(),
+        result,
+    }
+}
+
+// Files dropped while nobody was watching, or changed since last encrypted
+fn find_outdated_files(profile: &Profile, dir: &String) -> Result<Vec<String>, AnyError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(reason) => return error("Could not read directory", reason),
+    };
+
+    let mut file_paths = vec![];
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(reason) => return error("Could not read directory entry", reason),
+        };
+
+        let file_path = format!("{}", path.display());
+        if is_droppable_file(&path) && is_outdated(&profile, &file_path) {
+            file_paths.push(file_path);
+        }
+    }
+
+    file_paths.sort();
+    Ok(file_paths)
+}
+
+// Helper functions
+//
+
+fn changes_content(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_)
+            | EventKind::Modify(_)
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
+
+// Hidden and backup files are mostly editors at work, while .cz ones are already encrypted
+fn is_droppable_file(path: &Path) -> bool {
+    let file_name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    path.is_file()
+        && !file_name.starts_with('.')
+        && !file_name.ends_with('~')
+        && !file_name.ends_with(".cz")
+}
+
+fn is_outdated(profile: &Profile, file_path: &String) -> bool {
+    let storage = open_storage(&profile);
+    let plain_time = fs::metadata(file_path).and_then(|metadata| metadata.modified());
+    match (
+        plain_time,
+        storage.modified(&get_encrypted_name(&file_path)),
+    ) {
+        (Ok(plain_time), Ok(encrypted_time)) => plain_time > encrypted_time,
+        _ => true,
+    }
+}
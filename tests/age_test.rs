@@ -0,0 +1,175 @@
+extern crate moy_sekret;
+
+use moy_sekret::age_format::AgeOptions;
+use moy_sekret::SecretBytes;
+use std::fs;
+use std::io::prelude::*;
+use std::iter;
+use std::str::FromStr;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn decrypted_content(sandbox: &Sandbox, passphrase: Option<&SecretBytes>) -> Vec<u8> {
+    let age_file_path = sandbox.storage_file("report.txt.age");
+    if let Err(reason) = moy_sekret::age_format::decrypt(
+        &sandbox.profile,
+        &age_file_path,
+        &sandbox.local_dir,
+        passphrase,
+        true,
+    ) {
+        panic!("Should have decrypted age file but: {}", reason);
+    }
+    fs::read(format!("{}/report.txt", sandbox.local_dir)).unwrap()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_encrypt_to_age_recipients() {
+    let sandbox = Sandbox::with_profile("age_recipients");
+    let file_path = sandbox.write_local_file("report.txt", b"for age users too");
+    let age_identity = age::x25519::Identity::generate();
+
+    let options = AgeOptions {
+        recipients: vec![age_identity.to_public().to_string()],
+        ..AgeOptions::default()
+    };
+    if let Err(reason) =
+        moy_sekret::age_format::encrypt(&sandbox.profile, &file_path, &options, true)
+    {
+        panic!("Should have encrypted age file but: {}", reason);
+    }
+
+    let age_content = fs::read(sandbox.storage_file("report.txt.age")).unwrap();
+    assert!(age_content.starts_with(b"age-encryption.org/v1\n"));
+
+    let decryptor = age::Decryptor::new(&age_content[..]).unwrap();
+    let mut reader = decryptor
+        .decrypt(iter::once(&age_identity as &dyn age::Identity))
+        .unwrap();
+    let mut plain_content = vec![];
+    reader.read_to_end(&mut plain_content).unwrap();
+    assert_eq!(b"for age users too".to_vec(), plain_content);
+
+    assert_eq!(
+        b"for age users too".to_vec(),
+        decrypted_content(&sandbox, None)
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_decrypt_armored_files_made_by_age() {
+    let sandbox = Sandbox::with_profile("age_armored");
+    let recipient = match moy_sekret::age_format::recipient(&sandbox.profile) {
+        Ok(recipient) => recipient,
+        Err(reason) => panic!("Should have got recipient but: {}", reason),
+    };
+    assert!(recipient.starts_with("age1"));
+
+    let age_recipient = age::x25519::Recipient::from_str(&recipient).unwrap();
+    let encryptor =
+        age::Encryptor::with_recipients(iter::once(&age_recipient as &dyn age::Recipient)).unwrap();
+    let armored =
+        age::armor::ArmoredWriter::wrap_output(vec![], age::armor::Format::AsciiArmor).unwrap();
+    let mut writer = encryptor.wrap_output(armored).unwrap();
+    writer.write_all(b"from an age user").unwrap();
+    let age_content = writer.finish().unwrap().finish().unwrap();
+    assert!(age_content.starts_with(b"-----BEGIN AGE ENCRYPTED FILE-----"));
+    fs::write(sandbox.storage_file("report.txt.age"), &age_content).unwrap();
+
+    assert_eq!(
+        b"from an age user".to_vec(),
+        decrypted_content(&sandbox, None)
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_encrypt_to_passphrase_only() {
+    let sandbox = Sandbox::with_profile("age_passphrase");
+    let file_path = sandbox.write_local_file("report.txt", b"shared by word of mouth");
+
+    let options = AgeOptions {
+        passphrase: Some(SecretBytes::new(b"correct horse battery staple\n".to_vec())),
+        armor: true,
+        ..AgeOptions::default()
+    };
+    if let Err(reason) =
+        moy_sekret::age_format::encrypt(&sandbox.profile, &file_path, &options, true)
+    {
+        panic!("Should have encrypted age file but: {}", reason);
+    }
+
+    let age_file_path = sandbox.storage_file("report.txt.age");
+    let age_content = fs::read(&age_file_path).unwrap();
+    let decryptor = age::Decryptor::new(age::armor::ArmoredReader::new(&age_content[..])).unwrap();
+    assert!(decryptor.is_scrypt());
+    let age_identity = age::scrypt::Identity::new(age::secrecy::SecretString::from(
+        "correct horse battery staple".to_owned(),
+    ));
+    let mut reader = decryptor
+        .decrypt(iter::once(&age_identity as &dyn age::Identity))
+        .unwrap();
+    let mut plain_content = vec![];
+    reader.read_to_end(&mut plain_content).unwrap();
+    assert_eq!(b"shared by word of mouth".to_vec(), plain_content);
+
+    assert!(moy_sekret::age_format::decrypt(
+        &sandbox.profile,
+        &age_file_path,
+        &sandbox.local_dir,
+        None,
+        true
+    )
+    .is_err());
+    let passphrase = SecretBytes::new(b"correct horse battery staple".to_vec());
+    assert_eq!(
+        b"shared by word of mouth".to_vec(),
+        decrypted_content(&sandbox, Some(&passphrase))
+    );
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_not_write_age_file_through_symlink_nor_over_existing_one() {
+    use std::os::unix::fs::symlink;
+
+    let sandbox = Sandbox::with_profile("age_target");
+    let file_path = sandbox.write_local_file("report.txt", b"for age users too");
+    let target_file_path = sandbox.write_local_file("target.txt", b"left alone");
+    symlink(
+        fs::canonicalize(&target_file_path).unwrap(),
+        sandbox.storage_file("report.txt.age"),
+    )
+    .unwrap();
+
+    let options = AgeOptions::default();
+    assert!(moy_sekret::age_format::encrypt(&sandbox.profile, &file_path, &options, true).is_err());
+    assert_eq!(b"left alone".to_vec(), fs::read(&target_file_path).unwrap());
+
+    fs::remove_file(sandbox.storage_file("report.txt.age")).unwrap();
+    let plan = moy_sekret::age_format::plan_encrypt(&sandbox.profile, &file_path, false).unwrap();
+    assert_eq!(vec![sandbox.storage_file("report.txt.age")], plan.created);
+    moy_sekret::age_format::encrypt(&sandbox.profile, &file_path, &options, false).unwrap();
+    assert!(
+        moy_sekret::age_format::encrypt(&sandbox.profile, &file_path, &options, false).is_err()
+    );
+}
@@ -0,0 +1,290 @@
+extern crate moy_sekret;
+
+use data_encoding::BASE64;
+use moy_sekret::agent::{Request, Response};
+use sodiumoxide::crypto::box_;
+use sodiumoxide::crypto::generichash;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn start_agent(socket_path: &String) -> thread::JoinHandle<()> {
+    let agent_socket_path = socket_path.to_owned();
+    let handle = thread::spawn(move || {
+        moy_sekret::agent::start(&agent_socket_path, 0).unwrap();
+    });
+
+    for _ in 0..100 {
+        if Path::new(socket_path).exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    handle
+}
+
+fn read_key_file(sandbox: &Sandbox, extension: &str) -> Vec<u8> {
+    let key_path = format!("{}/{}.{}", sandbox.storage_dir, sandbox.profile, extension);
+    let raw_base64 = fs::read_to_string(key_path).unwrap();
+    BASE64.decode(raw_base64.trim().as_bytes()).unwrap()
+}
+
+fn derive_subkey(sk: &[u8], context: &[u8]) -> Vec<u8> {
+    let mut state = generichash::State::new(Some(32), Some(sk)).unwrap();
+    state.update(context).unwrap();
+    state.finalize().unwrap().as_ref().to_vec()
+}
+
+// Every kind of request, so a new one cannot be added without being checked here too
+fn every_request(profile: &String, peer: &[u8], context: &[u8]) -> Vec<Request> {
+    let nonce = box_::gen_nonce().as_ref().to_vec();
+    let requests = vec![
+        Request::List,
+        Request::PublicKey {
+            profile: profile.to_owned(),
+        },
+        Request::Seal {
+            profile: profile.to_owned(),
+            nonce: nonce.to_owned(),
+            data: b"plain content".to_vec(),
+        },
+        Request::OpenFrom {
+            profile: profile.to_owned(),
+            peer: peer.to_vec(),
+            nonce: nonce.to_owned(),
+            data: vec![0; 64],
+        },
+        Request::SenderTag {
+            profile: profile.to_owned(),
+            peer: peer.to_vec(),
+            message: b"tagged message".to_vec(),
+        },
+        Request::KeyedDigest {
+            profile: profile.to_owned(),
+            context: context.to_vec(),
+            message: vec![],
+        },
+        Request::SigningKey {
+            profile: profile.to_owned(),
+            context: context.to_vec(),
+        },
+        Request::Sign {
+            profile: profile.to_owned(),
+            context: context.to_vec(),
+            message: b"signed message".to_vec(),
+        },
+    ];
+
+    for request in &requests {
+        match request {
+            // Handing keys in, or changing what is held, is covered by the tests above
+            Request::Add { .. } | Request::Remove { .. } | Request::Stop => unreachable!(),
+            Request::List
+            | Request::PublicKey { .. }
+            | Request::Seal { .. }
+            | Request::OpenFrom { .. }
+            | Request::SenderTag { .. }
+            | Request::KeyedDigest { .. }
+            | Request::SigningKey { .. }
+            | Request::Sign { .. } => (),
+        }
+    }
+    requests
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_encrypt_and_decrypt_with_keys_held_by_agent() {
+    let sandbox = Sandbox::with_profile("agent_fallback");
+    let socket_path = format!("{}/agent.sock", sandbox.local_dir);
+    let agent = start_agent(&socket_path);
+
+    match moy_sekret::agent::add(&socket_path, &sandbox.profile, None) {
+        Ok(report) => assert_eq!(1, report.fingerprints.len()),
+        Err(reason) => panic!("Should have added keys to agent but: {}", reason),
+    }
+    assert_eq!(
+        vec![sandbox.profile.to_owned()],
+        moy_sekret::agent::list(&socket_path).unwrap()
+    );
+
+    // Without its secret key file, the profile only works through the agent
+    env::set_var(moy_sekret::agent::SOCKET_ENV_VAR, &socket_path);
+    fs::remove_file(format!("{}/{}.sk", sandbox.storage_dir, sandbox.profile)).unwrap();
+
+    let file_path = sandbox.write_local_file("agent.txt", b"held by the agent");
+    if let Err(reason) = moy_sekret::encrypt(&sandbox.profile, &file_path, false) {
+        panic!("Should have encrypted through agent but: {}", reason);
+    }
+    fs::remove_file(&file_path).unwrap();
+
+    let encrypted_file_path = sandbox.storage_file("agent.txt.cz");
+    if let Err(reason) = moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        false,
+    ) {
+        panic!("Should have decrypted through agent but: {}", reason);
+    }
+    assert_eq!(b"held by the agent".to_vec(), fs::read(&file_path).unwrap());
+
+    moy_sekret::agent::remove(&socket_path, &sandbox.profile).unwrap();
+    assert!(moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        true
+    )
+    .is_err());
+
+    moy_sekret::agent::stop(&socket_path).unwrap();
+    agent.join().unwrap();
+    env::remove_var(moy_sekret::agent::SOCKET_ENV_VAR);
+    assert!(!Path::new(&socket_path).exists());
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_forget_keys_once_their_ttl_expires() {
+    let sandbox = Sandbox::with_profile("agent_ttl");
+    let socket_path = format!("{}/agent.sock", sandbox.local_dir);
+    let agent = start_agent(&socket_path);
+
+    moy_sekret::agent::add(&socket_path, &sandbox.profile, Some(1)).unwrap();
+    assert_eq!(1, moy_sekret::agent::list(&socket_path).unwrap().len());
+
+    thread::sleep(Duration::from_millis(1500));
+    assert!(moy_sekret::agent::list(&socket_path).unwrap().is_empty());
+
+    moy_sekret::agent::stop(&socket_path).unwrap();
+    agent.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_not_start_agent_twice_on_same_socket() {
+    let sandbox = Sandbox::new("agent_twice");
+    let socket_path = format!("{}/agent.sock", sandbox.local_dir);
+    let agent = start_agent(&socket_path);
+
+    match moy_sekret::agent::start(&socket_path, 0) {
+        Ok(_) => panic!("Should not have started a second agent"),
+        Err(reason) => assert!(reason.to_string().contains("already listening")),
+    }
+
+    moy_sekret::agent::stop(&socket_path).unwrap();
+    agent.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_keep_socket_private_to_its_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sandbox = Sandbox::new("agent_private");
+    let socket_dir = format!("{}/agent", sandbox.local_dir);
+    let socket_path = format!("{}/agent.sock", socket_dir);
+    let agent = start_agent(&socket_path);
+
+    let mode = |path: &String| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(0o700, mode(&socket_dir));
+    assert_eq!(0o600, mode(&socket_path));
+    assert!(moy_sekret::agent::list(&socket_path).unwrap().is_empty());
+
+    moy_sekret::agent::stop(&socket_path).unwrap();
+    agent.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_keep_answering_while_a_client_stalls() {
+    use std::io::prelude::*;
+    use std::os::unix::net::UnixStream;
+    use std::time::Instant;
+
+    let sandbox = Sandbox::new("agent_stall");
+    let socket_path = format!("{}/agent.sock", sandbox.local_dir);
+    let agent = start_agent(&socket_path);
+
+    // Connects and never says a thing
+    let _stalled = UnixStream::connect(&socket_path).unwrap();
+
+    // Claims a message far larger than any real one, which gets turned down unread
+    let mut oversized = UnixStream::connect(&socket_path).unwrap();
+    oversized.write_all(&(1u32 << 30).to_le_bytes()).unwrap();
+    let mut response = vec![];
+    oversized.read_to_end(&mut response).unwrap();
+    assert!(response.is_empty());
+
+    let started_at = Instant::now();
+    assert!(moy_sekret::agent::list(&socket_path).unwrap().is_empty());
+    assert!(started_at.elapsed() < Duration::from_secs(5));
+
+    moy_sekret::agent::stop(&socket_path).unwrap();
+    agent.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_never_answer_with_key_material() {
+    let sandbox = Sandbox::with_profile("agent_no_keys");
+    let socket_path = format!("{}/agent.sock", sandbox.local_dir);
+    let agent = start_agent(&socket_path);
+    moy_sekret::agent::add(&socket_path, &sandbox.profile, None).unwrap();
+
+    let pk = box_::PublicKey::from_slice(&read_key_file(&sandbox, "pk")).unwrap();
+    let sk = box_::SecretKey::from_slice(&read_key_file(&sandbox, "sk")).unwrap();
+    let (peer_pk, _) = box_::gen_keypair();
+    let context = b"moy-sekret audit log";
+
+    // Secret key, whatever it shares with anyone, and whatever is derived from it
+    let seed = derive_subkey(&sk.0, context);
+    let key_material = vec![
+        sk.0.to_vec(),
+        box_::precompute(&pk, &sk).as_ref().to_vec(),
+        box_::precompute(&peer_pk, &sk).as_ref().to_vec(),
+        seed.to_owned(),
+        derive_subkey(&sk.0, b"moy-sekret git-filter"),
+    ];
+
+    for request in every_request(&sandbox.profile, peer_pk.as_ref(), context) {
+        let data = match moy_sekret::agent::ask(&socket_path, &request).unwrap() {
+            Response::Data(data) => data.to_vec(),
+            Response::Profiles(profile_names) => profile_names.concat().into_bytes(),
+            Response::Done | Response::Failed(_) => continue,
+        };
+        for key in &key_material {
+            assert!(!data.windows(key.len()).any(|window| window == &key[..]));
+        }
+    }
+
+    moy_sekret::agent::stop(&socket_path).unwrap();
+    agent.join().unwrap();
+}
@@ -0,0 +1,101 @@
+extern crate moy_sekret;
+
+use moy_sekret::EncryptOptions;
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn armor_options() -> EncryptOptions {
+    EncryptOptions {
+        armor: true,
+        ..EncryptOptions::default()
+    }
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_write_armored_text_when_asked_to() {
+    let sandbox = Sandbox::with_profile("armor_write");
+    let file_path = sandbox.write_local_file("ticket.txt", b"paste me somewhere");
+
+    if let Err(reason) =
+        moy_sekret::encrypt_with_options(&sandbox.profile, &file_path, true, &armor_options())
+    {
+        panic!("Should have encrypted file but: {}", reason);
+    }
+
+    let armored = fs::read_to_string(sandbox.storage_file("ticket.txt.cz")).unwrap();
+    assert!(armored.starts_with("-----BEGIN MOY SEKRET ENCRYPTED FILE-----\n"));
+    assert!(armored.ends_with("-----END MOY SEKRET ENCRYPTED FILE-----\n"));
+    assert!(armored.lines().all(|line| line.len() <= 64));
+}
+
+#[test]
+#[testaun_case]
+fn should_decrypt_armored_text_even_after_pasting() {
+    let sandbox = Sandbox::with_profile("armor_paste");
+    let file_path = sandbox.write_local_file("ticket.txt", b"paste me somewhere");
+    moy_sekret::encrypt_with_options(&sandbox.profile, &file_path, true, &armor_options()).unwrap();
+
+    // Mail clients and ticket systems like to indent and add line breaks
+    let encrypted_file_path = sandbox.storage_file("ticket.txt.cz");
+    let armored = fs::read_to_string(&encrypted_file_path).unwrap();
+    let pasted: Vec<String> = armored
+        .lines()
+        .map(|line| format!("  {}\r", line))
+        .collect();
+    fs::write(&encrypted_file_path, format!("\n\n{}\n", pasted.join("\n"))).unwrap();
+
+    if let Err(reason) = moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        true,
+    ) {
+        panic!("Should have decrypted file but: {}", reason);
+    }
+    assert_eq!(
+        b"paste me somewhere".to_vec(),
+        fs::read(format!("{}/ticket.txt", sandbox.local_dir)).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_not_decrypt_armored_text_that_was_cut_short() {
+    let sandbox = Sandbox::with_profile("armor_cut");
+    let file_path = sandbox.write_local_file("ticket.txt", b"paste me somewhere");
+    moy_sekret::encrypt_with_options(&sandbox.profile, &file_path, true, &armor_options()).unwrap();
+
+    let encrypted_file_path = sandbox.storage_file("ticket.txt.cz");
+    let armored = fs::read_to_string(&encrypted_file_path).unwrap();
+    let cut: Vec<&str> = armored
+        .lines()
+        .filter(|line| !line.starts_with("-----END"))
+        .collect();
+    fs::write(&encrypted_file_path, cut.join("\n")).unwrap();
+
+    assert!(moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        true
+    )
+    .is_err());
+}
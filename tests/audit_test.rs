@@ -0,0 +1,246 @@
+extern crate moy_sekret;
+
+use data_encoding::HEXLOWER;
+use moy_sekret::audit::{Entry, LogHead};
+use sodiumoxide::crypto::hash::sha256;
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn encrypt_and_decrypt(sandbox: &Sandbox) {
+    let file_path = sandbox.write_local_file("ledger.txt", b"who did what and when");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, true).unwrap();
+    moy_sekret::decrypt(
+        &sandbox.profile,
+        &sandbox.storage_file("ledger.txt.cz"),
+        &sandbox.local_dir,
+        true,
+    )
+    .unwrap();
+}
+
+fn log_file(sandbox: &Sandbox) -> String {
+    sandbox.storage_file(&format!("{}.log", sandbox.profile))
+}
+
+fn head_file(sandbox: &Sandbox) -> String {
+    sandbox.storage_file(&format!("{}.head", sandbox.profile))
+}
+
+fn enable_signing(sandbox: &Sandbox) {
+    moy_sekret::configure(
+        &sandbox.profile,
+        &"log-signing".to_owned(),
+        &"ed25519".to_owned(),
+    )
+    .unwrap();
+}
+
+// Does what anyone able to write the log and its head could, save for signing
+fn strip_signatures(sandbox: &Sandbox) {
+    let log_content = fs::read_to_string(log_file(&sandbox)).unwrap();
+    let mut previous: Option<String> = None;
+    let mut lines = vec![];
+    for line in log_content.lines() {
+        let mut entry: Entry = serde_json::from_str(line).unwrap();
+        entry.record.signer = None;
+        entry.record.previous = previous;
+        entry.signature = None;
+        entry.hash =
+            HEXLOWER.encode(sha256::hash(&serde_json::to_vec(&entry.record).unwrap()).as_ref());
+        previous = Some(entry.hash.to_owned());
+        lines.push(serde_json::to_string(&entry).unwrap());
+    }
+    fs::write(log_file(&sandbox), format!("{}\n", lines.join("\n"))).unwrap();
+
+    let head = LogHead {
+        sequence: lines.len() as u64,
+        hash: previous.unwrap(),
+        signer: None,
+        signature: None,
+    };
+    fs::write(head_file(&sandbox), serde_json::to_string(&head).unwrap()).unwrap();
+}
+
+// Cuts the log down to its first entries and points the head at the new last one
+fn truncate_log(sandbox: &Sandbox, entry_count: usize) {
+    let log_content = fs::read_to_string(log_file(&sandbox)).unwrap();
+    let lines: Vec<&str> = log_content.lines().take(entry_count).collect();
+    fs::write(log_file(&sandbox), format!("{}\n", lines.join("\n"))).unwrap();
+
+    let last_entry: Entry = serde_json::from_str(lines[entry_count - 1]).unwrap();
+    let mut head: LogHead =
+        serde_json::from_str(&fs::read_to_string(head_file(&sandbox)).unwrap()).unwrap();
+    head.sequence = last_entry.record.sequence;
+    head.hash = last_entry.hash;
+    fs::write(head_file(&sandbox), serde_json::to_string(&head).unwrap()).unwrap();
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_record_every_operation_in_a_chained_log() {
+    let sandbox = Sandbox::with_profile("audit_chain");
+    encrypt_and_decrypt(&sandbox);
+
+    let entries = match moy_sekret::audit::show(&sandbox.profile) {
+        Ok(entries) => entries,
+        Err(reason) => panic!("Should have shown log but: {}", reason),
+    };
+    let actions: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry.record.action.as_str())
+        .collect();
+    assert_eq!(vec!["init", "encrypt", "decrypt"], actions);
+    assert_eq!(Some(entries[1].hash.to_owned()), entries[2].record.previous);
+
+    match moy_sekret::audit::verify(&sandbox.profile) {
+        Ok(verification) => assert_eq!(3, verification.entries),
+        Err(reason) => panic!("Should have verified log but: {}", reason),
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_detect_edited_or_truncated_log() {
+    let sandbox = Sandbox::with_profile("audit_tamper");
+    encrypt_and_decrypt(&sandbox);
+    let log_content = fs::read_to_string(log_file(&sandbox)).unwrap();
+
+    let edited_content = log_content.replacen("\"encrypt\"", "\"decrypt\"", 1);
+    fs::write(log_file(&sandbox), edited_content).unwrap();
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_err());
+
+    let lines: Vec<&str> = log_content.lines().collect();
+    fs::write(log_file(&sandbox), format!("{}\n", lines[..2].join("\n"))).unwrap();
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_err());
+
+    fs::write(log_file(&sandbox), format!("{}\n", lines[1..].join("\n"))).unwrap();
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_err());
+
+    fs::write(log_file(&sandbox), log_content).unwrap();
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_ok());
+}
+
+#[test]
+#[testaun_case]
+fn should_sign_entries_and_keep_chain_across_new_keys() {
+    let sandbox = Sandbox::with_profile("audit_signed");
+    enable_signing(&sandbox);
+    encrypt_and_decrypt(&sandbox);
+
+    match moy_sekret::audit::verify(&sandbox.profile) {
+        Ok(verification) => assert_eq!(3, verification.signed),
+        Err(reason) => panic!("Should have verified log but: {}", reason),
+    }
+
+    // Entries signed by the keys being replaced stay valid, as they come before the new init
+    moy_sekret::init(&sandbox.profile, &sandbox.storage_dir, true).unwrap();
+    match moy_sekret::audit::verify(&sandbox.profile) {
+        Ok(verification) => assert_eq!(5, verification.entries),
+        Err(reason) => panic!("Should have verified log but: {}", reason),
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_detect_stripped_signatures() {
+    let sandbox = Sandbox::with_profile("audit_stripped");
+    enable_signing(&sandbox);
+    encrypt_and_decrypt(&sandbox);
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_ok());
+
+    strip_signatures(&sandbox);
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_err());
+}
+
+#[test]
+#[testaun_case]
+fn should_detect_truncated_log_with_rewritten_head() {
+    let sandbox = Sandbox::with_profile("audit_truncated");
+    enable_signing(&sandbox);
+    encrypt_and_decrypt(&sandbox);
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_ok());
+
+    // Decryption is gone from the log, and the head now claims encryption came last
+    truncate_log(&sandbox, 3);
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_err());
+
+    fs::remove_file(head_file(&sandbox)).unwrap();
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_err());
+}
+
+#[test]
+#[testaun_case]
+fn should_record_secret_store_operations() {
+    let sandbox = Sandbox::with_profile("audit_secrets");
+    let secret_path = "db/password".to_owned();
+    moy_sekret::secret_store::set(&sandbox.profile, &secret_path, b"hunter2", false).unwrap();
+    moy_sekret::secret_store::get(&sandbox.profile, &secret_path).unwrap();
+    moy_sekret::secret_store::remove(&sandbox.profile, &secret_path).unwrap();
+
+    let entries = moy_sekret::audit::show(&sandbox.profile).unwrap();
+    let actions: Vec<(&str, &str)> = entries
+        .iter()
+        .map(|entry| (entry.record.action.as_str(), entry.record.subject.as_str()))
+        .collect();
+    assert_eq!(
+        vec![
+            ("init", sandbox.profile.as_str()),
+            ("secret-set", "db/password"),
+            ("secret-get", "db/password"),
+            ("secret-remove", "db/password"),
+        ],
+        actions
+    );
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_ok());
+}
+
+#[test]
+#[testaun_case]
+fn should_keep_log_head_out_of_profile() {
+    let sandbox = Sandbox::with_profile("audit_head");
+    let profile_content = fs::read_to_string(sandbox.profile_file()).unwrap();
+    encrypt_and_decrypt(&sandbox);
+
+    assert_eq!(
+        profile_content,
+        fs::read_to_string(sandbox.profile_file()).unwrap()
+    );
+    let head: LogHead =
+        serde_json::from_str(&fs::read_to_string(head_file(&sandbox)).unwrap()).unwrap();
+    assert_eq!(3, head.sequence);
+}
+
+#[test]
+#[testaun_case]
+fn should_warn_instead_of_failing_when_log_cannot_record() {
+    let sandbox = Sandbox::with_profile("audit_unrecorded");
+    let file_path = sandbox.write_local_file("ledger.txt", b"who did what and when");
+
+    // Nothing can be read from nor appended to a directory, so recording fails
+    fs::remove_file(log_file(&sandbox)).unwrap();
+    fs::create_dir(log_file(&sandbox)).unwrap();
+
+    let report = match moy_sekret::encrypt(&sandbox.profile, &file_path, true) {
+        Ok(report) => report,
+        Err(reason) => panic!("Should have encrypted file but: {}", reason),
+    };
+    assert!(fs::metadata(sandbox.storage_file("ledger.txt.cz")).is_ok());
+    assert_eq!(1, report.warnings.len());
+}
@@ -0,0 +1,161 @@
+extern crate moy_sekret;
+
+use moy_sekret::SecretBytes;
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn encrypt_sample(sandbox: &Sandbox) -> String {
+    let file_path = sandbox.write_local_file("sample.txt", b"worth backing up");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+    fs::remove_file(&file_path).unwrap();
+    sandbox.storage_file("sample.txt.cz")
+}
+
+fn as_texts(blocks: &[String]) -> Vec<SecretBytes> {
+    blocks
+        .iter()
+        .map(|block| SecretBytes::new(block.as_bytes().to_vec()))
+        .collect()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_restore_key_from_plain_backup() {
+    let sandbox = Sandbox::with_profile("backup_plain");
+    let encrypted_file_path = encrypt_sample(&sandbox);
+
+    let blocks = moy_sekret::backup::export(&sandbox.profile, 1, 1).unwrap();
+    assert_eq!(1, blocks.len());
+    assert!(blocks[0].starts_with("-----BEGIN MOY SEKRET KEY BACKUP-----"));
+
+    // Storage is lost, but the encrypted file was kept elsewhere
+    let kept_file_path = format!("{}/sample.txt.cz", sandbox.local_dir);
+    fs::copy(&encrypted_file_path, &kept_file_path).unwrap();
+    fs::remove_dir_all(&sandbox.storage_dir).unwrap();
+
+    if let Err(reason) = moy_sekret::backup::restore(
+        &sandbox.profile,
+        &sandbox.storage_dir,
+        &as_texts(&blocks),
+        true,
+    ) {
+        panic!("Should have restored key but: {}", reason);
+    }
+
+    moy_sekret::decrypt(&sandbox.profile, &kept_file_path, &sandbox.local_dir, false).unwrap();
+    let plain_file_path = format!("{}/sample.txt", sandbox.local_dir);
+    assert_eq!(
+        b"worth backing up".to_vec(),
+        fs::read(plain_file_path).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_restore_key_from_enough_shares_only() {
+    let sandbox = Sandbox::with_profile("backup_shares");
+    let encrypted_file_path = encrypt_sample(&sandbox);
+
+    let blocks = moy_sekret::backup::export(&sandbox.profile, 5, 3).unwrap();
+    assert_eq!(5, blocks.len());
+
+    match moy_sekret::backup::restore(
+        &sandbox.profile,
+        &sandbox.storage_dir,
+        &as_texts(&blocks[1..3]),
+        true,
+    ) {
+        Ok(_) => panic!("Should not have restored key from two shares"),
+        Err(reason) => assert!(reason.to_string().contains("Only 2 of the 3")),
+    }
+
+    let chosen_blocks = vec![blocks[4].to_owned(), blocks[0].to_owned() + &blocks[2]];
+    if let Err(reason) = moy_sekret::backup::restore(
+        &sandbox.profile,
+        &sandbox.storage_dir,
+        &as_texts(&chosen_blocks),
+        true,
+    ) {
+        panic!("Should have restored key but: {}", reason);
+    }
+
+    moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+#[testaun_case]
+fn should_not_restore_key_from_mistyped_backup() {
+    let sandbox = Sandbox::with_profile("backup_typo");
+    let blocks = moy_sekret::backup::export(&sandbox.profile, 1, 1).unwrap();
+
+    let body_line = blocks[0].lines().nth(4).unwrap().to_owned();
+    let first = body_line.chars().next().unwrap();
+    let typo = if first == 'A' { 'B' } else { 'A' };
+    let mistyped_line = format!("{}{}", typo, &body_line[1..]);
+    let mistyped = blocks[0].replace(&body_line, &mistyped_line);
+
+    match moy_sekret::backup::restore(
+        &sandbox.profile,
+        &sandbox.storage_dir,
+        &as_texts(&[mistyped]),
+        true,
+    ) {
+        Ok(_) => panic!("Should not have restored key from mistyped backup"),
+        Err(reason) => assert!(reason.to_string().contains("checksum does not match")),
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_set_profile_up_like_init_when_restoring() {
+    let sandbox = Sandbox::with_profile("backup_setup");
+    let blocks = moy_sekret::backup::export(&sandbox.profile, 1, 1).unwrap();
+
+    match moy_sekret::backup::restore(
+        &sandbox.profile,
+        &sandbox.storage_dir,
+        &as_texts(&blocks),
+        false,
+    ) {
+        Ok(_) => panic!("Should not have restored over existing profile"),
+        Err(reason) => assert!(reason.to_string().contains("profile already exists")),
+    }
+
+    let plan =
+        moy_sekret::backup::plan_restore(&sandbox.profile, &sandbox.storage_dir, true).unwrap();
+    assert_eq!(3, plan.overwritten.len());
+
+    moy_sekret::backup::restore(
+        &sandbox.profile,
+        &sandbox.storage_dir,
+        &as_texts(&blocks),
+        true,
+    )
+    .unwrap();
+    let entries = moy_sekret::audit::show(&sandbox.profile).unwrap();
+    assert_eq!("restore", entries.last().unwrap().record.action);
+    assert!(moy_sekret::audit::verify(&sandbox.profile).is_ok());
+}
@@ -0,0 +1,166 @@
+extern crate moy_sekret;
+
+use moy_sekret::batch::Outcome;
+use moy_sekret::{DecryptOptions, EncryptOptions};
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn write_reports(sandbox: &Sandbox, count: usize) {
+    for index in 0..count {
+        sandbox.write_local_file(
+            &format!("report_{}.txt", index),
+            format!("quarterly report #{}", index).as_bytes(),
+        );
+    }
+}
+
+fn failed_paths(outcomes: &[Outcome]) -> Vec<String> {
+    outcomes
+        .iter()
+        .filter(|outcome| outcome.result.is_err())
+        .map(|outcome| outcome.file_path.to_owned())
+        .collect()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_encrypt_every_file_matching_patterns() {
+    let sandbox = Sandbox::with_profile("batch_encrypt");
+    write_reports(&sandbox, 12);
+    sandbox.write_local_file("notes.md", b"not a report");
+
+    let patterns = vec![
+        format!("{}/report_*.txt", sandbox.local_dir),
+        format!("{}/missing.txt", sandbox.local_dir),
+    ];
+    let outcomes = match moy_sekret::batch::encrypt(
+        &sandbox.profile,
+        &patterns,
+        false,
+        &EncryptOptions::default(),
+        None,
+    ) {
+        Ok(outcomes) => outcomes,
+        Err(reason) => panic!("Should have run batch but: {}", reason),
+    };
+
+    assert_eq!(13, outcomes.len());
+    assert_eq!(
+        vec![format!("{}/missing.txt", sandbox.local_dir)],
+        failed_paths(&outcomes)
+    );
+    for index in 0..12 {
+        assert!(fs::metadata(sandbox.storage_file(&format!("report_{}.txt.cz", index))).is_ok());
+    }
+    assert!(fs::metadata(sandbox.storage_file("notes.md.cz")).is_err());
+}
+
+#[test]
+#[testaun_case]
+fn should_decrypt_every_file_matching_patterns() {
+    let sandbox = Sandbox::with_profile("batch_decrypt");
+    write_reports(&sandbox, 6);
+    let patterns = vec![format!("{}/*.txt", sandbox.local_dir)];
+    moy_sekret::batch::encrypt(
+        &sandbox.profile,
+        &patterns,
+        false,
+        &EncryptOptions::default(),
+        Some(2),
+    )
+    .unwrap();
+
+    let dest_dir = format!("{}/plain", sandbox.local_dir);
+    let patterns = vec![format!("{}/*.cz", sandbox.storage_dir)];
+    let outcomes = moy_sekret::batch::decrypt(
+        &sandbox.profile,
+        &patterns,
+        &dest_dir,
+        false,
+        &DecryptOptions::default(),
+        Some(2),
+    )
+    .unwrap();
+
+    assert_eq!(6, outcomes.len());
+    assert!(failed_paths(&outcomes).is_empty());
+    for index in 0..6 {
+        assert_eq!(
+            format!("quarterly report #{}", index).into_bytes(),
+            fs::read(format!("{}/report_{}.txt", dest_dir, index)).unwrap()
+        );
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_keep_going_when_some_files_fail() {
+    let sandbox = Sandbox::with_profile("batch_partial");
+    write_reports(&sandbox, 3);
+    let file_path = sandbox.write_local_file("report_1.txt", b"encrypted before");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+
+    let patterns = vec![format!("{}/report_*.txt", sandbox.local_dir)];
+    let outcomes = moy_sekret::batch::encrypt(
+        &sandbox.profile,
+        &patterns,
+        false,
+        &EncryptOptions::default(),
+        None,
+    )
+    .unwrap();
+
+    let failed = failed_paths(&outcomes);
+    assert_eq!(1, failed.len());
+    assert!(failed[0].ends_with("report_1.txt"));
+    assert!(fs::metadata(sandbox.storage_file("report_0.txt.cz")).is_ok());
+    assert!(fs::metadata(sandbox.storage_file("report_2.txt.cz")).is_ok());
+}
+
+#[test]
+#[testaun_case]
+fn should_not_let_same_named_files_replace_one_another() {
+    let sandbox = Sandbox::with_profile("batch_clash");
+    for year in &["2019", "2020"] {
+        fs::create_dir_all(format!("{}/{}", sandbox.local_dir, year)).unwrap();
+        sandbox.write_local_file(
+            &format!("{}/summary.txt", year),
+            format!("summary of {}", year).as_bytes(),
+        );
+    }
+    sandbox.write_local_file("2020/budget.txt", b"budget of 2020");
+
+    let patterns = vec![format!("{}/*/*.txt", sandbox.local_dir)];
+    let outcomes = moy_sekret::batch::encrypt(
+        &sandbox.profile,
+        &patterns,
+        false,
+        &EncryptOptions::default(),
+        None,
+    )
+    .unwrap();
+
+    let failed = failed_paths(&outcomes);
+    assert_eq!(2, failed.len());
+    assert!(failed[0].ends_with("2019/summary.txt"));
+    assert!(failed[1].ends_with("2020/summary.txt"));
+    assert!(fs::metadata(sandbox.storage_file("summary.txt.cz")).is_err());
+    assert!(fs::metadata(sandbox.storage_file("budget.txt.cz")).is_ok());
+}
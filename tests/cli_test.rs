@@ -0,0 +1,109 @@
+extern crate moy_sekret;
+
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::cli;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+// Exit codes are truncated to a byte, 666 reaches the shell as 154
+const ERROR_EXIT_CODE: i32 = 666 & 0xff;
+
+const NO_PROMPT_MESSAGE: &str =
+    "There is no terminal to prompt on or input is disabled, use --yes to proceed";
+
+fn encrypt_once(sandbox: &Sandbox) -> String {
+    let file_path = sandbox.write_local_file("plan.txt", b"first version");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+    sandbox.write_local_file("plan.txt", b"second version");
+    file_path
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_fail_rather_than_prompt_without_a_terminal() {
+    let sandbox = Sandbox::with_profile("cli_no_terminal");
+    let file_path = encrypt_once(&sandbox);
+    let encrypted_content = fs::read(sandbox.storage_file("plan.txt.cz")).unwrap();
+
+    let output = cli::run(&[
+        "encrypt",
+        "-p",
+        &sandbox.profile,
+        "-f",
+        &file_path,
+        "--override",
+    ]);
+
+    assert_eq!(Some(ERROR_EXIT_CODE), output.status.code());
+    assert!(cli::stderr(&output).contains(NO_PROMPT_MESSAGE));
+    assert_eq!(
+        encrypted_content,
+        fs::read(sandbox.storage_file("plan.txt.cz")).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_fail_rather_than_prompt_when_input_is_disabled() {
+    let sandbox = Sandbox::with_profile("cli_no_input");
+    let file_path = encrypt_once(&sandbox);
+
+    let output = cli::run(&[
+        "--no-input",
+        "encrypt",
+        "-p",
+        &sandbox.profile,
+        "-f",
+        &file_path,
+        "--override",
+    ]);
+
+    assert_eq!(Some(ERROR_EXIT_CODE), output.status.code());
+    assert!(cli::stderr(&output).contains(NO_PROMPT_MESSAGE));
+}
+
+#[test]
+#[testaun_case]
+fn should_skip_prompt_when_told_yes() {
+    let sandbox = Sandbox::with_profile("cli_yes");
+    let file_path = encrypt_once(&sandbox);
+
+    let output = cli::run(&[
+        "--yes",
+        "encrypt",
+        "-p",
+        &sandbox.profile,
+        "-f",
+        &file_path,
+        "--override",
+    ]);
+
+    assert_eq!(Some(0), output.status.code(), "{}", cli::stderr(&output));
+    assert!(cli::stdout(&output).contains("Encryption succesfully done"));
+
+    fs::remove_file(&file_path).unwrap();
+    moy_sekret::decrypt(
+        &sandbox.profile,
+        &sandbox.storage_file("plan.txt.cz"),
+        &sandbox.local_dir,
+        false,
+    )
+    .unwrap();
+    assert_eq!(b"second version".to_vec(), fs::read(&file_path).unwrap());
+}
@@ -26,3 +26,103 @@ pub mod fixtures {
     pub const F_PROFILE: &str = "int_tester";
     pub const F_OVERRIDE_PROFILE: bool = false;
 }
+
+// Sandbox
+//
+
+#[allow(dead_code)]
+pub mod sandbox {
+    use std::fs;
+    use std::path::Path;
+
+    // Each test gets its own profile and directories, so tests can run in parallel
+    pub struct Sandbox {
+        pub profile: String,
+        pub storage_dir: String,
+        pub local_dir: String,
+    }
+
+    impl Sandbox {
+        pub fn new(test_name: &str) -> Sandbox {
+            let sandbox = Sandbox {
+                profile: format!("{}_{}", super::fixtures::F_PROFILE, test_name),
+                storage_dir: format!("{}/{}/storage", super::fixtures::F_STORAGE_DIR, test_name),
+                local_dir: format!("{}/{}/local", super::fixtures::F_STORAGE_DIR, test_name),
+            };
+            sandbox.clean();
+            fs::create_dir_all(&sandbox.local_dir).unwrap();
+            sandbox
+        }
+
+        pub fn with_profile(test_name: &str) -> Sandbox {
+            let sandbox = Sandbox::new(test_name);
+            moy_sekret::init(&sandbox.profile, &sandbox.storage_dir, true).unwrap();
+            sandbox
+        }
+
+        pub fn write_local_file(&self, file_name: &str, content: &[u8]) -> String {
+            let file_path = format!("{}/{}", self.local_dir, file_name);
+            fs::write(&file_path, content).unwrap();
+            file_path
+        }
+
+        pub fn storage_file(&self, file_name: &str) -> String {
+            let abs_storage_dir = Path::new(&self.storage_dir).canonicalize().unwrap();
+            format!("{}/{}", abs_storage_dir.display(), file_name)
+        }
+
+        pub fn profile_file(&self) -> String {
+            match dirs::home_dir() {
+                Some(path) => format!("{}/.moy-sekret.{}.toml", path.display(), self.profile),
+                None => format!(".moy-sekret.{}.toml", self.profile),
+            }
+        }
+
+        fn clean(&self) {
+            let _ = fs::remove_file(self.profile_file());
+            if let Some(test_dir) = Path::new(&self.storage_dir).parent() {
+                let _ = fs::remove_dir_all(test_dir);
+            }
+        }
+    }
+
+    impl Drop for Sandbox {
+        fn drop(&mut self) {
+            self.clean();
+        }
+    }
+}
+
+// Command line
+//
+
+#[allow(dead_code)]
+pub mod cli {
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    // Input is never a terminal here, just as in scripts and CI jobs
+    pub fn run(args: &[&str]) -> Output {
+        run_with_input(args, b"")
+    }
+
+    pub fn run_with_input(args: &[&str], input: &[u8]) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_moy-sekret"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    pub fn stdout(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    pub fn stderr(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+}
@@ -0,0 +1,89 @@
+extern crate moy_sekret;
+
+use moy_sekret::{Compression, EncryptOptions};
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn compressible_content() -> Vec<u8> {
+    b"{\"level\":\"info\",\"message\":\"request served\"}\n".repeat(500)
+}
+
+fn encrypted_size(sandbox: &Sandbox, options: &EncryptOptions) -> u64 {
+    let file_path = sandbox.write_local_file("app.log", &compressible_content());
+    moy_sekret::encrypt_with_options(&sandbox.profile, &file_path, true, &options).unwrap();
+    fs::metadata(sandbox.storage_file("app.log.cz"))
+        .unwrap()
+        .len()
+}
+
+fn decrypted_content(sandbox: &Sandbox) -> Vec<u8> {
+    let encrypted_file_path = sandbox.storage_file("app.log.cz");
+    moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        true,
+    )
+    .unwrap();
+    fs::read(format!("{}/app.log", sandbox.local_dir)).unwrap()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_compress_before_encrypting_when_asked_to() {
+    let sandbox = Sandbox::with_profile("compression_override");
+
+    let plain_size = encrypted_size(&sandbox, &EncryptOptions::default());
+    let compressed_size = encrypted_size(
+        &sandbox,
+        &EncryptOptions {
+            compression: Some(Compression::Zstd),
+            ..EncryptOptions::default()
+        },
+    );
+
+    assert!(compressed_size * 10 < plain_size);
+    assert_eq!(compressible_content(), decrypted_content(&sandbox));
+}
+
+#[test]
+#[testaun_case]
+fn should_use_profile_compression_unless_overridden() {
+    let sandbox = Sandbox::with_profile("compression_profile");
+    let compression = "compression".to_owned();
+
+    if let Err(reason) = moy_sekret::configure(&sandbox.profile, &compression, &"zstd".to_owned()) {
+        panic!("Should have configured profile but: {}", reason);
+    }
+    let profile_default_size = encrypted_size(&sandbox, &EncryptOptions::default());
+    assert_eq!(compressible_content(), decrypted_content(&sandbox));
+
+    let overridden_size = encrypted_size(
+        &sandbox,
+        &EncryptOptions {
+            compression: Some(Compression::None),
+            ..EncryptOptions::default()
+        },
+    );
+    assert!(profile_default_size * 10 < overridden_size);
+    assert_eq!(compressible_content(), decrypted_content(&sandbox));
+
+    assert!(moy_sekret::configure(&sandbox.profile, &compression, &"gzip".to_owned()).is_err());
+}
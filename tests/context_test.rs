@@ -0,0 +1,194 @@
+extern crate moy_sekret;
+
+use moy_sekret::{DecryptOptions, EncryptOptions, SecretBytes};
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn label_options(label: &str) -> DecryptOptions {
+    DecryptOptions {
+        label: Some(label.to_owned()),
+    }
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_not_decrypt_file_swapped_for_another_one() {
+    let sandbox = Sandbox::with_profile("context_swap");
+    let prod_file_path = sandbox.write_local_file("prod.env", b"DB_PASSWORD=prod");
+    let dev_file_path = sandbox.write_local_file("dev.env", b"DB_PASSWORD=dev");
+    moy_sekret::encrypt(&sandbox.profile, &prod_file_path, true).unwrap();
+    moy_sekret::encrypt(&sandbox.profile, &dev_file_path, true).unwrap();
+
+    fs::copy(
+        sandbox.storage_file("dev.env.cz"),
+        sandbox.storage_file("prod.env.cz"),
+    )
+    .unwrap();
+
+    let decrypted = moy_sekret::decrypt(
+        &sandbox.profile,
+        &sandbox.storage_file("prod.env.cz"),
+        &sandbox.local_dir,
+        true,
+    );
+    match decrypted {
+        Ok(_) => panic!("Should not have decrypted swapped file"),
+        Err(reason) => assert!(format!("{}", reason).contains("cannot stand in for prod.env")),
+    }
+    assert_eq!(
+        b"DB_PASSWORD=prod".to_vec(),
+        fs::read(&prod_file_path).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_check_label_bound_on_encryption() {
+    let sandbox = Sandbox::with_profile("context_label");
+    let file_path = sandbox.write_local_file("app.env", b"API_KEY=prod");
+    let options = EncryptOptions {
+        label: Some("prod".to_owned()),
+        ..EncryptOptions::default()
+    };
+    moy_sekret::encrypt_with_options(&sandbox.profile, &file_path, true, &options).unwrap();
+    let encrypted_file_path = sandbox.storage_file("app.env.cz");
+
+    for options in &[label_options("staging"), DecryptOptions::default()] {
+        assert!(moy_sekret::decrypt_with_options(
+            &sandbox.profile,
+            &encrypted_file_path,
+            &sandbox.local_dir,
+            true,
+            &options,
+        )
+        .is_err());
+    }
+
+    if let Err(reason) = moy_sekret::decrypt_with_options(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        true,
+        &label_options("prod"),
+    ) {
+        panic!("Should have decrypted file but: {}", reason);
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_not_decrypt_file_as_another_profile() {
+    let sandbox = Sandbox::with_profile("context_profile");
+    let file_path = sandbox.write_local_file("app.env", b"API_KEY=prod");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, true).unwrap();
+
+    // Same keys under another name, which still must not open files made by the first one
+    let other_profile = format!("{}_other", sandbox.profile);
+    let other_storage_dir = format!("{}/other", sandbox.local_dir);
+    let blocks = moy_sekret::backup::export(&sandbox.profile, 1, 1).unwrap();
+    let backup_texts = vec![SecretBytes::new(blocks[0].as_bytes().to_vec())];
+    moy_sekret::backup::restore(&other_profile, &other_storage_dir, &backup_texts, true).unwrap();
+
+    let decrypted = moy_sekret::decrypt(
+        &other_profile,
+        &sandbox.storage_file("app.env.cz"),
+        &sandbox.local_dir,
+        true,
+    );
+    match decrypted {
+        Ok(_) => panic!("Should not have decrypted file as another profile"),
+        Err(reason) => assert!(format!("{}", reason).contains("cannot be opened as")),
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_not_decrypt_passphrase_file_swapped_for_another_one() {
+    let sandbox = Sandbox::new("context_passphrase");
+    let passphrase = SecretBytes::new(b"same for both".to_vec());
+    for (file_name, content) in &[("prod.env", b"KEY=prod"), ("dev.env", b"KEY=dev_")] {
+        let file_path = sandbox.write_local_file(file_name, *content);
+        moy_sekret::encrypt_with_passphrase(
+            &file_path,
+            &passphrase,
+            true,
+            &EncryptOptions::default(),
+        )
+        .unwrap();
+    }
+
+    let prod_file_path = format!("{}/prod.env.cz", sandbox.local_dir);
+    fs::copy(format!("{}/dev.env.cz", sandbox.local_dir), &prod_file_path).unwrap();
+
+    let dest_dir = format!("{}/plain", sandbox.local_dir);
+    assert!(moy_sekret::decrypt_with_passphrase(
+        &prod_file_path,
+        &passphrase,
+        &dest_dir,
+        true,
+        &DecryptOptions::default()
+    )
+    .is_err());
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_not_inject_variables_from_swapped_or_unlabeled_file() {
+    let sandbox = Sandbox::with_profile("context_exec");
+    let prod_file_path = sandbox.write_local_file("prod.env", b"DB_PASSWORD=prod");
+    let dev_file_path = sandbox.write_local_file("dev.env", b"DB_PASSWORD=dev");
+    let options = EncryptOptions {
+        label: Some("prod".to_owned()),
+        ..EncryptOptions::default()
+    };
+    moy_sekret::encrypt_with_options(&sandbox.profile, &prod_file_path, true, &options).unwrap();
+    moy_sekret::encrypt(&sandbox.profile, &dev_file_path, true).unwrap();
+    let command = vec!["true".to_owned()];
+
+    let encrypted_file_path = sandbox.storage_file("prod.env.cz");
+    assert!(moy_sekret::exec::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &command,
+        &DecryptOptions::default(),
+    )
+    .is_err());
+    match moy_sekret::exec::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &command,
+        &label_options("prod"),
+    ) {
+        Ok(code) => assert_eq!(0, code),
+        Err(reason) => panic!("Should have run command but: {}", reason),
+    }
+
+    fs::copy(sandbox.storage_file("dev.env.cz"), &encrypted_file_path).unwrap();
+    match moy_sekret::exec::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &command,
+        &label_options("prod"),
+    ) {
+        Ok(_) => panic!("Should not have run command with swapped file"),
+        Err(reason) => assert!(format!("{}", reason).contains("cannot stand in for prod.env")),
+    }
+}
@@ -7,6 +7,7 @@ use testaun::testaun_case;
 #[macro_use]
 pub mod common;
 use common::fixtures::*;
+use common::sandbox::Sandbox;
 
 // Helpers
 //
@@ -42,3 +43,51 @@ fn should_whatever() {
 fn should_whatever_else() {
     assert!(false, "you so wrong");
 }
+
+#[test]
+#[testaun_case]
+fn should_decrypt_a_file_previously_encrypted() {
+    let sandbox = Sandbox::with_profile("decrypt_round_trip");
+    let file_path = sandbox.write_local_file("important.txt", b"This is important");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+
+    let encrypted_file_path = sandbox.storage_file("important.txt.cz");
+    let dest_dir = format!("{}/plain", sandbox.local_dir);
+    match moy_sekret::decrypt(&sandbox.profile, &encrypted_file_path, &dest_dir, false) {
+        Ok(_) => {
+            let content = fs::read(format!("{}/important.txt", dest_dir)).unwrap();
+            assert_eq!(b"This is important".to_vec(), content);
+        }
+        Err(reason) => panic!("Should have decrypted but: {}", reason),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_not_decrypt_through_a_symlink_at_destination() {
+    let sandbox = Sandbox::with_profile("decrypt_symlink");
+    let file_path = sandbox.write_local_file("important.txt", b"This is important");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+
+    let abs_local_dir = Path::new(&sandbox.local_dir).canonicalize().unwrap();
+    let victim_path = format!("{}/victim.txt", abs_local_dir.display());
+    let dest_dir = format!("{}/plain", sandbox.local_dir);
+    fs::create_dir_all(&dest_dir).unwrap();
+    std::os::unix::fs::symlink(&victim_path, format!("{}/important.txt", dest_dir)).unwrap();
+
+    let encrypted_file_path = sandbox.storage_file("important.txt.cz");
+    let should_override = true;
+    match moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &dest_dir,
+        should_override,
+    ) {
+        Ok(_) => panic!("Should not have decrypted through a symlink"),
+        Err(reason) => {
+            assert!(reason.to_string().contains("Refusing to follow symlink"));
+            assert!(!Path::new(&victim_path).exists());
+        }
+    }
+}
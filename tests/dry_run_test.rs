@@ -0,0 +1,96 @@
+extern crate moy_sekret;
+
+use moy_sekret::EncryptOptions;
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_plan_init_without_touching_existing_keys() {
+    let sandbox = Sandbox::with_profile("dry_run_init");
+    let secret_key_path = sandbox.storage_file(&format!("{}.sk", sandbox.profile));
+    let secret_key = fs::read(&secret_key_path).unwrap();
+
+    let plan = match moy_sekret::plan_init(&sandbox.profile, &sandbox.storage_dir, true) {
+        Ok(plan) => plan,
+        Err(reason) => panic!("Should have planned init but: {}", reason),
+    };
+
+    assert!(plan.created.is_empty());
+    assert_eq!(3, plan.overwritten.len());
+    assert!(plan.overwritten.contains(&secret_key_path));
+    assert_eq!(secret_key, fs::read(&secret_key_path).unwrap());
+
+    assert!(moy_sekret::plan_init(&sandbox.profile, &sandbox.storage_dir, false).is_err());
+}
+
+#[test]
+#[testaun_case]
+fn should_plan_encrypt_and_source_removal_without_touching_disk() {
+    let sandbox = Sandbox::with_profile("dry_run_encrypt");
+    let file_path = sandbox.write_local_file("plan.txt", b"to be or not to be");
+
+    let plan = match moy_sekret::plan_encrypt(&sandbox.profile, &file_path, false)
+        .and_then(|plan| Ok(plan.merge(moy_sekret::plan_remove_source(&file_path)?)))
+    {
+        Ok(plan) => plan,
+        Err(reason) => panic!("Should have planned encryption but: {}", reason),
+    };
+
+    assert_eq!(vec![sandbox.storage_file("plan.txt.cz")], plan.created);
+    assert_eq!(vec![file_path.to_owned()], plan.removed);
+    assert!(fs::metadata(sandbox.storage_file("plan.txt.cz")).is_err());
+    assert!(fs::metadata(&file_path).is_ok());
+
+    moy_sekret::encrypt_with_options(
+        &sandbox.profile,
+        &file_path,
+        false,
+        &EncryptOptions::default(),
+    )
+    .unwrap();
+    assert!(moy_sekret::plan_encrypt(&sandbox.profile, &file_path, false).is_err());
+    match moy_sekret::plan_encrypt(&sandbox.profile, &file_path, true) {
+        Ok(plan) => assert_eq!(vec![sandbox.storage_file("plan.txt.cz")], plan.overwritten),
+        Err(reason) => panic!("Should have planned encryption but: {}", reason),
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_plan_batch_decrypt_file_by_file() {
+    let sandbox = Sandbox::with_profile("dry_run_batch");
+    for file_name in &["one.txt", "two.txt"] {
+        let file_path = sandbox.write_local_file(file_name, b"plain content");
+        moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+    }
+    fs::remove_file(format!("{}/one.txt", sandbox.local_dir)).unwrap();
+
+    let patterns = vec![sandbox.storage_file("*.cz")];
+    let outcomes = match moy_sekret::batch::plan_decrypt(&patterns, &sandbox.local_dir, false) {
+        Ok(outcomes) => outcomes,
+        Err(reason) => panic!("Should have planned batch but: {}", reason),
+    };
+
+    assert_eq!(2, outcomes.len());
+    match &outcomes[0].result {
+        Ok(plan) => assert_eq!(vec![format!("{}/one.txt", sandbox.local_dir)], plan.created),
+        Err(reason) => panic!("Should have planned decryption but: {}", reason),
+    }
+    assert!(outcomes[1].result.is_err());
+    assert!(fs::metadata(format!("{}/one.txt", sandbox.local_dir)).is_err());
+}
@@ -0,0 +1,168 @@
+extern crate moy_sekret;
+
+use moy_sekret::{Compression, DecryptOptions, EncryptOptions};
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn encrypt_file(sandbox: &Sandbox, content: &[u8]) -> String {
+    let file_path = sandbox.write_local_file("config.toml", content);
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+    sandbox.storage_file("config.toml.cz")
+}
+
+fn decrypted_content(sandbox: &Sandbox, encrypted_file_path: &String) -> Vec<u8> {
+    let dest_dir = format!("{}/plain", sandbox.local_dir);
+    moy_sekret::decrypt(&sandbox.profile, &encrypted_file_path, &dest_dir, true).unwrap();
+    fs::read(format!("{}/config.toml", dest_dir)).unwrap()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_encrypt_changes_made_by_editor() {
+    let sandbox = Sandbox::with_profile("edit_changes");
+    let encrypted_file_path = encrypt_file(&sandbox, b"port = 8080\n");
+    let editor = "sed -i s/8080/9090/".to_string();
+
+    match moy_sekret::edit::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &editor,
+        &DecryptOptions::default(),
+    ) {
+        Ok(report) => {
+            assert_eq!(vec![encrypted_file_path.clone()], report.written);
+            assert_eq!(
+                b"port = 9090\n".to_vec(),
+                decrypted_content(&sandbox, &encrypted_file_path)
+            );
+        }
+        Err(reason) => panic!("Should have edited file but: {}", reason),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_not_encrypt_again_when_nothing_changed() {
+    let sandbox = Sandbox::with_profile("edit_unchanged");
+    let encrypted_file_path = encrypt_file(&sandbox, b"port = 8080\n");
+    let encrypted_content = fs::read(&encrypted_file_path).unwrap();
+
+    match moy_sekret::edit::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &"true".to_string(),
+        &DecryptOptions::default(),
+    ) {
+        Ok(report) => {
+            assert!(report.written.is_empty());
+            assert_eq!(encrypted_content, fs::read(&encrypted_file_path).unwrap());
+        }
+        Err(reason) => panic!("Should have edited file but: {}", reason),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_discard_changes_when_editor_fails() {
+    let sandbox = Sandbox::with_profile("edit_failing_editor");
+    let encrypted_file_path = encrypt_file(&sandbox, b"port = 8080\n");
+    let encrypted_content = fs::read(&encrypted_file_path).unwrap();
+
+    match moy_sekret::edit::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &"false".to_string(),
+        &DecryptOptions::default(),
+    ) {
+        Ok(_) => panic!("Should not have edited file"),
+        Err(_) => assert_eq!(encrypted_content, fs::read(&encrypted_file_path).unwrap()),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_keep_original_settings_when_encrypting_changes() {
+    let sandbox = Sandbox::with_profile("edit_settings");
+    let content = format!("port = 8080\n{}", "padding = none\n".repeat(2000));
+    let file_path = sandbox.write_local_file("config.toml", content.as_bytes());
+    let options = EncryptOptions {
+        compression: Some(Compression::Zstd),
+        ..EncryptOptions::default()
+    };
+    moy_sekret::encrypt_with_options(&sandbox.profile, &file_path, false, &options).unwrap();
+    let encrypted_file_path = sandbox.storage_file("config.toml.cz");
+    let editor = "sed -i s/8080/9090/".to_string();
+
+    match moy_sekret::edit::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &editor,
+        &DecryptOptions::default(),
+    ) {
+        Ok(_) => {
+            // Still compressed, while the profile itself would not have compressed it
+            assert!(fs::metadata(&encrypted_file_path).unwrap().len() < 1024);
+            assert_eq!(
+                content.replace("8080", "9090").into_bytes(),
+                decrypted_content(&sandbox, &encrypted_file_path)
+            );
+        }
+        Err(reason) => panic!("Should have edited file but: {}", reason),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_replace_encrypted_file_rather_than_write_through_symlink() {
+    let sandbox = Sandbox::with_profile("edit_symlink");
+    let encrypted_file_path = encrypt_file(&sandbox, b"port = 8080\n");
+    let encrypted_content = fs::read(&encrypted_file_path).unwrap();
+
+    let link_dir = format!("{}/link", sandbox.local_dir);
+    let link_path = format!("{}/config.toml.cz", link_dir);
+    fs::create_dir_all(&link_dir).unwrap();
+    std::os::unix::fs::symlink(&encrypted_file_path, &link_path).unwrap();
+
+    let editor = "sed -i s/8080/9090/".to_string();
+    if let Err(reason) = moy_sekret::edit::run(
+        &sandbox.profile,
+        &link_path,
+        &editor,
+        &DecryptOptions::default(),
+    ) {
+        panic!("Should have edited file but: {}", reason);
+    }
+
+    assert!(!fs::symlink_metadata(&link_path)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(encrypted_content, fs::read(&encrypted_file_path).unwrap());
+    assert_eq!(
+        b"port = 9090\n".to_vec(),
+        decrypted_content(&sandbox, &link_path)
+    );
+    assert_eq!(1, fs::read_dir(&link_dir).unwrap().count());
+}
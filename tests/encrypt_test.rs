@@ -7,6 +7,7 @@ use testaun::testaun_case;
 #[macro_use]
 pub mod common;
 use common::fixtures::*;
+use common::sandbox::Sandbox;
 
 // Helpers
 //
@@ -42,3 +43,49 @@ fn should_whatever() {
 fn should_whatever_else() {
     assert!(false, "you so wrong");
 }
+
+#[test]
+#[testaun_case]
+fn should_remove_source_file_after_encrypting_it() {
+    let sandbox = Sandbox::with_profile("encrypt_remove_source");
+    let file_path = sandbox.write_local_file("important.txt", b"This is important");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+
+    match moy_sekret::remove_source(&sandbox.profile, &file_path) {
+        Ok(_) => {
+            assert!(!Path::new(&file_path).exists());
+
+            let encrypted_file_path = sandbox.storage_file("important.txt.cz");
+            moy_sekret::decrypt(
+                &sandbox.profile,
+                &encrypted_file_path,
+                &sandbox.local_dir,
+                false,
+            )
+            .unwrap();
+            let content = fs::read(&file_path).unwrap();
+            assert_eq!(b"This is important".to_vec(), content);
+        }
+        Err(reason) => panic!("Should have removed source file but: {}", reason),
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_not_remove_source_file_when_encrypted_one_does_not_match() {
+    let sandbox = Sandbox::with_profile("encrypt_remove_source_mismatch");
+    let file_path = sandbox.write_local_file("important.txt", b"This is important");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+    fs::write(&file_path, b"This changed afterwards").unwrap();
+
+    match moy_sekret::remove_source(&sandbox.profile, &file_path) {
+        Ok(_) => panic!("Should not have removed a source file that was not encrypted"),
+        Err(reason) => {
+            assert_eq!(
+                "Source removal failed while verifying encrypted file: Encrypted file does not match source file",
+                reason.to_string()
+            );
+            assert!(Path::new(&file_path).exists());
+        }
+    }
+}
@@ -0,0 +1,96 @@
+extern crate moy_sekret;
+
+use moy_sekret::DecryptOptions;
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn encrypt_dotenv(sandbox: &Sandbox, content: &[u8]) -> String {
+    let file_path = sandbox.write_local_file("app.env", content);
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+    fs::remove_file(&file_path).unwrap();
+    sandbox.storage_file("app.env.cz")
+}
+
+fn shell(script: &str) -> Vec<String> {
+    vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()]
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_run_command_with_decrypted_variables() {
+    let sandbox = Sandbox::with_profile("exec_variables");
+    let encrypted_file_path = encrypt_dotenv(
+        &sandbox,
+        b"# Database\nexport DB_USER=admin\nDB_PASS=\"s3cr3t \\\"quoted\\\"\"\nGREETING='hello world' \nEMPTY=\nPLAIN=value # comment\n",
+    );
+
+    let script = r#"test "$DB_USER" = admin && test "$DB_PASS" = 's3cr3t "quoted"' && test "$GREETING" = 'hello world' && test -z "$EMPTY" && test "$PLAIN" = value"#;
+    match moy_sekret::exec::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &shell(script),
+        &DecryptOptions::default(),
+    ) {
+        Ok(code) => assert_eq!(0, code),
+        Err(reason) => panic!("Should have run command but: {}", reason),
+    }
+
+    let local_files = fs::read_dir(&sandbox.local_dir).unwrap().count();
+    assert_eq!(0, local_files);
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_forward_command_exit_code() {
+    let sandbox = Sandbox::with_profile("exec_exit_code");
+    let encrypted_file_path = encrypt_dotenv(&sandbox, b"CODE=42\n");
+
+    match moy_sekret::exec::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &shell("exit $CODE"),
+        &DecryptOptions::default(),
+    ) {
+        Ok(code) => assert_eq!(42, code),
+        Err(reason) => panic!("Should have run command but: {}", reason),
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_not_run_command_when_dotenv_is_malformed() {
+    let sandbox = Sandbox::with_profile("exec_malformed");
+    let encrypted_file_path = encrypt_dotenv(&sandbox, b"GOOD=1\nthis is not a variable\n");
+
+    match moy_sekret::exec::run(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &shell("true"),
+        &DecryptOptions::default(),
+    ) {
+        Ok(_) => panic!("Should not have run command"),
+        Err(reason) => assert_eq!(
+            "Execution failed while parsing environment file: Could not parse line 2 of environment file",
+            reason.to_string()
+        ),
+    }
+}
@@ -0,0 +1,134 @@
+extern crate moy_sekret;
+
+use moy_sekret::git_filter;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn clean(sandbox: &Sandbox, file_path: &str, content: &[u8]) -> Vec<u8> {
+    let mut cleaned = Vec::new();
+    git_filter::clean(
+        &sandbox.profile,
+        &file_path.to_string(),
+        &mut &content[..],
+        &mut cleaned,
+    )
+    .unwrap();
+    cleaned
+}
+
+fn smudge(sandbox: &Sandbox, file_path: &str, content: &[u8]) -> Result<Vec<u8>, String> {
+    let mut smudged = Vec::new();
+    git_filter::smudge(
+        &sandbox.profile,
+        &file_path.to_string(),
+        &mut &content[..],
+        &mut smudged,
+    )
+    .map_err(|reason| reason.to_string())?;
+    Ok(smudged)
+}
+
+// Data goes last with its length ahead of it, and the nonce right before that
+fn nonce_of(cleaned: &[u8]) -> Vec<u8> {
+    let data_len_at = (0..cleaned.len() - 8)
+        .rev()
+        .find(|&index| {
+            let mut raw_len = [0; 8];
+            raw_len.copy_from_slice(&cleaned[index..index + 8]);
+            u64::from_le_bytes(raw_len) as usize == cleaned.len() - index - 8
+        })
+        .unwrap();
+    cleaned[data_len_at - 24..data_len_at].to_vec()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_clean_deterministically_and_smudge_back() {
+    let sandbox = Sandbox::with_profile("git_filter_round_trip");
+    let content = b"password=very-secret\n";
+
+    let cleaned = clean(&sandbox, "config/app.env", content);
+    assert_ne!(content.to_vec(), cleaned);
+    assert_eq!(cleaned, clean(&sandbox, "config/app.env", content));
+    assert_ne!(cleaned, clean(&sandbox, "config/other.env", content));
+
+    assert_eq!(
+        Ok(content.to_vec()),
+        smudge(&sandbox, "config/app.env", &cleaned)
+    );
+    assert!(smudge(&sandbox, "config/other.env", &cleaned).is_err());
+}
+
+#[test]
+#[testaun_case]
+fn should_not_reuse_nonce_once_profile_settings_change() {
+    let sandbox = Sandbox::with_profile("git_filter_settings");
+    let content = b"password=very-secret\n";
+
+    let cleaned = clean(&sandbox, "config/app.env", content);
+    moy_sekret::configure(&sandbox.profile, &"padding".to_owned(), &"padme".to_owned()).unwrap();
+    let padded = clean(&sandbox, "config/app.env", content);
+    moy_sekret::configure(
+        &sandbox.profile,
+        &"compression".to_owned(),
+        &"zstd".to_owned(),
+    )
+    .unwrap();
+    let compressed = clean(&sandbox, "config/app.env", content);
+
+    // Same key and path, so only a nonce of its own keeps each keystream apart
+    assert_ne!(nonce_of(&cleaned), nonce_of(&padded));
+    assert_ne!(nonce_of(&cleaned), nonce_of(&compressed));
+    assert_ne!(nonce_of(&padded), nonce_of(&compressed));
+    for cleaned in [cleaned, padded, compressed].iter() {
+        assert_eq!(
+            Ok(content.to_vec()),
+            smudge(&sandbox, "config/app.env", cleaned)
+        );
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_not_encrypt_twice_when_cleaning_encrypted_content() {
+    let sandbox = Sandbox::with_profile("git_filter_idempotent");
+    let cleaned = clean(&sandbox, "app.env", b"password=very-secret\n");
+
+    assert_eq!(cleaned, clean(&sandbox, "app.env", &cleaned));
+}
+
+#[test]
+#[testaun_case]
+fn should_smudge_plain_content_as_it_is() {
+    let sandbox = Sandbox::with_profile("git_filter_plain");
+    let content = b"committed before the filter was set up\n";
+
+    assert_eq!(Ok(content.to_vec()), smudge(&sandbox, "notes.txt", content));
+}
+
+#[test]
+#[testaun_case]
+fn should_not_grow_log_on_every_filter_run() {
+    let sandbox = Sandbox::with_profile("git_filter_quiet");
+    let cleaned = clean(&sandbox, "app.env", b"password=very-secret\n");
+    smudge(&sandbox, "app.env", &cleaned).unwrap();
+
+    let entries = moy_sekret::audit::show(&sandbox.profile).unwrap();
+    assert_eq!(1, entries.len());
+}
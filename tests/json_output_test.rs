@@ -0,0 +1,328 @@
+extern crate moy_sekret;
+
+use serde_json::Value;
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::cli;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+// Exit codes are truncated to a byte, 666 reaches the shell as 154
+const ERROR_EXIT_CODE: i32 = 666 & 0xff;
+
+fn run_json(args: &[&str]) -> (Option<i32>, Value) {
+    run_json_with_input(args, b"")
+}
+
+fn run_json_with_input(args: &[&str], input: &[u8]) -> (Option<i32>, Value) {
+    let mut json_args = vec!["--output", "json"];
+    json_args.extend_from_slice(args);
+    let output = cli::run_with_input(&json_args, input);
+
+    let stdout = cli::stdout(&output);
+    let document = match serde_json::from_str(stdout.trim()) {
+        Ok(document) => document,
+        Err(_) => panic!(
+            "stdout is not a JSON document: {:?}, stderr: {:?}",
+            stdout,
+            cli::stderr(&output)
+        ),
+    };
+    (output.status.code(), document)
+}
+
+fn assert_success(result: (Option<i32>, Value), command: &str) -> Value {
+    let (code, document) = result;
+    assert_eq!(Some(0), code, "{}", document);
+    assert_eq!(command, document["command"]);
+    assert_eq!("ok", document["status"]);
+    document
+}
+
+fn assert_failure(result: (Option<i32>, Value), command: &str) {
+    let (code, document) = result;
+    assert_eq!(Some(ERROR_EXIT_CODE), code, "{}", document);
+    assert_eq!(command, document["command"]);
+    assert_eq!("error", document["status"]);
+    assert_error(&document["error"]);
+}
+
+fn assert_error(error: &Value) {
+    assert!(error["kind"].is_string(), "{}", error);
+    assert!(error["message"].is_string(), "{}", error);
+    assert!(!error["chain"].as_array().unwrap().is_empty(), "{}", error);
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_report_encrypt_as_json() {
+    let sandbox = Sandbox::with_profile("json_encrypt");
+    let file_path = sandbox.write_local_file("notes.txt", b"meeting notes");
+
+    let document = assert_success(
+        run_json(&["encrypt", "-p", &sandbox.profile, "-f", &file_path]),
+        "encrypt",
+    );
+    assert_eq!(1, document["written"].as_array().unwrap().len());
+
+    assert_failure(
+        run_json(&[
+            "encrypt",
+            "-p",
+            &sandbox.profile,
+            "-f",
+            "./does/not/exist.txt",
+        ]),
+        "encrypt",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_decrypt_as_json() {
+    let sandbox = Sandbox::with_profile("json_decrypt");
+    let file_path = sandbox.write_local_file("notes.txt", b"meeting notes");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, true).unwrap();
+    fs::remove_file(&file_path).unwrap();
+
+    assert_success(
+        run_json(&[
+            "decrypt",
+            "-p",
+            &sandbox.profile,
+            "-f",
+            &sandbox.storage_file("notes.txt.cz"),
+            "-d",
+            &sandbox.local_dir,
+        ]),
+        "decrypt",
+    );
+
+    assert_failure(
+        run_json(&[
+            "decrypt",
+            "-p",
+            &sandbox.profile,
+            "-f",
+            &sandbox.storage_file("missing.txt.cz"),
+            "-d",
+            &sandbox.local_dir,
+        ]),
+        "decrypt",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_secret_as_json() {
+    let sandbox = Sandbox::with_profile("json_secret");
+
+    assert_success(
+        run_json_with_input(
+            &["secret", "set", "-p", &sandbox.profile, "db/password"],
+            b"hunter2",
+        ),
+        "secret set",
+    );
+    let document = assert_success(
+        run_json(&["secret", "get", "-p", &sandbox.profile, "db/password"]),
+        "secret get",
+    );
+    assert_eq!("hunter2", document["secret"]["value"]);
+
+    assert_failure(
+        run_json(&["secret", "get", "-p", &sandbox.profile, "db/missing"]),
+        "secret get",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_git_filter_failure_as_json() {
+    let sandbox = Sandbox::with_profile("json_git_filter");
+
+    // Filtered content is the whole stdout on success, only failures get a document
+    let output = cli::run_with_input(
+        &[
+            "--output",
+            "json",
+            "git-filter",
+            "-p",
+            &sandbox.profile,
+            "clean",
+            "--path",
+            "config/secrets.env",
+        ],
+        b"TOKEN=abc",
+    );
+    assert_eq!(Some(0), output.status.code());
+    assert!(!output.stdout.is_empty());
+    assert!(serde_json::from_slice::<Value>(&output.stdout).is_err());
+
+    assert_failure(
+        run_json_with_input(
+            &[
+                "git-filter",
+                "-p",
+                "int_tester_json_git_filter_missing",
+                "clean",
+                "--path",
+                "config/secrets.env",
+            ],
+            b"TOKEN=abc",
+        ),
+        "git-filter",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_batch_as_json() {
+    let sandbox = Sandbox::with_profile("json_batch");
+    sandbox.write_local_file("first.txt", b"first");
+    sandbox.write_local_file("second.txt", b"second");
+    let pattern = format!("{}/*.txt", sandbox.local_dir);
+
+    let document = assert_success(
+        run_json(&["batch", "encrypt", "-p", &sandbox.profile, &pattern]),
+        "batch encrypt",
+    );
+    assert_eq!(2, document["succeeded"]);
+
+    // Both files are already there, so each of them fails on its own
+    let (code, document) = run_json(&["batch", "encrypt", "-p", &sandbox.profile, &pattern]);
+    assert_eq!(Some(ERROR_EXIT_CODE), code, "{}", document);
+    assert_eq!("batch encrypt", document["command"]);
+    assert_eq!("error", document["status"]);
+    assert_eq!(2, document["failed"]);
+    for file in document["files"].as_array().unwrap() {
+        assert_eq!("error", file["status"]);
+        assert_error(&file["error"]);
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_report_age_as_json() {
+    let sandbox = Sandbox::with_profile("json_age");
+    let file_path = sandbox.write_local_file("notes.txt", b"meeting notes");
+
+    assert_success(
+        run_json(&["age", "encrypt", "-p", &sandbox.profile, "-f", &file_path]),
+        "age encrypt",
+    );
+
+    assert_failure(
+        run_json(&["age", "encrypt", "-p", &sandbox.profile, "-f", &file_path]),
+        "age encrypt",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_log_as_json() {
+    let sandbox = Sandbox::with_profile("json_log");
+    let file_path = sandbox.write_local_file("notes.txt", b"meeting notes");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, true).unwrap();
+
+    let document = assert_success(
+        run_json(&["log", "show", "-p", &sandbox.profile]),
+        "log show",
+    );
+    assert!(!document["entries"].as_array().unwrap().is_empty());
+
+    assert_failure(
+        run_json(&["log", "verify", "-p", "int_tester_json_log_missing"]),
+        "log verify",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_backup_key_as_json() {
+    let sandbox = Sandbox::with_profile("json_backup_key");
+
+    let document = assert_success(
+        run_json(&["backup-key", "-p", &sandbox.profile]),
+        "backup-key",
+    );
+    assert_eq!(1, document["items"].as_array().unwrap().len());
+
+    assert_failure(
+        run_json(&[
+            "backup-key",
+            "-p",
+            &sandbox.profile,
+            "--shares",
+            "2",
+            "--threshold",
+            "3",
+        ]),
+        "backup-key",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_config_as_json() {
+    let sandbox = Sandbox::with_profile("json_config");
+
+    assert_success(
+        run_json(&["config", "-p", &sandbox.profile, "padding", "padme"]),
+        "config",
+    );
+
+    assert_failure(
+        run_json(&["config", "-p", &sandbox.profile, "padding", "plenty"]),
+        "config",
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_report_new_recovery_phrase_as_json() {
+    let sandbox = Sandbox::new("json_recovery_phrase");
+    fs::create_dir_all(&sandbox.storage_dir).unwrap();
+
+    let document = assert_success(
+        run_json(&[
+            "init",
+            "-p",
+            &sandbox.profile,
+            "--dir",
+            &sandbox.storage_dir,
+            "--new-recovery-phrase",
+        ]),
+        "init",
+    );
+    let phrase = document["recovery_phrase"].as_str().unwrap();
+    let seed = moy_sekret::recovery::seed_from_phrase(phrase.as_bytes());
+    assert!(seed.is_ok());
+
+    // Keys are already there, so no phrase is handed out for keys that were never made
+    let (code, document) = run_json(&[
+        "init",
+        "-p",
+        &sandbox.profile,
+        "--dir",
+        &sandbox.storage_dir,
+        "--new-recovery-phrase",
+    ]);
+    assert_eq!(Some(ERROR_EXIT_CODE), code, "{}", document);
+    assert!(document.get("recovery_phrase").is_none());
+}
@@ -0,0 +1,87 @@
+extern crate moy_sekret;
+
+use moy_sekret::{EncryptOptions, Padding};
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn encrypted_size(sandbox: &Sandbox, content: &[u8], options: &EncryptOptions) -> u64 {
+    let file_path = sandbox.write_local_file("note.txt", &content);
+    moy_sekret::encrypt_with_options(&sandbox.profile, &file_path, true, &options).unwrap();
+    fs::metadata(sandbox.storage_file("note.txt.cz"))
+        .unwrap()
+        .len()
+}
+
+fn decrypted_content(sandbox: &Sandbox) -> Vec<u8> {
+    let encrypted_file_path = sandbox.storage_file("note.txt.cz");
+    moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.local_dir,
+        true,
+    )
+    .unwrap();
+    fs::read(format!("{}/note.txt", sandbox.local_dir)).unwrap()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_hide_small_size_differences_when_padding() {
+    let sandbox = Sandbox::with_profile("padding_override");
+    let padme = EncryptOptions {
+        padding: Some(Padding::Padme),
+        ..EncryptOptions::default()
+    };
+
+    let shorter_size = encrypted_size(&sandbox, &vec![b'a'; 1010], &padme);
+    let longer_size = encrypted_size(&sandbox, &vec![b'b'; 1020], &padme);
+    assert_eq!(shorter_size, longer_size);
+    assert_eq!(vec![b'b'; 1020], decrypted_content(&sandbox));
+
+    let unpadded_size = encrypted_size(&sandbox, &vec![b'c'; 1005], &EncryptOptions::default());
+    assert!(unpadded_size < longer_size);
+    assert_eq!(vec![b'c'; 1005], decrypted_content(&sandbox));
+}
+
+#[test]
+#[testaun_case]
+fn should_use_profile_padding_unless_overridden() {
+    let sandbox = Sandbox::with_profile("padding_profile");
+    let padding = "padding".to_owned();
+
+    if let Err(reason) = moy_sekret::configure(&sandbox.profile, &padding, &"padme".to_owned()) {
+        panic!("Should have configured profile but: {}", reason);
+    }
+    let profile_default_size =
+        encrypted_size(&sandbox, &vec![b'a'; 1005], &EncryptOptions::default());
+    assert_eq!(vec![b'a'; 1005], decrypted_content(&sandbox));
+
+    let overridden_size = encrypted_size(
+        &sandbox,
+        &vec![b'a'; 1005],
+        &EncryptOptions {
+            padding: Some(Padding::None),
+            ..EncryptOptions::default()
+        },
+    );
+    assert!(overridden_size < profile_default_size);
+
+    assert!(moy_sekret::configure(&sandbox.profile, &padding, &"random".to_owned()).is_err());
+}
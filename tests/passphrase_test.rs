@@ -0,0 +1,130 @@
+extern crate moy_sekret;
+
+use moy_sekret::{DecryptOptions, EncryptOptions, SecretBytes};
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn encrypt_with_passphrase(sandbox: &Sandbox, options: &EncryptOptions) -> String {
+    let file_path = sandbox.write_local_file("note.txt", b"for your eyes only");
+    let passphrase = SecretBytes::new(b"tell no one\n".to_vec());
+    if let Err(reason) =
+        moy_sekret::encrypt_with_passphrase(&file_path, &passphrase, true, &options)
+    {
+        panic!("Should have encrypted file but: {}", reason);
+    }
+    format!("{}.cz", file_path)
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_encrypt_and_decrypt_with_passphrase_only() {
+    let sandbox = Sandbox::new("passphrase_only");
+    let encrypted_file_path = encrypt_with_passphrase(&sandbox, &EncryptOptions::default());
+    assert!(moy_sekret::is_passphrase_file(&encrypted_file_path).unwrap());
+
+    let dest_dir = format!("{}/plain", sandbox.local_dir);
+    let passphrase = SecretBytes::new(b"tell no one".to_vec());
+    if let Err(reason) = moy_sekret::decrypt_with_passphrase(
+        &encrypted_file_path,
+        &passphrase,
+        &dest_dir,
+        false,
+        &DecryptOptions::default(),
+    ) {
+        panic!("Should have decrypted file but: {}", reason);
+    }
+    assert_eq!(
+        b"for your eyes only".to_vec(),
+        fs::read(format!("{}/note.txt", dest_dir)).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_not_decrypt_with_wrong_passphrase() {
+    let sandbox = Sandbox::new("passphrase_wrong");
+    let options = EncryptOptions {
+        armor: true,
+        ..EncryptOptions::default()
+    };
+    let encrypted_file_path = encrypt_with_passphrase(&sandbox, &options);
+
+    let passphrase = SecretBytes::new(b"tell everyone".to_vec());
+    assert!(moy_sekret::decrypt_with_passphrase(
+        &encrypted_file_path,
+        &passphrase,
+        &sandbox.local_dir,
+        true,
+        &DecryptOptions::default()
+    )
+    .is_err());
+}
+
+#[test]
+#[testaun_case]
+fn should_not_decrypt_passphrase_file_with_profile_keys() {
+    let sandbox = Sandbox::with_profile("passphrase_profile");
+    let encrypted_file_path = encrypt_with_passphrase(&sandbox, &EncryptOptions::default());
+
+    assert!(moy_sekret::decrypt(
+        &sandbox.profile,
+        &encrypted_file_path,
+        &sandbox.storage_dir,
+        true
+    )
+    .is_err());
+
+    let file_path = sandbox.write_local_file("other.txt", b"sealed by keys");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, true).unwrap();
+    assert!(!moy_sekret::is_passphrase_file(&sandbox.storage_file("other.txt.cz")).unwrap());
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_not_write_passphrase_file_through_symlink() {
+    let sandbox = Sandbox::new("passphrase_symlink");
+    let file_path = sandbox.write_local_file("note.txt", b"for your eyes only");
+    let passphrase = SecretBytes::new(b"tell no one\n".to_vec());
+    let victim_path = sandbox.write_local_file("victim.txt", b"left alone");
+    let dangling_path = format!("{}/planted.txt", sandbox.local_dir);
+
+    std::os::unix::fs::symlink(&victim_path, format!("{}.cz", file_path)).unwrap();
+    assert!(moy_sekret::encrypt_with_passphrase(
+        &file_path,
+        &passphrase,
+        true,
+        &EncryptOptions::default()
+    )
+    .is_err());
+    assert_eq!(b"left alone".to_vec(), fs::read(&victim_path).unwrap());
+
+    // Dangling ones do not look like an existing file, so only the write itself can catch them
+    fs::remove_file(format!("{}.cz", file_path)).unwrap();
+    std::os::unix::fs::symlink(&dangling_path, format!("{}.cz", file_path)).unwrap();
+    assert!(moy_sekret::encrypt_with_passphrase(
+        &file_path,
+        &passphrase,
+        false,
+        &EncryptOptions::default()
+    )
+    .is_err());
+    assert!(fs::symlink_metadata(&dangling_path).is_err());
+}
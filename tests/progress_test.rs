@@ -0,0 +1,188 @@
+extern crate moy_sekret;
+
+use moy_sekret::progress::{self, Reporter};
+use moy_sekret::{DecryptOptions, EncryptOptions};
+use std::fs;
+use std::sync::{Mutex, MutexGuard, Once};
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Stub reporter
+//
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Task {
+    action: String,
+    total_files: u64,
+    total_bytes: u64,
+    done_files: u64,
+    done_bytes: u64,
+    is_finished: bool,
+}
+
+// Keeps every task it was told about, instead of drawing them
+struct StubReporter;
+
+static TASKS: Mutex<Vec<Task>> = Mutex::new(Vec::new());
+static INSTALL: Once = Once::new();
+
+// Only one reporter may ever be installed, so tests take turns with it
+static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+impl Reporter for StubReporter {
+    fn start(&self, action: &str, total_files: u64, total_bytes: u64) {
+        lock(&TASKS).push(Task {
+            action: action.to_owned(),
+            total_files,
+            total_bytes,
+            ..Task::default()
+        });
+    }
+
+    fn advance(&self, bytes: u64) {
+        lock(&TASKS).last_mut().unwrap().done_bytes += bytes;
+    }
+
+    fn complete_file(&self) {
+        lock(&TASKS).last_mut().unwrap().done_files += 1;
+    }
+
+    fn finish(&self) {
+        lock(&TASKS).last_mut().unwrap().is_finished = true;
+    }
+}
+
+// Helpers
+//
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn take_tasks() -> Vec<Task> {
+    lock(&TASKS).drain(..).collect()
+}
+
+fn file_len(file_path: &String) -> u64 {
+    fs::metadata(file_path).unwrap().len()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {
+    INSTALL.call_once(|| progress::install(Box::new(StubReporter)));
+}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_count_bytes_when_encrypting_and_decrypting() {
+    let _guard = lock(&TEST_LOCK);
+    let sandbox = Sandbox::with_profile("progress_single");
+    let file_path = sandbox.write_local_file("notes.txt", b"meeting notes");
+    take_tasks();
+
+    moy_sekret::encrypt(&sandbox.profile, &file_path, true).unwrap();
+    assert_eq!(
+        vec![Task {
+            action: "Encrypting".to_owned(),
+            total_files: 1,
+            total_bytes: 2 * 13,
+            done_bytes: 2 * 13,
+            is_finished: true,
+            ..Task::default()
+        }],
+        take_tasks()
+    );
+
+    let cipher_path = sandbox.storage_file("notes.txt.cz");
+    let cipher_len = file_len(&cipher_path);
+    moy_sekret::decrypt(&sandbox.profile, &cipher_path, &sandbox.local_dir, true).unwrap();
+    assert_eq!(
+        vec![Task {
+            action: "Decrypting".to_owned(),
+            total_files: 1,
+            total_bytes: 2 * cipher_len,
+            done_bytes: 2 * cipher_len,
+            is_finished: true,
+            ..Task::default()
+        }],
+        take_tasks()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_count_files_and_bytes_in_batches() {
+    let _guard = lock(&TEST_LOCK);
+    let sandbox = Sandbox::with_profile("progress_batch");
+    sandbox.write_local_file("first.txt", b"first");
+    sandbox.write_local_file("second.txt", b"second");
+    let patterns = vec![format!("{}/*.txt", sandbox.local_dir)];
+    take_tasks();
+
+    moy_sekret::batch::encrypt(
+        &sandbox.profile,
+        &patterns,
+        true,
+        &EncryptOptions::default(),
+        Some(2),
+    )
+    .unwrap();
+    assert_eq!(
+        vec![Task {
+            action: "Encrypting".to_owned(),
+            total_files: 2,
+            total_bytes: 2 * 11,
+            done_files: 2,
+            done_bytes: 2 * 11,
+            is_finished: true,
+        }],
+        take_tasks()
+    );
+
+    let cipher_paths = vec![
+        sandbox.storage_file("first.txt.cz"),
+        sandbox.storage_file("second.txt.cz"),
+    ];
+    let cipher_len: u64 = cipher_paths.iter().map(file_len).sum();
+    moy_sekret::batch::decrypt(
+        &sandbox.profile,
+        &cipher_paths,
+        &sandbox.local_dir,
+        true,
+        &DecryptOptions::default(),
+        Some(2),
+    )
+    .unwrap();
+    assert_eq!(
+        vec![Task {
+            action: "Decrypting".to_owned(),
+            total_files: 2,
+            total_bytes: 2 * cipher_len,
+            done_files: 2,
+            done_bytes: 2 * cipher_len,
+            is_finished: true,
+        }],
+        take_tasks()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_not_show_progress_when_quiet_or_json() {
+    assert!(progress::should_show(false, false, true));
+    assert!(!progress::should_show(true, false, true));
+    assert!(!progress::should_show(false, true, true));
+    assert!(!progress::should_show(false, false, false));
+}
@@ -0,0 +1,94 @@
+extern crate moy_sekret;
+
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn read_key_file(storage_dir: &String, profile_name: &String, key: &str) -> Vec<u8> {
+    fs::read(format!("{}/{}.{}", storage_dir, profile_name, key)).unwrap()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_derive_same_keys_from_same_recovery_phrase() {
+    let phrase = moy_sekret::recovery::generate_phrase().unwrap();
+    assert_eq!(24, String::from_utf8_lossy(&phrase).split(' ').count());
+
+    let first = Sandbox::new("recovery_first");
+    let seed = moy_sekret::recovery::seed_from_phrase(&phrase).unwrap();
+    moy_sekret::init_from_seed(&first.profile, &first.storage_dir, &seed, false).unwrap();
+
+    let file_path = first.write_local_file("recovered.txt", b"made on the old machine");
+    moy_sekret::encrypt(&first.profile, &file_path, false).unwrap();
+    fs::remove_file(&file_path).unwrap();
+
+    // Same phrase, typed in capitals over two lines, for the same profile on a new machine
+    let second = Sandbox::new("recovery_second");
+    let retyped = String::from_utf8_lossy(&phrase)
+        .to_uppercase()
+        .replacen(' ', "\n", 1);
+    let seed = moy_sekret::recovery::seed_from_phrase(retyped.as_bytes()).unwrap();
+    moy_sekret::init_from_seed(&first.profile, &second.storage_dir, &seed, true).unwrap();
+
+    for key in &["pk", "sk"] {
+        assert_eq!(
+            read_key_file(&first.storage_dir, &first.profile, key),
+            read_key_file(&second.storage_dir, &first.profile, key)
+        );
+    }
+
+    let encrypted_file_path = first.storage_file("recovered.txt.cz");
+    moy_sekret::decrypt(
+        &first.profile,
+        &encrypted_file_path,
+        &second.local_dir,
+        false,
+    )
+    .unwrap();
+    let plain_file_path = format!("{}/recovered.txt", second.local_dir);
+    assert_eq!(
+        b"made on the old machine".to_vec(),
+        fs::read(plain_file_path).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_derive_keys_from_hex_seed() {
+    let sandbox = Sandbox::new("recovery_hex_seed");
+    let hex_seed = b"000102030405060708090a0b0c0d0e0f\n";
+    let seed = moy_sekret::recovery::seed_from_hex(hex_seed).unwrap();
+
+    match moy_sekret::init_from_seed(&sandbox.profile, &sandbox.storage_dir, &seed, false) {
+        Ok(report) => assert_eq!(1, report.fingerprints.len()),
+        Err(reason) => panic!("Should have initialized from seed but: {}", reason),
+    }
+
+    assert!(moy_sekret::recovery::seed_from_hex(b"0001").is_err());
+}
+
+#[test]
+#[testaun_case]
+fn should_not_accept_recovery_phrase_with_bad_checksum() {
+    let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+    match moy_sekret::recovery::seed_from_phrase(phrase.as_bytes()) {
+        Ok(_) => panic!("Should not have accepted recovery phrase"),
+        Err(reason) => assert!(reason.to_string().contains("Recovery phrase is not valid")),
+    }
+}
@@ -0,0 +1,99 @@
+extern crate moy_sekret;
+
+use moy_sekret::secret_store;
+use std::fs;
+use std::path::Path;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_set_get_list_and_remove_secrets() {
+    let sandbox = Sandbox::with_profile("secret_store_lifecycle");
+    let db_password = "db/prod/password".to_string();
+    let api_key = "api/key".to_string();
+
+    secret_store::set(&sandbox.profile, &db_password, b"s3cr3t", false).unwrap();
+    secret_store::set(&sandbox.profile, &api_key, b"k3y", false).unwrap();
+
+    let value = secret_store::get(&sandbox.profile, &db_password).unwrap();
+    assert_eq!(b"s3cr3t", &value[..]);
+
+    let all_secrets = secret_store::list(&sandbox.profile, &String::new()).unwrap();
+    assert_eq!(vec![api_key.clone(), db_password.clone()], all_secrets);
+    let db_secrets = secret_store::list(&sandbox.profile, &"db/".to_string()).unwrap();
+    assert_eq!(vec![db_password.clone()], db_secrets);
+
+    secret_store::remove(&sandbox.profile, &db_password).unwrap();
+    assert!(secret_store::get(&sandbox.profile, &db_password).is_err());
+    assert!(!Path::new(&sandbox.storage_file("secrets/db")).exists());
+    assert_eq!(
+        vec![api_key],
+        secret_store::list(&sandbox.profile, &String::new()).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_not_set_existing_secret_unless_overriding() {
+    let sandbox = Sandbox::with_profile("secret_store_override");
+    let secret_path = "api/key".to_string();
+    secret_store::set(&sandbox.profile, &secret_path, b"first", false).unwrap();
+
+    match secret_store::set(&sandbox.profile, &secret_path, b"second", false) {
+        Ok(_) => panic!("Should not have overridden existing secret"),
+        Err(reason) => assert_eq!(
+            "Setting secret failed because secret already exists",
+            reason.to_string()
+        ),
+    }
+
+    secret_store::set(&sandbox.profile, &secret_path, b"second", true).unwrap();
+    let value = secret_store::get(&sandbox.profile, &secret_path).unwrap();
+    assert_eq!(b"second", &value[..]);
+}
+
+#[test]
+#[testaun_case]
+fn should_not_set_secrets_outside_the_store() {
+    let sandbox = Sandbox::with_profile("secret_store_hostile");
+
+    for secret_path in &["../escape", "/etc/passwd", "a/../../escape", ""] {
+        let secret_path = secret_path.to_string();
+        assert!(secret_store::set(&sandbox.profile, &secret_path, b"x", true).is_err());
+    }
+}
+
+#[test]
+#[testaun_case]
+fn should_not_get_secret_swapped_for_another_one() {
+    let sandbox = Sandbox::with_profile("secret_store_swap");
+    let prod_password = "db/prod/password".to_string();
+    let staging_password = "db/staging/password".to_string();
+    secret_store::set(&sandbox.profile, &prod_password, b"prod", false).unwrap();
+    secret_store::set(&sandbox.profile, &staging_password, b"staging", false).unwrap();
+
+    fs::copy(
+        sandbox.storage_file("secrets/db/staging/password.cz"),
+        sandbox.storage_file("secrets/db/prod/password.cz"),
+    )
+    .unwrap();
+
+    match secret_store::get(&sandbox.profile, &prod_password) {
+        Ok(_) => panic!("Should not have got swapped secret"),
+        Err(reason) => assert!(format!("{}", reason).contains("cannot stand in for")),
+    }
+}
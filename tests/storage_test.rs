@@ -0,0 +1,133 @@
+extern crate moy_sekret;
+
+use moy_sekret::storage::{LocalStorage, MemoryStorage, Storage};
+use std::fs;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn put_get_list_and_delete(storage: &dyn Storage) {
+    storage.put("first.cz", b"first content").unwrap();
+    storage.put("second.cz", b"second content").unwrap();
+    storage.put("first.cz", b"first content again").unwrap();
+
+    assert!(storage.exists("first.cz"));
+    assert_eq!(
+        b"first content again".to_vec(),
+        storage.get("first.cz").unwrap()
+    );
+    assert_eq!(vec!["first.cz", "second.cz"], storage.list().unwrap());
+
+    storage.delete("first.cz").unwrap();
+    assert!(!storage.exists("first.cz"));
+    assert!(storage.get("first.cz").is_err());
+    assert!(storage.delete("first.cz").is_err());
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_keep_items_in_memory() {
+    let storage = MemoryStorage::new();
+    put_get_list_and_delete(&storage);
+    assert_eq!("memory:second.cz", storage.locate("second.cz"));
+}
+
+#[test]
+#[testaun_case]
+fn should_keep_items_as_files_in_storage_directory() {
+    let sandbox = Sandbox::new("storage_local");
+    fs::create_dir_all(&sandbox.storage_dir).unwrap();
+    let storage = LocalStorage::new(&sandbox.storage_dir);
+    put_get_list_and_delete(&storage);
+
+    assert_eq!(
+        format!("{}/second.cz", sandbox.storage_dir),
+        storage.locate("second.cz")
+    );
+    assert_eq!(
+        b"second content".to_vec(),
+        fs::read(sandbox.storage_file("second.cz")).unwrap()
+    );
+}
+
+#[test]
+#[testaun_case]
+fn should_not_reach_outside_storage() {
+    let sandbox = Sandbox::new("storage_outside");
+    fs::create_dir_all(&sandbox.storage_dir).unwrap();
+    fs::write(format!("{}/escaped.cz", sandbox.local_dir), b"content").unwrap();
+    let local_storage = LocalStorage::new(&sandbox.storage_dir);
+    let memory_storage = MemoryStorage::new();
+
+    for storage in &[&local_storage as &dyn Storage, &memory_storage] {
+        for name in &["../local/escaped.cz", "/etc/escaped.cz"] {
+            assert!(storage.put(name, b"content").is_err());
+            assert!(storage.put_new(name, b"content").is_err());
+            assert!(storage.append(name, b"content").is_err());
+            assert!(storage.get(name).is_err());
+            assert!(storage.delete(name).is_err());
+            assert!(storage.modified(name).is_err());
+            assert!(storage.lock(name).is_err());
+            assert!(!storage.exists(name));
+        }
+    }
+    assert!(fs::metadata(format!("{}/escaped.cz", sandbox.local_dir)).is_ok());
+}
+
+#[test]
+#[testaun_case]
+fn should_keep_nested_items_and_drop_their_empty_directories() {
+    let sandbox = Sandbox::new("storage_nested");
+    fs::create_dir_all(&sandbox.storage_dir).unwrap();
+    let storage = LocalStorage::new(&sandbox.storage_dir);
+
+    storage.put("secrets/db/password.cz", b"nested").unwrap();
+    assert!(storage.put_new("secrets/db/password.cz", b"again").is_err());
+    assert_eq!(vec!["secrets/db/password.cz"], storage.list().unwrap());
+
+    storage.delete("secrets/db/password.cz").unwrap();
+    assert!(fs::metadata(sandbox.storage_file("secrets")).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+#[testaun_case]
+fn should_not_follow_symlinks_in_local_storage() {
+    use std::os::unix::fs::symlink;
+
+    let sandbox = Sandbox::new("storage_symlink");
+    fs::create_dir_all(&sandbox.storage_dir).unwrap();
+    let target_file_path = sandbox.write_local_file("target.txt", b"left alone");
+    let target_dir = format!("{}/dir", sandbox.local_dir);
+    fs::create_dir_all(&target_dir).unwrap();
+    let abs_target_file_path = fs::canonicalize(&target_file_path).unwrap();
+    symlink(&abs_target_file_path, sandbox.storage_file("linked.cz")).unwrap();
+    symlink(
+        fs::canonicalize(&target_dir).unwrap(),
+        sandbox.storage_file("linked"),
+    )
+    .unwrap();
+    let storage = LocalStorage::new(&sandbox.storage_dir);
+
+    assert!(storage.put("linked.cz", b"replaced").is_err());
+    assert!(storage.put("linked/inner.cz", b"escaped").is_err());
+    assert!(storage.get("linked.cz").is_err());
+    assert!(!storage.exists("linked.cz"));
+    assert_eq!(b"left alone".to_vec(), fs::read(&target_file_path).unwrap());
+    assert!(fs::metadata(format!("{}/inner.cz", target_dir)).is_err());
+}
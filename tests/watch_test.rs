@@ -0,0 +1,133 @@
+extern crate moy_sekret;
+
+use moy_sekret::batch::Outcome;
+use moy_sekret::EncryptOptions;
+use std::fs;
+use std::path::Path;
+use testaun::testaun_case;
+
+#[macro_use]
+pub mod common;
+use common::sandbox::Sandbox;
+
+// Helpers
+//
+
+fn file_name(outcome: &Outcome) -> String {
+    let path = Path::new(&outcome.file_path);
+    path.file_name().unwrap().to_str().unwrap().to_owned()
+}
+
+// Test Setup
+//
+
+fn testaun_before() {}
+
+fn testaun_after() {}
+
+// Tests
+//
+
+#[test]
+#[testaun_case]
+fn should_encrypt_files_dropped_before_and_while_watching() {
+    let sandbox = Sandbox::with_profile("watch_drop");
+    sandbox.write_local_file("early.txt", b"dropped before watching");
+
+    let mut file_names = vec![];
+    let result = moy_sekret::watch::run(
+        &sandbox.profile,
+        &sandbox.local_dir,
+        false,
+        &EncryptOptions::default(),
+        |outcome| {
+            assert!(outcome.result.is_ok());
+            file_names.push(file_name(&outcome));
+            // Dropped only once watching, so it can only be noticed as a change
+            if file_names.len() == 1 {
+                sandbox.write_local_file("late.txt", b"dropped while watching");
+            }
+            file_names.len() < 2
+        },
+    );
+
+    if let Err(reason) = result {
+        panic!("Should have watched directory but: {}", reason);
+    }
+    assert_eq!(vec!["early.txt", "late.txt"], file_names);
+    assert!(fs::metadata(sandbox.storage_file("early.txt.cz")).is_ok());
+    assert!(fs::metadata(sandbox.storage_file("late.txt.cz")).is_ok());
+}
+
+#[test]
+#[testaun_case]
+fn should_remove_sources_once_encrypted_when_asked() {
+    let sandbox = Sandbox::with_profile("watch_remove");
+    let file_path = sandbox.write_local_file("inbox.txt", b"nobody should keep this around");
+
+    let result = moy_sekret::watch::run(
+        &sandbox.profile,
+        &sandbox.local_dir,
+        true,
+        &EncryptOptions::default(),
+        |outcome| {
+            assert!(outcome.result.is_ok());
+            false
+        },
+    );
+
+    if let Err(reason) = result {
+        panic!("Should have watched directory but: {}", reason);
+    }
+    assert!(fs::metadata(&file_path).is_err());
+    assert!(fs::metadata(sandbox.storage_file("inbox.txt.cz")).is_ok());
+}
+
+#[test]
+#[testaun_case]
+fn should_not_encrypt_again_files_unchanged_since_encrypted() {
+    let sandbox = Sandbox::with_profile("watch_unchanged");
+    let file_path = sandbox.write_local_file("old.txt", b"encrypted long ago");
+    moy_sekret::encrypt(&sandbox.profile, &file_path, false).unwrap();
+    sandbox.write_local_file(".old.txt.swp", b"editor at work");
+    sandbox.write_local_file("new.txt", b"never encrypted");
+
+    let mut file_names = vec![];
+    let result = moy_sekret::watch::run(
+        &sandbox.profile,
+        &sandbox.local_dir,
+        false,
+        &EncryptOptions::default(),
+        |outcome| {
+            file_names.push(file_name(&outcome));
+            false
+        },
+    );
+
+    if let Err(reason) = result {
+        panic!("Should have watched directory but: {}", reason);
+    }
+    assert_eq!(vec!["new.txt"], file_names);
+}
+
+#[test]
+#[testaun_case]
+fn should_not_watch_profile_storage_directory_nor_its_parents() {
+    let sandbox = Sandbox::with_profile("watch_storage");
+    let parent_dir = format!("{}/..", sandbox.storage_dir);
+
+    for dir in &[&sandbox.storage_dir, &parent_dir] {
+        let result = moy_sekret::watch::run(
+            &sandbox.profile,
+            &dir,
+            true,
+            &EncryptOptions::default(),
+            |_| false,
+        );
+        match result {
+            Ok(_) => panic!("Should not have watched {}", dir),
+            Err(reason) => assert!(format!("{}", reason).contains("profile storage directory")),
+        }
+    }
+    assert!(fs::metadata(sandbox.storage_file(&format!("{}.sk", sandbox.profile))).is_ok());
+}